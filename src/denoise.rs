@@ -0,0 +1,232 @@
+use crate::film::Aov;
+use crate::render::RenderOutput;
+use crate::vec3::{ColorSpace, Vec3};
+use rayon::prelude::*;
+
+// B3 spline, the filter taps of each à-trous pass
+const KERNEL: [f64; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+// passes, each with twice the spacing between taps, reaching 2 * (2^5 - 1) = 62 pixels out
+const PASSES: u32 = 5;
+// how much neighbours may differ in each feature before they stop counting
+const NORMAL_SIGMA: f64 = 0.3;
+const ALBEDO_SIGMA: f64 = 0.1;
+/// Difference in depth per pixel of spacing, relative to the depth.
+const DEPTH_SIGMA: f64 = 0.02;
+/// Difference in compressed colour at a strength of one, narrowing with each pass.
+const COLOR_SIGMA: f64 = 0.5;
+
+/// The feature images that guide the denoiser, laid out like `RenderOutput::pixels`, as the
+/// albedo, normal and depth AOVs give them.
+pub struct Guides<'a> {
+    pub albedo: &'a [Vec<Vec3<f64>>],
+    pub normal: &'a [Vec<Vec3<f64>>],
+    /// Depths in `x`, infinite where nothing was hit.
+    pub depth: &'a [Vec<Vec3<f64>>],
+}
+
+impl<'a> Guides<'a> {
+    /// The guides among the AOVs of a render, if it has all of them.
+    pub fn from_output(output: &'a RenderOutput) -> Option<Self> {
+        let layer = |aov: Aov| {
+            output
+                .aovs
+                .iter()
+                .find(|image| image.layer.aov == aov)
+                .map(|image| image.pixels.as_slice())
+        };
+        Some(Self {
+            albedo: layer(Aov::Albedo)?,
+            normal: layer(Aov::Normal)?,
+            depth: layer(Aov::Depth)?,
+        })
+    }
+}
+
+/// Edge-avoiding à-trous wavelet filter (Dammertz et al. 2010): repeated blurs with taps
+/// spaced further apart each pass, each neighbour weighted by how alike its colour, albedo,
+/// normal and depth are, so that noise is smoothed within surfaces but not across their
+/// edges. Lighting is filtered with the albedo divided out, which keeps textures sharp.
+pub struct Denoiser {
+    strength: f64,
+    working: ColorSpace,
+}
+
+impl Denoiser {
+    /// `strength` scales how different colours may be and still be averaged: zero leaves the
+    /// image as it is, one suits a few dozen samples per pixel.
+    pub fn new(strength: f64) -> Self {
+        Self {
+            strength,
+            working: ColorSpace::Srgb,
+        }
+    }
+
+    /// Space of the colours, whose luminance compresses highlights before they're compared.
+    /// sRGB by default.
+    pub fn with_working_space(mut self, working: ColorSpace) -> Self {
+        self.working = working;
+        self
+    }
+
+    pub fn denoise(&self, pixels: &[Vec<Vec3<f64>>], guides: &Guides) -> Vec<Vec<Vec3<f64>>> {
+        if self.strength <= 0.0 {
+            return pixels.to_vec();
+        }
+        let width = pixels.len();
+        let height = pixels.first().map_or(0, |column| column.len());
+        let flat = |image: &[Vec<Vec3<f64>>]| image.iter().flatten().copied().collect::<Vec<_>>();
+        let albedo = flat(guides.albedo);
+        let normal = flat(guides.normal);
+        let depth: Vec<f64> = guides.depth.iter().flatten().map(|depth| depth.x).collect();
+
+        // where nothing was hit the albedo is zero, and the colour is filtered as is
+        let divisor: Vec<Vec3<f64>> = albedo
+            .iter()
+            .map(|albedo| {
+                if albedo.max_component() > 0.0 {
+                    Vec3::new(albedo.x.max(0.01), albedo.y.max(0.01), albedo.z.max(0.01))
+                } else {
+                    Vec3::new(1.0, 1.0, 1.0)
+                }
+            })
+            .collect();
+        let mut lighting: Vec<Vec3<f64>> = flat(pixels)
+            .into_iter()
+            .zip(&divisor)
+            .map(|(color, &divisor)| color / divisor)
+            .collect();
+
+        for pass in 0..PASSES {
+            let step = 1 << pass;
+            let color_sigma2 = (self.strength * COLOR_SIGMA).powi(2) / (pass + 1) as f64;
+            let compressed: Vec<Vec3<f64>> = lighting
+                .iter()
+                .map(|&color| color / (1.0 + self.working.luminance(color).max(0.0)))
+                .collect();
+            lighting = (0..width * height)
+                .into_par_iter()
+                .map(|p| {
+                    let (x, y) = ((p / height) as i64, (p % height) as i64);
+                    let mut sum = Vec3::zero();
+                    let mut weight_sum = 0.0;
+                    for (i, kx) in KERNEL.iter().enumerate() {
+                        let qx = x + (i as i64 - 2) * step;
+                        if qx < 0 || qx >= width as i64 {
+                            continue;
+                        }
+                        for (j, ky) in KERNEL.iter().enumerate() {
+                            let qy = y + (j as i64 - 2) * step;
+                            if qy < 0 || qy >= height as i64 {
+                                continue;
+                            }
+                            let q = qx as usize * height + qy as usize;
+                            let difference = distance2(compressed[p], compressed[q]) / color_sigma2
+                                + distance2(albedo[p], albedo[q]) / (ALBEDO_SIGMA * ALBEDO_SIGMA)
+                                + distance2(normal[p], normal[q]) / (NORMAL_SIGMA * NORMAL_SIGMA);
+                            let weight = kx
+                                * ky
+                                * depth_weight(depth[p], depth[q], step as f64)
+                                * (-difference).exp();
+                            sum += lighting[q] * weight;
+                            weight_sum += weight;
+                        }
+                    }
+                    // the centre tap always counts
+                    sum / weight_sum
+                })
+                .collect();
+        }
+
+        lighting
+            .chunks(height.max(1))
+            .zip(divisor.chunks(height.max(1)))
+            .map(|(lighting, divisor)| {
+                lighting
+                    .iter()
+                    .zip(divisor)
+                    .map(|(&lighting, &divisor)| lighting * divisor)
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+fn distance2(a: Vec3<f64>, b: Vec3<f64>) -> f64 {
+    let d = a - b;
+    d.dot(&d)
+}
+
+/// Weight of a neighbour at depth `b` of a pixel at depth `a`, `step` pixels apart.
+fn depth_weight(a: f64, b: f64, step: f64) -> f64 {
+    if a.is_infinite() || b.is_infinite() {
+        return if a == b { 1.0 } else { 0.0 };
+    }
+    (-(a - b).abs() / (DEPTH_SIGMA * step * a.max(1e-6))).exp()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn image(size: usize, mut value: impl FnMut(usize, usize) -> Vec3<f64>) -> Vec<Vec<Vec3<f64>>> {
+        (0..size)
+            .map(|x| (0..size).map(|y| value(x, y)).collect())
+            .collect()
+    }
+
+    fn mean_squared_error(a: &[Vec<Vec3<f64>>], b: &[Vec<Vec3<f64>>]) -> f64 {
+        let errors: Vec<f64> = a
+            .iter()
+            .flatten()
+            .zip(b.iter().flatten())
+            .map(|(a, b)| distance2(*a, *b))
+            .collect();
+        errors.iter().sum::<f64>() / errors.len() as f64
+    }
+
+    #[test]
+    fn test_smooths_noise_but_keeps_edges() {
+        // a grey wall meeting a red floor at x = 16, rendered noisily
+        let size = 32;
+        let mut rng = StdRng::seed_from_u64(0);
+        let albedo = image(size, |x, _| {
+            if x < 16 {
+                Vec3::new(0.5, 0.5, 0.5)
+            } else {
+                Vec3::new(0.8, 0.1, 0.1)
+            }
+        });
+        let normal = image(size, |x, _| {
+            if x < 16 {
+                Vec3::new(0.0, 0.0, 1.0)
+            } else {
+                Vec3::new(0.0, 1.0, 0.0)
+            }
+        });
+        let depth = image(size, |_, _| Vec3::new(5.0, 0.0, 0.0));
+        let clean = albedo.clone();
+        let noisy = image(size, |x, y| clean[x][y] * (2.0 * rng.gen::<f64>()));
+        let guides = Guides {
+            albedo: &albedo,
+            normal: &normal,
+            depth: &depth,
+        };
+
+        let denoised = Denoiser::new(1.0).denoise(&noisy, &guides);
+        assert!(mean_squared_error(&denoised, &clean) < 0.05 * mean_squared_error(&noisy, &clean));
+        // nothing of the red floor bleeds onto the wall
+        assert!((denoised[15][10].x - denoised[15][10].y).abs() < 0.05);
+        assert_eq!(Denoiser::new(0.0).denoise(&noisy, &guides), noisy);
+    }
+
+    #[test]
+    fn test_depth_weight() {
+        assert_eq!(depth_weight(f64::INFINITY, f64::INFINITY, 1.0), 1.0);
+        assert_eq!(depth_weight(2.0, f64::INFINITY, 1.0), 0.0);
+        assert!(depth_weight(2.0, 2.01, 1.0) > depth_weight(2.0, 2.5, 1.0));
+        // further apart taps allow for more of a slope
+        assert!(depth_weight(2.0, 2.1, 4.0) > depth_weight(2.0, 2.1, 1.0));
+    }
+}
@@ -0,0 +1,298 @@
+use crate::ray::Ray;
+use crate::scene::Scene;
+use crate::vec3::Vec3;
+
+/// An arbitrary output variable: an image rendered alongside the beauty pass, for compositors
+/// to relight and denoise with. Surface properties are of the first surface camera rays hit.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Aov {
+    /// Surface colour, see `Material::albedo`.
+    Albedo,
+    /// Shading normal in world space.
+    Normal,
+    /// Distance from the camera, infinite where nothing was hit.
+    Depth,
+    /// Position in world space.
+    Position,
+    /// One more than the index of the top level object, zero where nothing was hit.
+    ObjectId,
+    /// One more than the scene's index of the material, see `Scene::material_id`, zero where
+    /// nothing was hit.
+    MaterialId,
+    /// Emission and background seen directly by the camera.
+    Emission,
+    DiffuseDirect,
+    DiffuseIndirect,
+    SpecularDirect,
+    SpecularIndirect,
+    /// The light of each of the scene's light groups, a layer per group.
+    LightGroups,
+}
+
+impl Aov {
+    pub const ALL: [Aov; 12] = [
+        Aov::Albedo,
+        Aov::Normal,
+        Aov::Depth,
+        Aov::Position,
+        Aov::ObjectId,
+        Aov::MaterialId,
+        Aov::Emission,
+        Aov::DiffuseDirect,
+        Aov::DiffuseIndirect,
+        Aov::SpecularDirect,
+        Aov::SpecularIndirect,
+        Aov::LightGroups,
+    ];
+
+    /// Name of the layer, or the prefix of the layer names of light groups.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Aov::Albedo => "albedo",
+            Aov::Normal => "normal",
+            Aov::Depth => "depth",
+            Aov::Position => "position",
+            Aov::ObjectId => "object_id",
+            Aov::MaterialId => "material_id",
+            Aov::Emission => "emission",
+            Aov::DiffuseDirect => "diffuse_direct",
+            Aov::DiffuseIndirect => "diffuse_indirect",
+            Aov::SpecularDirect => "specular_direct",
+            Aov::SpecularIndirect => "specular_indirect",
+            Aov::LightGroups => "light",
+        }
+    }
+
+    /// Names of the channels of the layer, of which single channel layers only use the first
+    /// component of their values.
+    pub fn channels(&self) -> &'static [&'static str] {
+        match self {
+            Aov::Normal | Aov::Position => &["X", "Y", "Z"],
+            Aov::Depth => &["Z"],
+            Aov::ObjectId | Aov::MaterialId => &["id"],
+            _ => &["R", "G", "B"],
+        }
+    }
+
+    /// True for the parts of the radiance that the integrator splits up, see
+    /// `Integrator::li_aovs`.
+    pub fn is_lighting(&self) -> bool {
+        matches!(
+            self,
+            Aov::Emission
+                | Aov::DiffuseDirect
+                | Aov::DiffuseIndirect
+                | Aov::SpecularDirect
+                | Aov::SpecularIndirect
+                | Aov::LightGroups
+        )
+    }
+
+    /// True for layers averaged over a pixel's samples by the filter like the beauty pass.
+    /// Depths, positions and IDs would mix into values no surface has, so they are taken from
+    /// the pixel's first sample.
+    pub(crate) fn is_filtered(&self) -> bool {
+        !matches!(
+            self,
+            Aov::Depth | Aov::Position | Aov::ObjectId | Aov::MaterialId
+        )
+    }
+}
+
+/// One layer of the AOVs a film keeps: light groups get one each.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AovLayer {
+    pub aov: Aov,
+    pub name: String,
+}
+
+impl AovLayer {
+    /// The layers of `aovs`, with a layer for each of `light_groups` in place of
+    /// `Aov::LightGroups`.
+    pub fn expand(aovs: &[Aov], light_groups: &[String]) -> Vec<Self> {
+        aovs.iter()
+            .flat_map(|&aov| match aov {
+                Aov::LightGroups => light_groups
+                    .iter()
+                    .map(|group| AovLayer {
+                        aov,
+                        name: format!("{}_{}", aov.name(), group),
+                    })
+                    .collect(),
+                _ => vec![AovLayer {
+                    aov,
+                    name: aov.name().to_string(),
+                }],
+            })
+            .collect()
+    }
+}
+
+/// Radiance of a camera path split by how it reached the camera: seen directly, or reflected
+/// off the first surface hit, diffusely or specularly, either straight from a light (direct) or
+/// after further bounces (indirect). The parts add up to the radiance. The light of each light
+/// group is kept too, which together with the background adds up to it as well.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LightingSample {
+    pub emission: Vec3<f64>,
+    pub diffuse_direct: Vec3<f64>,
+    pub diffuse_indirect: Vec3<f64>,
+    pub specular_direct: Vec3<f64>,
+    pub specular_indirect: Vec3<f64>,
+    /// Indexed like `LightGroups::names`.
+    pub light_groups: Vec<Vec3<f64>>,
+}
+
+impl LightingSample {
+    pub fn new(light_groups: usize) -> Self {
+        Self {
+            emission: Vec3::zero(),
+            diffuse_direct: Vec3::zero(),
+            diffuse_indirect: Vec3::zero(),
+            specular_direct: Vec3::zero(),
+            specular_indirect: Vec3::zero(),
+            light_groups: vec![Vec3::zero(); light_groups],
+        }
+    }
+
+    /// Adds radiance that bounced off `bounces` surfaces on its way from a light to the camera:
+    /// none when seen directly, one when lighting the first surface hit directly. Reflected
+    /// light is split by the `diffuse_fraction` of that surface's material. `group` is the light
+    /// group of the light it came from.
+    pub fn add(
+        &mut self,
+        radiance: Vec3<f64>,
+        bounces: u32,
+        diffuse_fraction: f64,
+        group: Option<usize>,
+    ) {
+        let diffuse = radiance * diffuse_fraction;
+        let specular = radiance * (1.0 - diffuse_fraction);
+        match bounces {
+            0 => self.emission += radiance,
+            1 => {
+                self.diffuse_direct += diffuse;
+                self.specular_direct += specular;
+            }
+            _ => {
+                self.diffuse_indirect += diffuse;
+                self.specular_indirect += specular;
+            }
+        }
+        if let Some(group) = group {
+            self.light_groups[group] += radiance;
+        }
+    }
+}
+
+/// What a camera sample found for every AOV.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AovSample {
+    pub albedo: Vec3<f64>,
+    pub normal: Vec3<f64>,
+    pub depth: f64,
+    pub position: Vec3<f64>,
+    pub object_id: f64,
+    pub material_id: f64,
+    pub lighting: LightingSample,
+}
+
+impl AovSample {
+    /// A sample of a camera ray that hit nothing.
+    pub fn miss(light_groups: usize) -> Self {
+        Self {
+            albedo: Vec3::zero(),
+            normal: Vec3::zero(),
+            depth: f64::INFINITY,
+            position: Vec3::zero(),
+            object_id: 0.0,
+            material_id: 0.0,
+            lighting: LightingSample::new(light_groups),
+        }
+    }
+
+    /// The surface AOVs of the first surface a camera `ray` hits, leaving the lighting to the
+    /// integrator.
+    pub fn first_hit(ray: &Ray, scene: &Scene, light_groups: usize) -> Self {
+        let mut sample = Self::miss(light_groups);
+        let Some((object, hit)) = scene.hit_object(ray, 0.001, f64::INFINITY) else {
+            return sample;
+        };
+        sample.albedo = hit.material.albedo(&hit);
+        sample.normal = hit.normal;
+        sample.depth = hit.t * ray.dir.length();
+        sample.position = hit.point;
+        sample.object_id = (object + 1) as f64;
+        sample.material_id = scene
+            .material_id(hit.material)
+            .map_or(0.0, |id| (id + 1) as f64);
+        sample
+    }
+
+    /// Value of the sample for each of `layers`, with light group layers in order.
+    pub(crate) fn values(&self, layers: &[AovLayer]) -> Vec<Vec3<f64>> {
+        let single = |value: f64| Vec3::new(value, 0.0, 0.0);
+        let mut groups = self.lighting.light_groups.iter();
+        layers
+            .iter()
+            .map(|layer| match layer.aov {
+                Aov::Albedo => self.albedo,
+                Aov::Normal => self.normal,
+                Aov::Depth => single(self.depth),
+                Aov::Position => self.position,
+                Aov::ObjectId => single(self.object_id),
+                Aov::MaterialId => single(self.material_id),
+                Aov::Emission => self.lighting.emission,
+                Aov::DiffuseDirect => self.lighting.diffuse_direct,
+                Aov::DiffuseIndirect => self.lighting.diffuse_indirect,
+                Aov::SpecularDirect => self.lighting.specular_direct,
+                Aov::SpecularIndirect => self.lighting.specular_indirect,
+                Aov::LightGroups => groups.next().copied().unwrap_or(Vec3::zero()),
+            })
+            .collect()
+    }
+}
+
+/// An AOV layer of a rendered image, laid out like `RenderOutput::pixels`. Single channel
+/// layers keep their values in `x`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AovImage {
+    pub layer: AovLayer,
+    pub pixels: Vec<Vec<Vec3<f64>>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_light_groups_expand_to_layers() {
+        let groups = ["key".to_string(), "fill".to_string()];
+        let layers = AovLayer::expand(&[Aov::Albedo, Aov::LightGroups, Aov::Depth], &groups);
+        let names: Vec<&str> = layers.iter().map(|layer| layer.name.as_str()).collect();
+        assert_eq!(names, ["albedo", "light_key", "light_fill", "depth"]);
+
+        let mut sample = AovSample::miss(2);
+        sample.lighting.light_groups[1] = Vec3::new(1.0, 2.0, 3.0);
+        let values = sample.values(&layers);
+        assert_eq!(values[2], Vec3::new(1.0, 2.0, 3.0));
+        assert_eq!(values[3].x, f64::INFINITY);
+    }
+
+    #[test]
+    fn test_lighting_parts_add_up() {
+        let mut lighting = LightingSample::new(1);
+        lighting.add(Vec3::new(0.5, 0.5, 0.5), 0, 0.25, None);
+        lighting.add(Vec3::new(1.0, 1.0, 1.0), 1, 0.25, Some(0));
+        lighting.add(Vec3::new(2.0, 2.0, 2.0), 3, 0.25, Some(0));
+        assert_eq!(lighting.diffuse_direct, Vec3::new(0.25, 0.25, 0.25));
+        assert_eq!(lighting.specular_indirect, Vec3::new(1.5, 1.5, 1.5));
+        let total = lighting.emission
+            + lighting.diffuse_direct
+            + lighting.diffuse_indirect
+            + lighting.specular_direct
+            + lighting.specular_indirect;
+        assert_eq!(total, Vec3::new(3.5, 3.5, 3.5));
+        assert_eq!(lighting.light_groups[0], Vec3::new(3.0, 3.0, 3.0));
+    }
+}
@@ -0,0 +1,254 @@
+use std::f64::consts::PI;
+
+/// Reconstruction filter weighting how much a sample counts towards the pixels around it, by
+/// its offset from their centres in pixels. Every filter is a product of the same function of
+/// the horizontal and vertical offsets, and zero beyond `radius` in either.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Filter {
+    /// Each sample counts equally, so with a radius of half a pixel it only counts towards the
+    /// pixel it's in.
+    Box { radius: f64 },
+    /// Falls off linearly to zero at the radius.
+    Tent { radius: f64 },
+    /// A Gaussian with standard deviation `sigma`, less its value at the radius so it reaches
+    /// zero there.
+    Gaussian { radius: f64, sigma: f64 },
+    /// Mitchell and Netravali's cubic, which sharpens with negative lobes. They recommend
+    /// `b` = `c` = 1/3.
+    Mitchell { radius: f64, b: f64, c: f64 },
+    /// Sinc windowed by a wider sinc stretched by `tau`, which is sharpest but rings most.
+    Lanczos { radius: f64, tau: f64 },
+}
+
+impl Filter {
+    pub fn radius(&self) -> f64 {
+        match *self {
+            Filter::Box { radius }
+            | Filter::Tent { radius }
+            | Filter::Gaussian { radius, .. }
+            | Filter::Mitchell { radius, .. }
+            | Filter::Lanczos { radius, .. } => radius,
+        }
+    }
+
+    /// Weight of a sample `offset` pixels from a pixel's centre.
+    pub fn evaluate(&self, offset: (f64, f64)) -> f64 {
+        self.evaluate_1d(offset.0) * self.evaluate_1d(offset.1)
+    }
+
+    fn evaluate_1d(&self, x: f64) -> f64 {
+        let radius = self.radius();
+        if x.abs() > radius {
+            return 0.0;
+        }
+        match *self {
+            Filter::Box { .. } => 1.0,
+            Filter::Tent { .. } => radius - x.abs(),
+            Filter::Gaussian { sigma, .. } => {
+                (gaussian(x, sigma) - gaussian(radius, sigma)).max(0.0)
+            }
+            Filter::Mitchell { b, c, .. } => mitchell(2.0 * x / radius, b, c),
+            Filter::Lanczos { tau, .. } => sinc(x) * sinc(x / tau),
+        }
+    }
+
+    /// Integral of the filter over the plane.
+    pub fn integral(&self) -> f64 {
+        let integral_1d = match *self {
+            Filter::Box { radius } => 2.0 * radius,
+            Filter::Tent { radius } => radius * radius,
+            _ => {
+                let table = Table::new(self);
+                table.values.iter().sum::<f64>() * table.bin_width
+            }
+        };
+        integral_1d * integral_1d
+    }
+}
+
+fn gaussian(x: f64, sigma: f64) -> f64 {
+    (-x * x / (2.0 * sigma * sigma)).exp() / (2.0 * PI * sigma * sigma).sqrt()
+}
+
+fn mitchell(x: f64, b: f64, c: f64) -> f64 {
+    let x = x.abs();
+    let value = if x <= 1.0 {
+        (12.0 - 9.0 * b - 6.0 * c) * x * x * x
+            + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+            + (6.0 - 2.0 * b)
+    } else if x <= 2.0 {
+        (-b - 6.0 * c) * x * x * x
+            + (6.0 * b + 30.0 * c) * x * x
+            + (-12.0 * b - 48.0 * c) * x
+            + (8.0 * b + 24.0 * c)
+    } else {
+        0.0
+    };
+    value / 6.0
+}
+
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-5 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+/// One dimensional filter tabulated at the centres of equal bins over its radius.
+struct Table {
+    values: Vec<f64>,
+    bin_width: f64,
+}
+
+impl Table {
+    fn new(filter: &Filter) -> Self {
+        let radius = filter.radius();
+        let bins = ((64.0 * radius).ceil() as usize).max(16);
+        let bin_width = 2.0 * radius / bins as f64;
+        Self {
+            values: (0..bins)
+                .map(|bin| filter.evaluate_1d(-radius + (bin as f64 + 0.5) * bin_width))
+                .collect(),
+            bin_width,
+        }
+    }
+}
+
+/// Offset of a sample from its pixel's centre, and the weight it adds to the pixel with.
+#[derive(Debug, Copy, Clone)]
+pub struct FilterSample {
+    pub offset: (f64, f64),
+    pub weight: f64,
+}
+
+/// Picks sample offsets in proportion to the magnitude of a filter, so that every sample only
+/// counts towards its own pixel, weighted by the filter over the probability of its offset.
+/// Box and tent filters are sampled exactly with a weight of one, others from a table.
+pub struct FilterSampler {
+    filter: Filter,
+    /// Cumulative distribution of the table's magnitudes, from 0 to 1.
+    cdf: Vec<f64>,
+    table: Option<Table>,
+}
+
+impl FilterSampler {
+    pub fn new(filter: Filter) -> Self {
+        let table = match filter {
+            Filter::Box { .. } | Filter::Tent { .. } => None,
+            _ => Some(Table::new(&filter)),
+        };
+        let mut cdf = vec![0.0];
+        if let Some(table) = &table {
+            for value in &table.values {
+                cdf.push(cdf.last().unwrap() + value.abs());
+            }
+            let total = *cdf.last().unwrap();
+            cdf.iter_mut().for_each(|value| *value /= total);
+        }
+        Self { filter, cdf, table }
+    }
+
+    pub fn sample(&self, u: (f64, f64)) -> FilterSample {
+        let (x, weight_x) = self.sample_1d(u.0);
+        let (y, weight_y) = self.sample_1d(u.1);
+        FilterSample {
+            offset: (x, y),
+            weight: weight_x * weight_y,
+        }
+    }
+
+    fn sample_1d(&self, u: f64) -> (f64, f64) {
+        let radius = self.filter.radius();
+        let Some(table) = &self.table else {
+            let x = match self.filter {
+                Filter::Tent { .. } if u < 0.5 => radius * ((2.0 * u).sqrt() - 1.0),
+                Filter::Tent { .. } => radius * (1.0 - (2.0 - 2.0 * u).sqrt()),
+                _ => radius * (2.0 * u - 1.0),
+            };
+            return (x, 1.0);
+        };
+        // the bin u falls in, skipping empty ones, and how far through it
+        let bin = self
+            .cdf
+            .partition_point(|&value| value <= u)
+            .clamp(1, table.values.len())
+            - 1;
+        let t = ((u - self.cdf[bin]) / (self.cdf[bin + 1] - self.cdf[bin])).clamp(0.0, 1.0);
+        let x = -radius + (bin as f64 + t) * table.bin_width;
+        // weighted by the table rather than the filter at x, so that bins a lobe crosses zero
+        // in don't give large weights
+        let pdf = (self.cdf[bin + 1] - self.cdf[bin]) / table.bin_width;
+        (x, table.values[bin] / pdf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filters() -> [Filter; 5] {
+        [
+            Filter::Box { radius: 0.5 },
+            Filter::Tent { radius: 1.0 },
+            Filter::Gaussian {
+                radius: 1.5,
+                sigma: 0.5,
+            },
+            Filter::Mitchell {
+                radius: 2.0,
+                b: 1.0 / 3.0,
+                c: 1.0 / 3.0,
+            },
+            Filter::Lanczos {
+                radius: 3.0,
+                tau: 3.0,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_weights_estimate_integral() {
+        // the mean weight of sampled offsets is the filter's integral, negative lobes included,
+        // or one for the filters sampled exactly
+        for filter in filters() {
+            let sampler = FilterSampler::new(filter);
+            let n = 256;
+            let mean = (0..n * n)
+                .map(|i| {
+                    let u = (
+                        ((i % n) as f64 + 0.5) / n as f64,
+                        ((i / n) as f64 + 0.5) / n as f64,
+                    );
+                    let sample = sampler.sample(u);
+                    assert!(sample.offset.0.abs() <= filter.radius());
+                    assert!(sample.offset.1.abs() <= filter.radius());
+                    sample.weight
+                })
+                .sum::<f64>()
+                / (n * n) as f64;
+            let expected = match filter {
+                Filter::Box { .. } | Filter::Tent { .. } => 1.0,
+                _ => filter.integral(),
+            };
+            assert!((mean - expected).abs() < 0.01 * expected, "{:?}", filter);
+        }
+    }
+
+    #[test]
+    fn test_sharpening_filters_have_negative_lobes() {
+        for filter in &filters()[3..] {
+            let radius = filter.radius();
+            assert!(filter.evaluate((0.0, 0.0)) > 0.0);
+            assert!((1..100).any(|i| filter.evaluate((i as f64 / 100.0 * radius, 0.0)) < 0.0));
+        }
+        assert_eq!(Filter::Box { radius: 0.5 }.evaluate((0.6, 0.0)), 0.0);
+    }
+
+    #[test]
+    fn test_box_sample_spans_pixel() {
+        let sampler = FilterSampler::new(Filter::Box { radius: 0.5 });
+        assert_eq!(sampler.sample((0.0, 1.0)).offset, (-0.5, 0.5));
+        assert_eq!(sampler.sample((0.25, 0.5)).weight, 1.0);
+    }
+}
@@ -0,0 +1,475 @@
+pub mod aov;
+pub mod filter;
+
+pub use aov::{Aov, AovImage, AovLayer, AovSample, LightingSample};
+pub use filter::{Filter, FilterSample, FilterSampler};
+
+use crate::render::{RenderOutput, Tile};
+use crate::vec3::{ColorSpace, Vec3};
+
+/// Running mean and variance of a pixel's luminance (Welford's algorithm).
+#[derive(Debug, Copy, Clone, Default)]
+pub(crate) struct PixelStats {
+    pub(crate) count: u32,
+    pub(crate) mean: f64,
+    /// Sum of squared differences from the mean.
+    pub(crate) m2: f64,
+}
+
+impl PixelStats {
+    fn add(&mut self, value: f64) {
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+    }
+
+    /// Combines the statistics of two sets of samples (Chan et al.'s update).
+    fn merge(&mut self, other: &PixelStats) {
+        let count = self.count + other.count;
+        if count == 0 {
+            return;
+        }
+        let delta = other.mean - self.mean;
+        let weight = other.count as f64 / count as f64;
+        self.mean += delta * weight;
+        self.m2 += other.m2 + delta * delta * self.count as f64 * weight;
+        self.count = count;
+    }
+
+    pub(crate) fn relative_error(&self) -> f64 {
+        if self.count < 2 {
+            return f64::INFINITY;
+        }
+        let variance = self.m2 / (self.count - 1) as f64;
+        (variance / self.count as f64).sqrt() / self.mean.max(0.01)
+    }
+}
+
+/// Samples taken in one pixel of a film.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct FilmPixel {
+    /// Sum of the radiance of the samples, each times its filter weight.
+    pub(crate) sum: Vec3<f64>,
+    pub(crate) weight_sum: f64,
+    pub(crate) stats: PixelStats,
+}
+
+impl Default for FilmPixel {
+    fn default() -> Self {
+        Self {
+            sum: Vec3::zero(),
+            weight_sum: 0.0,
+            stats: PixelStats::default(),
+        }
+    }
+}
+
+impl FilmPixel {
+    pub(crate) fn add(&mut self, radiance: Vec3<f64>, weight: f64, working: ColorSpace) {
+        self.sum += radiance * weight;
+        self.weight_sum += weight;
+        self.stats.add(working.luminance(radiance));
+    }
+}
+
+/// The image being rendered, kept as sums of filter weighted radiance in floating point until
+/// it's written, so that a render can be continued in passes and looked at in between.
+pub struct Film {
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) filter: Filter,
+    /// Samples every pixel has been asked for so far. Pixels adaptive sampling finds are done
+    /// take fewer.
+    pub(crate) samples_per_pixel: u32,
+    /// Pixels in columns from left to right, each from the bottom row up.
+    pub(crate) pixels: Vec<FilmPixel>,
+    /// Splatted radiance times the filter weight at each pixel, laid out like `pixels`.
+    pub(crate) splats: Vec<Vec3<f64>>,
+    /// AOVs kept besides the radiance, see `with_aovs`.
+    pub(crate) aov_layers: Vec<AovLayer>,
+    /// Values of the AOV layers, a run of one per layer for each pixel in the order of
+    /// `pixels`. Filtered layers are sums of filter weighted values like `FilmPixel::sum`.
+    pub(crate) aovs: Vec<Vec3<f64>>,
+    /// Space of the radiance, which adaptive sampling weighs luminance by.
+    pub(crate) working: ColorSpace,
+}
+
+impl Film {
+    pub fn new(width: u32, height: u32, filter: Filter) -> Self {
+        let pixel_count = (width * height) as usize;
+        Self {
+            width,
+            height,
+            filter,
+            samples_per_pixel: 0,
+            pixels: vec![FilmPixel::default(); pixel_count],
+            splats: vec![Vec3::zero(); pixel_count],
+            aov_layers: Vec::new(),
+            aovs: Vec::new(),
+            working: ColorSpace::Srgb,
+        }
+    }
+
+    /// Measures the luminance adaptive sampling goes by in `working`, sRGB by default.
+    pub fn with_working_space(mut self, working: ColorSpace) -> Self {
+        self.working = working;
+        self
+    }
+
+    /// Also keeps `aovs`, with a layer for each of the scene's `light_groups` for
+    /// `Aov::LightGroups`.
+    pub fn with_aovs(mut self, aovs: &[Aov], light_groups: &[String]) -> Self {
+        self.aov_layers = AovLayer::expand(aovs, light_groups);
+        self.aovs = vec![Vec3::zero(); self.pixels.len() * self.aov_layers.len()];
+        self
+    }
+
+    pub fn aov_layers(&self) -> &[AovLayer] {
+        &self.aov_layers
+    }
+
+    pub fn filter(&self) -> Filter {
+        self.filter
+    }
+
+    /// Samples every pixel has been asked for by the passes so far.
+    pub fn samples_per_pixel(&self) -> u32 {
+        self.samples_per_pixel
+    }
+
+    /// Samples taken in the pixel that took the fewest.
+    pub fn min_samples(&self) -> u32 {
+        self.pixels
+            .iter()
+            .map(|pixel| pixel.stats.count)
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// Adds a sample of the pixel at `pixel`, counted from the bottom left, with `weight` from
+    /// a `FilterSampler` for this film's filter.
+    pub fn add_sample(&mut self, pixel: (u32, u32), radiance: Vec3<f64>, weight: f64) {
+        let index = (pixel.0 * self.height + pixel.1) as usize;
+        self.pixels[index].add(radiance, weight, self.working);
+    }
+
+    /// Like `add_sample`, also adding the sample's AOVs.
+    pub fn add_sample_with_aovs(
+        &mut self,
+        pixel: (u32, u32),
+        radiance: Vec3<f64>,
+        weight: f64,
+        aovs: &AovSample,
+    ) {
+        let index = (pixel.0 * self.height + pixel.1) as usize;
+        let layers = self.aov_layers.len();
+        add_aovs(
+            &self.aov_layers,
+            &mut self.aovs[index * layers..(index + 1) * layers],
+            &aovs.values(&self.aov_layers),
+            weight,
+            self.pixels[index].stats.count == 0,
+        );
+        self.pixels[index].add(radiance, weight, self.working);
+    }
+
+    /// Adds radiance a light path brought to a position on the film, from 0 to 1 across it and
+    /// from the bottom up, to the pixels whose filter reaches it.
+    pub fn add_splat(&mut self, film: (f64, f64), radiance: Vec3<f64>) {
+        let window = Tile {
+            x: 0,
+            y: 0,
+            width: self.width,
+            height: self.height,
+        };
+        self.add_splat_within(window, film, radiance);
+    }
+
+    /// Like `add_splat`, but leaving out pixels outside `window`.
+    pub(crate) fn add_splat_within(&mut self, window: Tile, film: (f64, f64), radiance: Vec3<f64>) {
+        let x = film.0 * self.width as f64;
+        let y = film.1 * self.height as f64;
+        let radius = self.filter.radius();
+        // pixels with centres within the radius
+        let range = |p: f64, start: u32, size: u32| {
+            let first = (p - 0.5 - radius).ceil().max(start as f64) as u32;
+            let last = ((p - 0.5 + radius).floor() as i64).min((start + size) as i64 - 1);
+            first as i64..=last
+        };
+        for i in range(x, window.x, window.width) {
+            for j in range(y, window.y, window.height) {
+                let offset = (x - (i as f64 + 0.5), y - (j as f64 + 0.5));
+                let weight = self.filter.evaluate(offset);
+                if weight != 0.0 {
+                    self.splats[(i as u32 * self.height + j as u32) as usize] += radiance * weight;
+                }
+            }
+        }
+    }
+
+    /// Adds the samples, splats and AOVs of another film of the same size, filter and AOVs.
+    pub fn merge(&mut self, other: &Film) {
+        let layers = self.aov_layers.len();
+        if layers > 0 {
+            // before the pixels, so that their counts tell which film has their first samples
+            for ((pixel, values), other_values) in self
+                .pixels
+                .iter()
+                .zip(self.aovs.chunks_mut(layers))
+                .zip(other.aovs.chunks(layers))
+            {
+                let first = pixel.stats.count == 0;
+                add_aovs(&self.aov_layers, values, other_values, 1.0, first);
+            }
+        }
+        for (pixel, other) in self.pixels.iter_mut().zip(&other.pixels) {
+            pixel.sum += other.sum;
+            pixel.weight_sum += other.weight_sum;
+            pixel.stats.merge(&other.stats);
+        }
+        for (splat, other) in self.splats.iter_mut().zip(&other.splats) {
+            *splat += *other;
+        }
+    }
+
+    /// The image so far. Splats are divided by the filter's integral and scaled by the mean
+    /// number of samples per rendered pixel, as they land wherever the light paths of every
+    /// pixel's samples reach. A film of only splats, as Metropolis light transport renders,
+    /// takes them as they are.
+    pub fn image(&self) -> RenderOutput {
+        let total: u64 = self
+            .pixels
+            .iter()
+            .map(|pixel| pixel.stats.count as u64)
+            .sum();
+        let rendered = self
+            .pixels
+            .iter()
+            .filter(|pixel| pixel.stats.count > 0)
+            .count();
+        let splat_scale = if total == 0 {
+            1.0
+        } else {
+            rendered as f64 / total as f64
+        } / self.filter.integral();
+        let height = self.height as usize;
+        let column = |i: usize| i * height..(i + 1) * height;
+        RenderOutput {
+            pixels: (0..self.width as usize)
+                .map(|i| {
+                    column(i)
+                        .rev()
+                        .map(|index| {
+                            let pixel = &self.pixels[index];
+                            let mean = if pixel.weight_sum == 0.0 {
+                                Vec3::zero()
+                            } else {
+                                pixel.sum / pixel.weight_sum
+                            };
+                            mean + self.splats[index] * splat_scale
+                        })
+                        .collect()
+                })
+                .collect(),
+            samples: (0..self.width as usize)
+                .map(|i| {
+                    column(i)
+                        .rev()
+                        .map(|index| self.pixels[index].stats.count)
+                        .collect()
+                })
+                .collect(),
+            aovs: self
+                .aov_layers
+                .iter()
+                .enumerate()
+                .map(|(layer_index, layer)| AovImage {
+                    layer: layer.clone(),
+                    pixels: (0..self.width as usize)
+                        .map(|i| {
+                            column(i)
+                                .rev()
+                                .map(|index| {
+                                    let value =
+                                        self.aovs[index * self.aov_layers.len() + layer_index];
+                                    let weight_sum = self.pixels[index].weight_sum;
+                                    if !layer.aov.is_filtered() {
+                                        value
+                                    } else if weight_sum == 0.0 {
+                                        Vec3::zero()
+                                    } else {
+                                        value / weight_sum
+                                    }
+                                })
+                                .collect()
+                        })
+                        .collect(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Adds the `sample` values of AOV `layers` with the filter `weight` to a pixel's `values`,
+/// replacing those of unfiltered layers only with the pixel's `first` sample.
+pub(crate) fn add_aovs(
+    layers: &[AovLayer],
+    values: &mut [Vec3<f64>],
+    sample: &[Vec3<f64>],
+    weight: f64,
+    first: bool,
+) {
+    for ((value, sample), layer) in values.iter_mut().zip(sample).zip(layers) {
+        if layer.aov.is_filtered() {
+            *value += *sample * weight;
+        } else if first {
+            *value = *sample;
+        }
+    }
+}
+
+/// Outlier rejection: darkens each pixel of an image laid out like `RenderOutput::pixels` whose
+/// luminance is over `threshold` times that of the brightest of its eight neighbours down to
+/// that neighbour's. A rare bright path leaves a lone white pixel that more samples would take
+/// very long to average away, whereas edges and highlights have bright neighbours and are kept.
+/// Pixels with only black neighbours, such as a small light against a black background, are
+/// kept too, as darkening them would blank them out. The lighting layers among `aovs` are darkened alike, so that they still add up to the image.
+/// Luminance is that of the `working` space.
+pub fn reject_outliers(
+    pixels: &mut [Vec<Vec3<f64>>],
+    aovs: &mut [AovImage],
+    threshold: f64,
+    working: ColorSpace,
+) {
+    let image = pixels.to_vec();
+    let width = image.len();
+    for (x, column) in pixels.iter_mut().enumerate() {
+        let height = column.len();
+        for (y, pixel) in column.iter_mut().enumerate() {
+            let brightest = (x.saturating_sub(1)..(x + 2).min(width))
+                .flat_map(|nx| (y.saturating_sub(1)..(y + 2).min(height)).map(move |ny| (nx, ny)))
+                .filter(|&neighbour| neighbour != (x, y))
+                .map(|(nx, ny)| working.luminance(image[nx][ny]))
+                .fold(0.0, f64::max);
+            let luminance = working.luminance(*pixel);
+            if brightest > 0.0 && luminance > threshold * brightest {
+                let scale = brightest / luminance;
+                *pixel *= scale;
+                for aov in aovs.iter_mut().filter(|aov| aov.layer.aov.is_lighting()) {
+                    aov.pixels[x][y] *= scale;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_weighted_mean() {
+        let mut film = Film::new(2, 1, Filter::Box { radius: 0.5 });
+        film.add_sample((1, 0), Vec3::new(1.0, 1.0, 1.0), 3.0);
+        film.add_sample((1, 0), Vec3::new(5.0, 5.0, 5.0), 1.0);
+        let image = film.image();
+        assert_eq!(image.pixels[0][0], Vec3::zero());
+        assert_eq!(image.pixels[1][0], Vec3::new(2.0, 2.0, 2.0));
+        assert_eq!(image.samples, vec![vec![0], vec![2]]);
+    }
+
+    #[test]
+    fn test_aovs_filtered_or_from_first_sample() {
+        let mut film =
+            Film::new(1, 1, Filter::Box { radius: 0.5 }).with_aovs(&[Aov::Albedo, Aov::Depth], &[]);
+        let mut sample = AovSample::miss(0);
+        sample.albedo = Vec3::new(1.0, 1.0, 1.0);
+        sample.depth = 2.0;
+        film.add_sample_with_aovs((0, 0), Vec3::zero(), 3.0, &sample);
+        sample.albedo = Vec3::new(5.0, 5.0, 5.0);
+        sample.depth = 7.0;
+        film.add_sample_with_aovs((0, 0), Vec3::zero(), 1.0, &sample);
+
+        let mut other =
+            Film::new(1, 1, Filter::Box { radius: 0.5 }).with_aovs(&[Aov::Albedo, Aov::Depth], &[]);
+        other.add_sample_with_aovs((0, 0), Vec3::zero(), 4.0, &sample);
+        film.merge(&other);
+
+        let image = film.image();
+        assert_eq!(image.aovs[0].layer.name, "albedo");
+        assert_eq!(image.aovs[0].pixels[0][0], Vec3::new(3.5, 3.5, 3.5));
+        assert_eq!(image.aovs[1].pixels[0][0].x, 2.0);
+    }
+
+    #[test]
+    fn test_reject_outliers() {
+        let grey = Vec3::new(0.5, 0.5, 0.5);
+        let mut pixels = vec![vec![grey; 4]; 4];
+        pixels[1][1] = Vec3::new(50.0, 50.0, 50.0);
+        // a bright edge along the last column
+        pixels[3] = vec![Vec3::new(2.0, 2.0, 2.0); 4];
+        let layer = |aov: Aov| AovImage {
+            layer: AovLayer {
+                aov,
+                name: aov.name().to_string(),
+            },
+            pixels: pixels.clone(),
+        };
+        let mut aovs = [layer(Aov::DiffuseDirect), layer(Aov::Albedo)];
+        reject_outliers(&mut pixels, &mut aovs, 4.0, ColorSpace::Srgb);
+        assert!((pixels[1][1] - grey).length() < 1e-12);
+        // lighting still adds up to the image, while surface properties are kept
+        assert_eq!(aovs[0].pixels, pixels);
+        assert_eq!(aovs[1].pixels[1][1], Vec3::new(50.0, 50.0, 50.0));
+        assert_eq!(pixels[3][0], Vec3::new(2.0, 2.0, 2.0));
+        assert_eq!(pixels[2][2], grey);
+    }
+
+    #[test]
+    fn test_reject_outliers_keeps_lone_lights() {
+        let mut pixels = vec![vec![Vec3::zero(); 3]; 3];
+        pixels[1][1] = Vec3::new(50.0, 50.0, 50.0);
+        let expected = pixels.clone();
+        reject_outliers(&mut pixels, &mut [], 4.0, ColorSpace::Srgb);
+        assert_eq!(pixels, expected);
+    }
+
+    #[test]
+    fn test_splat_keeps_energy() {
+        // a splat spread by a wide filter adds up to the same as one landing in a single pixel
+        let filter = Filter::Gaussian {
+            radius: 3.0,
+            sigma: 1.0,
+        };
+        let mut film = Film::new(16, 16, filter);
+        film.add_splat((0.53, 0.47), Vec3::new(1.0, 1.0, 1.0));
+        let image = film.image();
+        let total: f64 = image.pixels.iter().flatten().map(|pixel| pixel.x).sum();
+        assert!((total - 1.0).abs() < 0.01);
+        assert!(
+            image
+                .pixels
+                .iter()
+                .flatten()
+                .filter(|pixel| pixel.x > 0.0)
+                .count()
+                > 1
+        );
+    }
+
+    #[test]
+    fn test_splat_within_window() {
+        let mut film = Film::new(4, 4, Filter::Tent { radius: 2.0 });
+        let window = Tile {
+            x: 0,
+            y: 0,
+            width: 2,
+            height: 4,
+        };
+        film.add_splat_within(window, (0.5, 0.5), Vec3::new(1.0, 1.0, 1.0));
+        let image = film.image();
+        assert!(image.pixels[1][1].x > 0.0);
+        assert_eq!(image.pixels[2][1], Vec3::zero());
+    }
+}
@@ -0,0 +1,58 @@
+use crate::integrator::Integrator;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::scene::Scene;
+use crate::vec3::utils::sample_cosine_hemisphere;
+use crate::vec3::{Onb, Vec3};
+
+/// Fraction of the cosine weighted hemisphere above the first hit that is unoccluded within
+/// `max_distance`, shown in grey. Misses are black.
+pub struct AmbientOcclusion {
+    samples: u32,
+    max_distance: f64,
+}
+
+impl AmbientOcclusion {
+    pub fn new(samples: u32, max_distance: f64) -> Self {
+        Self {
+            samples: samples.max(1),
+            max_distance,
+        }
+    }
+}
+
+impl Integrator for AmbientOcclusion {
+    fn li(&self, ray: &Ray, scene: &Scene, sampler: &mut dyn Sampler) -> Vec3<f64> {
+        let Some(hit) = scene.hit(ray, 0.001, f64::INFINITY) else {
+            return Vec3::zero();
+        };
+
+        let frame = Onb::new(&hit.normal);
+        let unoccluded = (0..self.samples)
+            .filter(|_| {
+                let dir = frame.to_world(&sample_cosine_hemisphere(sampler.get_2d()));
+                let occlusion_ray = Ray::new(hit.point, dir, ray.time);
+                scene
+                    .hit(&occlusion_ray, 0.001, self.max_distance)
+                    .is_none()
+            })
+            .count();
+        let visibility = unoccluded as f64 / self.samples as f64;
+        Vec3::new(visibility, visibility, visibility)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::integrator::tests::mean_ground_radiance;
+
+    #[test]
+    fn test_open_ground_is_mostly_visible() {
+        let visibility = mean_ground_radiance(&AmbientOcclusion::new(4, f64::INFINITY), 200);
+        // only the small light above blocks part of the sky
+        assert!(visibility.x > 0.8 && visibility.x < 1.0);
+        let local = mean_ground_radiance(&AmbientOcclusion::new(4, 1.0), 50);
+        assert_eq!(local.x, 1.0);
+    }
+}
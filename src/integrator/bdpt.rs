@@ -0,0 +1,670 @@
+use crate::integrator::path::MIN_BOUNCES;
+use crate::integrator::{Integrator, Splat};
+use crate::objects::hittable::HitRecord;
+use crate::objects::Camera;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::scene::Scene;
+use crate::vec3::{Point3, Vec3};
+use std::f64::consts::PI;
+
+/// Bidirectional path tracer (Veach's thesis, following the structure of pbrt's). A subpath is
+/// traced from the camera and another from a light, every pair of their vertices is connected
+/// and each of the resulting strategies is weighted with the power heuristic. Connections
+/// straight to the camera land on other pixels and are returned as splats.
+///
+/// Emissive objects that aren't in the scene's light list, and the background, are only found
+/// by camera subpaths.
+pub struct Bdpt {
+    camera: Camera,
+    max_depth: u32,
+}
+
+impl Bdpt {
+    /// `max_depth` is the longest path in bounces, so edges minus one.
+    pub fn new(camera: Camera, max_depth: u32) -> Self {
+        Self { camera, max_depth }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum VertexKind {
+    Camera,
+    Light,
+    Surface,
+    /// A camera subpath leaving the scene. `wo` points back along the escaping ray.
+    Background,
+}
+
+#[derive(Clone, Copy)]
+struct Vertex<'a> {
+    kind: VertexKind,
+    point: Point3<f64>,
+    /// Outward geometric normal of lights and surfaces, zero otherwise.
+    normal: Vec3<f64>,
+    /// Surface hit with its normal facing `wo`.
+    hit: Option<HitRecord<'a>>,
+    /// Unit direction towards the previous vertex of the subpath.
+    wo: Vec3<f64>,
+    /// Index of the top level object, for looking up lights.
+    object: Option<usize>,
+    /// Throughput from the start of the subpath up to and including this vertex.
+    beta: Vec3<f64>,
+    delta: bool,
+    /// Area densities of sampling this vertex from the previous one and, for the other
+    /// direction, from the next one.
+    pdf_fwd: f64,
+    pdf_rev: f64,
+}
+
+/// The hit with its normal flipped if needed to face `w`, as materials expect for `wo`.
+fn facing<'a>(hit: &HitRecord<'a>, w: &Vec3<f64>) -> HitRecord<'a> {
+    let mut hit = *hit;
+    if hit.normal.dot(w) < 0.0 {
+        hit.normal = -hit.normal;
+        hit.front_face = !hit.front_face;
+    }
+    hit
+}
+
+fn outward_normal(hit: &HitRecord) -> Vec3<f64> {
+    if hit.front_face {
+        hit.normal
+    } else {
+        -hit.normal
+    }
+}
+
+// zero densities come from delta vertices, for which the ratios cancel out
+fn remap0(pdf: f64) -> f64 {
+    if pdf != 0.0 {
+        pdf
+    } else {
+        1.0
+    }
+}
+
+impl<'a> Vertex<'a> {
+    fn camera(point: Point3<f64>, beta: Vec3<f64>) -> Self {
+        Self {
+            kind: VertexKind::Camera,
+            point,
+            normal: Vec3::zero(),
+            hit: None,
+            wo: Vec3::zero(),
+            object: None,
+            beta,
+            delta: false,
+            pdf_fwd: 0.0,
+            pdf_rev: 0.0,
+        }
+    }
+
+    fn light(hit: HitRecord<'a>, object: usize, beta: Vec3<f64>, pdf_fwd: f64) -> Self {
+        Self {
+            kind: VertexKind::Light,
+            point: hit.point,
+            normal: outward_normal(&hit),
+            hit: Some(hit),
+            wo: Vec3::zero(),
+            object: Some(object),
+            beta,
+            delta: false,
+            pdf_fwd,
+            pdf_rev: 0.0,
+        }
+    }
+
+    fn surface(hit: HitRecord<'a>, object: usize, wo: Vec3<f64>, beta: Vec3<f64>) -> Self {
+        Self {
+            kind: VertexKind::Surface,
+            point: hit.point,
+            normal: outward_normal(&hit),
+            hit: Some(hit),
+            wo,
+            object: Some(object),
+            beta,
+            delta: false,
+            pdf_fwd: 0.0,
+            pdf_rev: 0.0,
+        }
+    }
+
+    fn background(wo: Vec3<f64>, beta: Vec3<f64>, pdf_fwd: f64) -> Self {
+        Self {
+            kind: VertexKind::Background,
+            point: Point3::zero(),
+            normal: Vec3::zero(),
+            hit: None,
+            wo,
+            object: None,
+            beta,
+            delta: false,
+            pdf_fwd,
+            pdf_rev: 0.0,
+        }
+    }
+
+    fn is_on_surface(&self) -> bool {
+        matches!(self.kind, VertexKind::Light | VertexKind::Surface)
+    }
+
+    fn is_connectible(&self) -> bool {
+        match self.kind {
+            VertexKind::Surface => !self.hit.is_some_and(|hit| hit.material.is_delta()),
+            VertexKind::Background => false,
+            _ => true,
+        }
+    }
+
+    fn is_emitter(&self) -> bool {
+        match self.kind {
+            VertexKind::Surface | VertexKind::Light => {
+                self.hit.is_some_and(|hit| hit.material.is_emissive())
+            }
+            VertexKind::Background => true,
+            VertexKind::Camera => false,
+        }
+    }
+
+    /// Unit direction and squared distance to another vertex.
+    fn direction_to(&self, next: &Vertex) -> Option<(Vec3<f64>, f64)> {
+        if next.kind == VertexKind::Background {
+            return Some((-next.wo, 1.0));
+        }
+        let d = next.point - self.point;
+        let dist2 = d.dot(&d);
+        if dist2 == 0.0 {
+            return None;
+        }
+        Some((d / dist2.sqrt(), dist2))
+    }
+
+    /// BSDF value for scattering from `wo` towards `next`.
+    fn f(&self, next: &Vertex) -> Vec3<f64> {
+        let (Some(hit), Some((wi, _))) = (self.hit, self.direction_to(next)) else {
+            return Vec3::zero();
+        };
+        hit.material.eval(&hit, &self.wo, &wi)
+    }
+
+    /// Radiance emitted from this vertex towards `next`.
+    fn le(&self, scene: &Scene, next: &Vertex) -> Vec3<f64> {
+        if self.kind == VertexKind::Background {
+            return scene.background.color(&-self.wo);
+        }
+        let (Some(hit), Some((w, _))) = (self.hit, self.direction_to(next)) else {
+            return Vec3::zero();
+        };
+        hit.material.emitted(&facing(&hit, &w))
+    }
+
+    /// Converts a solid angle density of sampling the direction to `next` into an area density
+    /// at `next`.
+    fn convert_density(&self, pdf: f64, next: &Vertex) -> f64 {
+        match self.direction_to(next) {
+            Some(_) if next.kind == VertexKind::Background => pdf,
+            Some((w, dist2)) if next.is_on_surface() => pdf * next.normal.dot(&w).abs() / dist2,
+            Some((_, dist2)) => pdf / dist2,
+            None => 0.0,
+        }
+    }
+
+    /// Area density of sampling `next` from this vertex having arrived from `prev`.
+    fn pdf(&self, camera: &Camera, prev: Option<&Vertex>, next: &Vertex) -> f64 {
+        if self.kind == VertexKind::Light {
+            return self.pdf_light(next);
+        }
+        let Some((wn, _)) = self.direction_to(next) else {
+            return 0.0;
+        };
+        let pdf = match (self.kind, self.hit, prev) {
+            (VertexKind::Camera, _, _) => camera.pdf_importance(&Ray::new(self.point, wn, 0.0)).1,
+            (VertexKind::Surface, Some(hit), Some(prev)) => {
+                let Some((wp, _)) = self.direction_to(prev) else {
+                    return 0.0;
+                };
+                hit.material.pdf(&facing(&hit, &wp), &wp, &wn)
+            }
+            _ => 0.0,
+        };
+        self.convert_density(pdf, next)
+    }
+
+    /// Area density at `next` of this emitting vertex sending light towards it, for cosine
+    /// weighted emission from the front face.
+    fn pdf_light(&self, next: &Vertex) -> f64 {
+        let Some((w, _)) = self.direction_to(next) else {
+            return 0.0;
+        };
+        let pdf_dir = self.normal.dot(&w).max(0.0) / PI;
+        self.convert_density(pdf_dir, next)
+    }
+
+    /// Area density of a light subpath starting at this point.
+    fn pdf_light_origin(&self, scene: &Scene) -> f64 {
+        let Some(object) = self.object else {
+            return 0.0;
+        };
+        let pmf = scene.lights.pmf(object);
+        if pmf == 0.0 {
+            return 0.0;
+        }
+        pmf / scene.world.objects[object].area()
+    }
+}
+
+/// Geometry term between two vertices including visibility.
+fn geometry(scene: &Scene, a: &Vertex, b: &Vertex, time: f64) -> f64 {
+    let Some((w, dist2)) = a.direction_to(b) else {
+        return 0.0;
+    };
+    let mut g = 1.0 / dist2;
+    if a.is_on_surface() {
+        g *= a.normal.dot(&w).abs();
+    }
+    if b.is_on_surface() {
+        g *= b.normal.dot(&w).abs();
+    }
+    if g == 0.0 || !scene.unoccluded(&a.point, &b.point, time) {
+        return 0.0;
+    }
+    g
+}
+
+impl Bdpt {
+    fn camera_subpath<'a>(
+        &self,
+        ray: &Ray,
+        scene: &'a Scene,
+        sampler: &mut dyn Sampler,
+        path: &mut Vec<Vertex<'a>>,
+    ) {
+        let (_, pdf_dir) = self.camera.pdf_importance(ray);
+        let beta = Vec3::new(1.0, 1.0, 1.0);
+        path.push(Vertex::camera(ray.orig, beta));
+        self.random_walk(
+            scene,
+            *ray,
+            beta,
+            pdf_dir,
+            self.max_depth + 1,
+            true,
+            sampler,
+            path,
+        );
+    }
+
+    fn light_subpath<'a>(
+        &self,
+        scene: &'a Scene,
+        time: f64,
+        sampler: &mut dyn Sampler,
+        path: &mut Vec<Vertex<'a>>,
+    ) {
+        let Some(emission) = scene.lights.sample_emission(
+            &scene.world,
+            sampler.get_1d(),
+            sampler.get_2d(),
+            sampler.get_2d(),
+        ) else {
+            return;
+        };
+        path.push(Vertex::light(
+            emission.hit,
+            emission.object,
+            emission.le,
+            emission.pdf_pos,
+        ));
+        let ray = Ray::new(emission.hit.point, emission.dir, time);
+        let (beta, pdf_dir) = (emission.weight(), emission.pdf_dir);
+        self.random_walk(
+            scene,
+            ray,
+            beta,
+            pdf_dir,
+            self.max_depth,
+            false,
+            sampler,
+            path,
+        );
+    }
+
+    /// Extends a subpath by sampling BSDFs from `ray`, whose direction has solid angle density
+    /// `pdf`, adding up to `max_bounces` vertices.
+    #[allow(clippy::too_many_arguments)]
+    fn random_walk<'a>(
+        &self,
+        scene: &'a Scene,
+        mut ray: Ray,
+        mut beta: Vec3<f64>,
+        pdf: f64,
+        max_bounces: u32,
+        from_camera: bool,
+        sampler: &mut dyn Sampler,
+        path: &mut Vec<Vertex<'a>>,
+    ) {
+        let mut pdf_fwd = pdf;
+        let mut bounces = 0;
+        while bounces < max_bounces {
+            let prev = path.len() - 1;
+            let Some((index, hit)) = scene.hit_object(&ray, 0.001, f64::INFINITY) else {
+                if from_camera {
+                    path.push(Vertex::background(-ray.dir.normalize(), beta, pdf_fwd));
+                }
+                return;
+            };
+            let wo = -ray.dir.normalize();
+            let mut vertex = Vertex::surface(hit, index, wo, beta);
+            vertex.pdf_fwd = path[prev].convert_density(pdf_fwd, &vertex);
+            bounces += 1;
+            if bounces >= max_bounces {
+                path.push(vertex);
+                return;
+            }
+
+            let Some(sample) = hit
+                .material
+                .sample(&hit, &wo, sampler.get_1d(), sampler.get_2d())
+            else {
+                path.push(vertex);
+                return;
+            };
+            beta *= sample.weight(&hit.normal);
+            pdf_fwd = sample.pdf;
+            let mut pdf_rev = hit.material.pdf(&facing(&hit, &sample.wi), &sample.wi, &wo);
+            if sample.delta {
+                vertex.delta = true;
+                pdf_fwd = 0.0;
+                pdf_rev = 0.0;
+            }
+            path.push(vertex);
+            path[prev].pdf_rev = path[prev + 1].convert_density(pdf_rev, &path[prev]);
+            ray = Ray::new(hit.point, sample.wi, ray.time);
+
+            // russian roulette only rescales the throughput, the MIS weights ignore it
+            if bounces >= MIN_BOUNCES {
+                let survival = beta.max_component().min(0.95);
+                if sampler.get_1d() >= survival {
+                    return;
+                }
+                beta = beta / survival;
+            }
+        }
+    }
+
+    /// Contribution of the path made of the first `s` light and first `t` camera vertices, and
+    /// the film position it lands on when it connects straight to the camera.
+    #[allow(clippy::too_many_arguments)]
+    fn connect(
+        &self,
+        scene: &Scene,
+        light: &[Vertex],
+        camera: &[Vertex],
+        s: usize,
+        t: usize,
+        time: f64,
+        sampler: &mut dyn Sampler,
+    ) -> (Vec3<f64>, Option<(f64, f64)>) {
+        if t > 1 && s != 0 && camera[t - 1].kind == VertexKind::Background {
+            return (Vec3::zero(), None);
+        }
+
+        let mut sampled = None;
+        let mut film = None;
+        let radiance = if s == 0 {
+            // the camera subpath found an emitter by itself
+            let pt = &camera[t - 1];
+            if pt.is_emitter() {
+                pt.beta * pt.le(scene, &camera[t - 2])
+            } else {
+                Vec3::zero()
+            }
+        } else if t == 1 {
+            // connect the light subpath to a point on the lens
+            let qs = &light[s - 1];
+            match self.camera.sample_importance(&qs.point, sampler.get_2d()) {
+                Some(sample) if qs.is_connectible() && sample.pdf > 0.0 => {
+                    let beta = sample.importance / sample.pdf;
+                    let vertex = Vertex::camera(sample.lens_point, Vec3::new(beta, beta, beta));
+                    let mut l = qs.beta * qs.f(&vertex) * vertex.beta;
+                    if qs.is_on_surface() {
+                        l *= (sample.lens_point - qs.point)
+                            .normalize()
+                            .dot(&qs.normal)
+                            .abs();
+                    }
+                    if !l.near_zero() && !scene.unoccluded(&qs.point, &sample.lens_point, time) {
+                        l = Vec3::zero();
+                    }
+                    film = Some(sample.film);
+                    sampled = Some(vertex);
+                    l
+                }
+                _ => Vec3::zero(),
+            }
+        } else if s == 1 {
+            // sample a new point on a light, as in next event estimation
+            let pt = &camera[t - 1];
+            let light_sample = scene
+                .lights
+                .pick(sampler.get_1d())
+                .and_then(|(index, pmf)| {
+                    let (hit, pdf) =
+                        scene.world.objects[index].sample(&pt.point, sampler.get_2d())?;
+                    Some((index, hit, pdf * pmf))
+                });
+            match light_sample {
+                Some((index, hit, pdf)) if pt.is_connectible() && pdf > 0.0 => {
+                    let le = hit.material.emitted(&hit);
+                    let mut vertex = Vertex::light(hit, index, le / pdf, 0.0);
+                    vertex.pdf_fwd = vertex.pdf_light_origin(scene);
+                    let mut l = pt.beta * pt.f(&vertex) * vertex.beta;
+                    if pt.is_on_surface() {
+                        l *= (hit.point - pt.point).normalize().dot(&pt.normal).abs();
+                    }
+                    if !l.near_zero() && !scene.unoccluded(&pt.point, &hit.point, time) {
+                        l = Vec3::zero();
+                    }
+                    sampled = Some(vertex);
+                    l
+                }
+                _ => Vec3::zero(),
+            }
+        } else {
+            let (qs, pt) = (&light[s - 1], &camera[t - 1]);
+            if qs.is_connectible() && pt.is_connectible() {
+                let l = qs.beta * qs.f(pt) * pt.f(qs) * pt.beta;
+                if l.near_zero() {
+                    l
+                } else {
+                    l * geometry(scene, qs, pt, time)
+                }
+            } else {
+                Vec3::zero()
+            }
+        };
+
+        if radiance.near_zero() {
+            return (Vec3::zero(), None);
+        }
+        let weight = self.mis_weight(scene, light, camera, sampled, s, t);
+        (radiance * weight, film)
+    }
+
+    /// Power heuristic weight of the strategy with `s` light and `t` camera vertices against
+    /// every other strategy that could have produced the same path.
+    fn mis_weight(
+        &self,
+        scene: &Scene,
+        light: &[Vertex],
+        camera: &[Vertex],
+        sampled: Option<Vertex>,
+        s: usize,
+        t: usize,
+    ) -> f64 {
+        if s + t == 2 {
+            return 1.0;
+        }
+        // nothing but the camera subpath can find the background or unlisted emitters
+        if s == 0 && camera[t - 1].pdf_light_origin(scene) == 0.0 {
+            return 1.0;
+        }
+
+        let mut light = light[..s].to_vec();
+        let mut camera = camera[..t].to_vec();
+        if let Some(sampled) = sampled {
+            if s == 1 {
+                light[0] = sampled;
+            } else if t == 1 {
+                camera[0] = sampled;
+            }
+        }
+
+        // the connecting vertices are never delta, and their reverse densities depend on the
+        // strategy
+        if t > 0 {
+            camera[t - 1].delta = false;
+        }
+        if s > 0 {
+            light[s - 1].delta = false;
+        }
+        if t > 0 {
+            camera[t - 1].pdf_rev = if s > 0 {
+                let prev = if s > 1 { Some(&light[s - 2]) } else { None };
+                light[s - 1].pdf(&self.camera, prev, &camera[t - 1])
+            } else {
+                camera[t - 1].pdf_light_origin(scene)
+            };
+        }
+        if t > 1 {
+            camera[t - 2].pdf_rev = if s > 0 {
+                camera[t - 1].pdf(&self.camera, Some(&light[s - 1]), &camera[t - 2])
+            } else {
+                camera[t - 1].pdf_light(&camera[t - 2])
+            };
+        }
+        if s > 0 {
+            let prev = if t > 1 { Some(&camera[t - 2]) } else { None };
+            light[s - 1].pdf_rev = camera[t - 1].pdf(&self.camera, prev, &light[s - 1]);
+        }
+        if s > 1 {
+            light[s - 2].pdf_rev =
+                light[s - 1].pdf(&self.camera, Some(&camera[t - 1]), &light[s - 2]);
+        }
+
+        let mut sum = 0.0;
+        let mut ratio = 1.0;
+        for i in (1..t).rev() {
+            ratio *= remap0(camera[i].pdf_rev) / remap0(camera[i].pdf_fwd);
+            if !camera[i].delta && !camera[i - 1].delta {
+                sum += ratio * ratio;
+            }
+        }
+        ratio = 1.0;
+        for i in (0..s).rev() {
+            ratio *= remap0(light[i].pdf_rev) / remap0(light[i].pdf_fwd);
+            let delta_before = i > 0 && light[i - 1].delta;
+            if !light[i].delta && !delta_before {
+                sum += ratio * ratio;
+            }
+        }
+        1.0 / (1.0 + sum)
+    }
+}
+
+impl Integrator for Bdpt {
+    /// Radiance without the contributions that connect light subpaths to the camera, which only
+    /// `li_splat` can return.
+    fn li(&self, ray: &Ray, scene: &Scene, sampler: &mut dyn Sampler) -> Vec3<f64> {
+        self.li_splat(ray, scene, sampler, &mut Vec::new())
+    }
+
+    fn li_splat(
+        &self,
+        ray: &Ray,
+        scene: &Scene,
+        sampler: &mut dyn Sampler,
+        splats: &mut Vec<Splat>,
+    ) -> Vec3<f64> {
+        let mut camera = Vec::with_capacity(self.max_depth as usize + 2);
+        let mut light = Vec::with_capacity(self.max_depth as usize + 1);
+        self.camera_subpath(ray, scene, sampler, &mut camera);
+        self.light_subpath(scene, ray.time, sampler, &mut light);
+
+        let mut radiance = Vec3::zero();
+        for t in 1..=camera.len() {
+            for s in 0..=light.len() {
+                let depth = (s + t) as i64 - 2;
+                if (s == 1 && t == 1) || depth < 0 || depth > self.max_depth as i64 {
+                    continue;
+                }
+                let (l, film) = self.connect(scene, &light, &camera, s, t, ray.time, sampler);
+                match film {
+                    Some(film) => splats.push(Splat { film, radiance: l }),
+                    None => radiance += l,
+                }
+            }
+        }
+        radiance
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::integrator::tests::lit_scene;
+    use crate::integrator::PathTracer;
+    use crate::sampler::IndependentSampler;
+
+    fn camera() -> Camera {
+        Camera::new(
+            Point3::new(0.0, 3.0, 0.0),
+            Point3::zero(),
+            Vec3::new(0.0, 0.0, -1.0),
+            60.0,
+            1.0,
+            0.0,
+            3.0,
+            0.0,
+            1.0,
+        )
+    }
+
+    /// Mean radiance over a small image of the lit ground, splats included.
+    fn mean_image_radiance(integrator: &dyn Integrator, spp: u32) -> f64 {
+        let scene = lit_scene();
+        let camera = camera();
+        let mut sampler = IndependentSampler::new(0);
+        let n = 6;
+        let mut sum = Vec3::zero();
+        let mut splats = Vec::new();
+        for i in 0..n {
+            for j in 0..n {
+                for _ in 0..spp {
+                    let s = (i as f64 + sampler.get_1d()) / n as f64;
+                    let t = (j as f64 + sampler.get_1d()) / n as f64;
+                    let ray = camera.generate_ray(s, t, sampler.get_2d(), sampler.get_1d());
+                    sum += integrator.li_splat(&ray, &scene, &mut sampler, &mut splats);
+                }
+            }
+        }
+        for splat in splats {
+            sum += splat.radiance;
+        }
+        sum.x / (n * n * spp) as f64
+    }
+
+    #[test]
+    fn test_agrees_with_path_tracer() {
+        let bdpt = mean_image_radiance(&Bdpt::new(camera(), 8), 100);
+        let path = mean_image_radiance(&PathTracer::new(8), 100);
+        assert!(path > 0.0);
+        assert!(
+            (bdpt - path).abs() < 0.05 * path,
+            "bdpt {} path {}",
+            bdpt,
+            path
+        );
+    }
+}
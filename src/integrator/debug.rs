@@ -0,0 +1,173 @@
+use crate::integrator::path::MIN_BOUNCES;
+use crate::integrator::Integrator;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::scene::Scene;
+use crate::vec3::Vec3;
+
+/// What a `DebugView` shows at the first hit along each camera ray. Misses are black.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum DebugMode {
+    /// Outward geometric normal mapped from [-1, 1] to [0, 1] per axis.
+    Normal,
+    /// Distance to the hit in grey, white at `max_distance` and beyond.
+    Depth { max_distance: f64 },
+    /// Material colour, see `Material::albedo`.
+    Albedo,
+    /// Texture coordinates as red and green.
+    Uv,
+    /// A distinct colour for each top level object in the scene.
+    ObjectId,
+    /// Number of bounces a path traced path survives, as a heatmap from blue at none to red at
+    /// `max_depth`.
+    Bounces { max_depth: u32 },
+}
+
+/// False colour views of the scene geometry and materials, for tracking down bad normals, uvs
+/// and the like.
+pub struct DebugView {
+    mode: DebugMode,
+}
+
+impl DebugView {
+    pub fn new(mode: DebugMode) -> Self {
+        Self { mode }
+    }
+
+    fn bounces(
+        &self,
+        camera_ray: &Ray,
+        scene: &Scene,
+        max_depth: u32,
+        sampler: &mut dyn Sampler,
+    ) -> u32 {
+        let mut throughput = Vec3::new(1.0, 1.0, 1.0);
+        let mut ray = *camera_ray;
+        for depth in 0..max_depth {
+            let Some(hit) = scene.hit(&ray, 0.001, f64::INFINITY) else {
+                return depth;
+            };
+            let wo = -ray.dir.normalize();
+            let Some(sample) = hit
+                .material
+                .sample(&hit, &wo, sampler.get_1d(), sampler.get_2d())
+            else {
+                return depth;
+            };
+            throughput *= sample.weight(&hit.normal);
+            ray = Ray::new(hit.point, sample.wi, ray.time);
+
+            // same russian roulette as the path tracer so path lengths match
+            if depth + 1 >= MIN_BOUNCES {
+                let survival = throughput.max_component().min(0.95);
+                if sampler.get_1d() >= survival {
+                    return depth + 1;
+                }
+                throughput = throughput / survival;
+            }
+        }
+        max_depth
+    }
+}
+
+impl Integrator for DebugView {
+    fn li(&self, ray: &Ray, scene: &Scene, sampler: &mut dyn Sampler) -> Vec3<f64> {
+        // the views of the first surface hit are black where nothing is
+        let first_hit = || scene.hit_object(ray, 0.001, f64::INFINITY);
+        match self.mode {
+            DebugMode::Normal => first_hit().map_or(Vec3::zero(), |(_, hit)| {
+                let outward = if hit.front_face {
+                    hit.normal
+                } else {
+                    -hit.normal
+                };
+                (outward.normalize() + Vec3::new(1.0, 1.0, 1.0)) * 0.5
+            }),
+            DebugMode::Depth { max_distance } => first_hit().map_or(Vec3::zero(), |(_, hit)| {
+                let distance = hit.t * ray.dir.length();
+                let grey = (distance / max_distance).clamp(0.0, 1.0);
+                Vec3::new(grey, grey, grey)
+            }),
+            DebugMode::Albedo => {
+                first_hit().map_or(Vec3::zero(), |(_, hit)| hit.material.albedo(&hit))
+            }
+            DebugMode::Uv => {
+                first_hit().map_or(Vec3::zero(), |(_, hit)| Vec3::new(hit.u, hit.v, 0.0))
+            }
+            DebugMode::ObjectId => first_hit().map_or(Vec3::zero(), |(index, _)| id_color(index)),
+            DebugMode::Bounces { max_depth } => {
+                let bounces = self.bounces(ray, scene, max_depth, sampler);
+                heatmap(bounces as f64 / max_depth.max(1) as f64)
+            }
+        }
+    }
+}
+
+/// Maps `t` in [0, 1] through blue, cyan, green and yellow to red.
+pub fn heatmap(t: f64) -> Vec3<f64> {
+    let t = t.clamp(0.0, 1.0);
+    Vec3::new(
+        (1.5 - (4.0 * t - 3.0).abs()).clamp(0.0, 1.0),
+        (1.5 - (4.0 * t - 2.0).abs()).clamp(0.0, 1.0),
+        (1.5 - (4.0 * t - 1.0).abs()).clamp(0.0, 1.0),
+    )
+}
+
+/// Bright, well separated colour for an id, stepping around the hue circle by the golden ratio.
+fn id_color(id: usize) -> Vec3<f64> {
+    let hue = (id as f64 * 0.618_033_988_749_895).fract() * 6.0;
+    let x = 1.0 - (hue % 2.0 - 1.0).abs();
+    match hue as u32 {
+        0 => Vec3::new(1.0, x, 0.0),
+        1 => Vec3::new(x, 1.0, 0.0),
+        2 => Vec3::new(0.0, 1.0, x),
+        3 => Vec3::new(0.0, x, 1.0),
+        4 => Vec3::new(x, 0.0, 1.0),
+        _ => Vec3::new(1.0, 0.0, x),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::integrator::tests::lit_scene;
+    use crate::sampler::IndependentSampler;
+    use crate::vec3::Point3;
+
+    fn down_ray() -> Ray {
+        Ray::new(Point3::new(0.0, 2.0, 0.0), Vec3::new(0.0, -1.0, 0.0), 0.0)
+    }
+
+    #[test]
+    fn test_normal_and_depth() {
+        let scene = lit_scene();
+        let mut sampler = IndependentSampler::new(0);
+        let normal = DebugView::new(DebugMode::Normal).li(&down_ray(), &scene, &mut sampler);
+        assert!((normal - Vec3::new(0.5, 1.0, 0.5)).length() < 1e-9);
+        let depth = DebugView::new(DebugMode::Depth { max_distance: 4.0 }).li(
+            &down_ray(),
+            &scene,
+            &mut sampler,
+        );
+        assert!((depth.x - 0.5).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_albedo_and_object_id() {
+        let scene = lit_scene();
+        let mut sampler = IndependentSampler::new(0);
+        let albedo = DebugView::new(DebugMode::Albedo).li(&down_ray(), &scene, &mut sampler);
+        assert_eq!(albedo, Vec3::new(0.5, 0.5, 0.5));
+        let ground = DebugView::new(DebugMode::ObjectId).li(&down_ray(), &scene, &mut sampler);
+        let up = Ray::new(Point3::new(0.0, 2.0, 0.0), Vec3::new(0.0, 1.0, 0.0), 0.0);
+        let light = DebugView::new(DebugMode::ObjectId).li(&up, &scene, &mut sampler);
+        assert_ne!(ground, light);
+    }
+
+    #[test]
+    fn test_heatmap_ends() {
+        assert_eq!(heatmap(0.0), Vec3::new(0.0, 0.0, 0.5));
+        assert_eq!(heatmap(1.0), Vec3::new(0.5, 0.0, 0.0));
+        assert_eq!(heatmap(0.5), Vec3::new(0.5, 1.0, 0.5));
+    }
+}
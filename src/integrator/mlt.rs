@@ -0,0 +1,391 @@
+use crate::film::{Film, Filter};
+use crate::integrator::{Bdpt, Integrator, Splat};
+use crate::objects::Camera;
+use crate::sampler::{hash, Sampler};
+use crate::scene::Scene;
+use crate::vec3::Vec3;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rayon::prelude::*;
+
+// chains run one after another on a thread and add to the same film
+const CHAIN_GROUP: usize = 16;
+
+/// Primary sample space Metropolis light transport (Kelemen et al. 2002). Paths are found by
+/// bidirectional path tracing driven by an `MltSampler`, so a path is a point in the unit
+/// hypercube of random numbers it consumed. Markov chains explore that space with small
+/// perturbations and fresh large steps, spending their samples where the image is bright,
+/// which finds light that only reaches the camera through narrow gaps.
+///
+/// The image brightness is set by a bootstrap pass of independent paths, and chains start from
+/// paths picked among them in proportion to their contribution.
+pub struct Mlt {
+    camera: Camera,
+    bdpt: Bdpt,
+    bootstrap_samples: usize,
+    chains: usize,
+    seed: u64,
+    /// Chance of each mutation replacing the whole path rather than perturbing it.
+    large_step_probability: f64,
+}
+
+impl Mlt {
+    pub fn new(
+        camera: Camera,
+        max_depth: u32,
+        bootstrap_samples: usize,
+        chains: usize,
+        seed: u64,
+    ) -> Self {
+        Self {
+            camera,
+            bdpt: Bdpt::new(camera, max_depth),
+            bootstrap_samples,
+            chains,
+            seed,
+            large_step_probability: 0.3,
+        }
+    }
+
+    /// Traces the path `sampler` describes, replacing `splats` with its contributions to the
+    /// film, and returns their total luminance in the scene's working space.
+    fn contributions(
+        &self,
+        scene: &Scene,
+        sampler: &mut MltSampler,
+        splats: &mut Vec<Splat>,
+    ) -> f64 {
+        splats.clear();
+        let film = sampler.get_2d();
+        let ray = self.camera.get_ray(film.0, film.1, sampler);
+        let radiance = self.bdpt.li_splat(&ray, scene, sampler, splats);
+        splats.push(Splat { film, radiance });
+        let luminance: f64 = splats
+            .iter()
+            .map(|splat| scene.working.luminance(splat.radiance))
+            .sum();
+        if luminance.is_finite() && luminance > 0.0 {
+            luminance
+        } else {
+            0.0
+        }
+    }
+
+    /// Renders a `width` by `height` image with an average of `mutations_per_pixel` mutations
+    /// per pixel, splatting them through `filter`, and calling `on_chain` as each Markov chain
+    /// finishes. The result is in columns from left to right, each from the top row down, as
+    /// `write_image` takes it.
+    pub fn render(
+        &self,
+        scene: &Scene,
+        width: u32,
+        height: u32,
+        filter: Filter,
+        mutations_per_pixel: u32,
+        on_chain: impl Fn() + Sync,
+    ) -> Vec<Vec<Vec3<f64>>> {
+        let pixel_count = (width * height) as usize;
+        let seed = |index: usize| hash(&[self.seed, index as u64]);
+
+        // bootstrap: the mean contribution of independent paths normalises the image
+        let weights: Vec<f64> = (0..self.bootstrap_samples)
+            .into_par_iter()
+            .map_init(Vec::new, |splats, index| {
+                let mut sampler = MltSampler::new(seed(index), self.large_step_probability);
+                self.contributions(scene, &mut sampler, splats)
+            })
+            .collect();
+        let cdf: Vec<f64> = weights
+            .iter()
+            .scan(0.0, |sum, weight| {
+                *sum += weight;
+                Some(*sum)
+            })
+            .collect();
+        let total = cdf.last().copied().unwrap_or(0.0);
+        if total == 0.0 {
+            return vec![vec![Vec3::zero(); height as usize]; width as usize];
+        }
+        let brightness = total / self.bootstrap_samples as f64;
+
+        let mutations_per_chain =
+            (mutations_per_pixel as usize * pixel_count).div_ceil(self.chains.max(1));
+        // every mutation splats a total luminance of one, which the bootstrap rescales
+        let scale =
+            brightness * pixel_count as f64 / (mutations_per_chain * self.chains.max(1)) as f64;
+        // chains are summed in fixed groups so the image doesn't depend on thread scheduling,
+        // and as many groups run at a time as there are threads to keep their films in memory
+        let render_group = |group: usize| {
+            let mut film = Film::new(width, height, filter);
+            for chain in group * CHAIN_GROUP..self.chains.min((group + 1) * CHAIN_GROUP) {
+                let mut rng = StdRng::seed_from_u64(seed(self.bootstrap_samples + chain));
+                let mut splat = |splats: &[Splat], weight: f64| {
+                    for splat in splats {
+                        film.add_splat(splat.film, splat.radiance * (weight * scale));
+                    }
+                };
+
+                // replay a bootstrap path picked in proportion to its contribution
+                let start = cdf
+                    .partition_point(|&sum| sum <= rng.gen::<f64>() * total)
+                    .min(weights.len() - 1);
+                let mut sampler = MltSampler::new(seed(start), self.large_step_probability);
+                let mut current = Vec::new();
+                let mut current_f = self.contributions(scene, &mut sampler, &mut current);
+                let mut proposed = Vec::new();
+
+                for _ in 0..mutations_per_chain {
+                    sampler.start_iteration();
+                    let proposed_f = self.contributions(scene, &mut sampler, &mut proposed);
+                    let accept = if current_f > 0.0 {
+                        (proposed_f / current_f).min(1.0)
+                    } else {
+                        1.0
+                    };
+                    // both states contribute in proportion to their chance of being next,
+                    // which wastes none of the rejected paths
+                    if accept > 0.0 {
+                        splat(&proposed, accept / proposed_f);
+                    }
+                    if accept < 1.0 {
+                        splat(&current, (1.0 - accept) / current_f);
+                    }
+                    if rng.gen::<f64>() < accept {
+                        sampler.accept();
+                        std::mem::swap(&mut current, &mut proposed);
+                        current_f = proposed_f;
+                    } else {
+                        sampler.reject();
+                    }
+                }
+                on_chain();
+            }
+            film
+        };
+        let groups = self.chains.div_ceil(CHAIN_GROUP);
+        let batch = rayon::current_num_threads();
+        let mut film = Film::new(width, height, filter);
+        for first in (0..groups).step_by(batch) {
+            let films: Vec<Film> = (first..groups.min(first + batch))
+                .into_par_iter()
+                .map(render_group)
+                .collect();
+            for group in &films {
+                film.merge(group);
+            }
+        }
+        film.image().pixels
+    }
+}
+
+/// A coordinate of primary sample space with what is needed to undo its latest mutation.
+#[derive(Debug, Copy, Clone, Default)]
+struct PrimarySample {
+    value: f64,
+    /// Iteration the value was last brought up to date in.
+    modified: u64,
+    backup: f64,
+    modified_backup: u64,
+}
+
+/// Replayable sampler for `Mlt`. Each dimension drawn is the next coordinate of a point in
+/// primary sample space, so an integrator drawing from it traces the path that point
+/// describes, and the same seed replays the same first path. Coordinates are mutated lazily
+/// when they are next drawn, which keeps paths of any length cheap.
+pub struct MltSampler {
+    rng: StdRng,
+    large_step_probability: f64,
+    samples: Vec<PrimarySample>,
+    index: usize,
+    iteration: u64,
+    large_step: bool,
+    last_large_step: u64,
+}
+
+impl MltSampler {
+    /// A sampler whose first iteration is a large step.
+    pub fn new(seed: u64, large_step_probability: f64) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(seed),
+            large_step_probability,
+            samples: Vec::new(),
+            index: 0,
+            iteration: 0,
+            large_step: true,
+            last_large_step: 0,
+        }
+    }
+
+    /// Starts proposing a mutation of the current point, drawing again from its first
+    /// coordinate.
+    pub fn start_iteration(&mut self) {
+        self.iteration += 1;
+        self.large_step = self.rng.gen::<f64>() < self.large_step_probability;
+        self.index = 0;
+    }
+
+    /// Keeps the proposed point.
+    pub fn accept(&mut self) {
+        if self.large_step {
+            self.last_large_step = self.iteration;
+        }
+    }
+
+    /// Returns to the point before the latest `start_iteration`.
+    pub fn reject(&mut self) {
+        for sample in &mut self.samples {
+            if sample.modified == self.iteration {
+                sample.value = sample.backup;
+                sample.modified = sample.modified_backup;
+            }
+        }
+        self.iteration -= 1;
+    }
+
+    fn next(&mut self) -> f64 {
+        if self.index == self.samples.len() {
+            // a coordinate no path has used yet, uniform as if drawn by the last large step
+            self.samples.push(PrimarySample {
+                value: self.rng.gen(),
+                modified: self.last_large_step,
+                ..Default::default()
+            });
+        }
+        let sample = &mut self.samples[self.index];
+        self.index += 1;
+
+        // apply the large step or small steps the coordinate missed while it was unused
+        if sample.modified < self.last_large_step {
+            sample.value = self.rng.gen();
+            sample.modified = self.last_large_step;
+        }
+        sample.backup = sample.value;
+        sample.modified_backup = sample.modified;
+        if self.large_step {
+            sample.value = self.rng.gen();
+        } else {
+            for _ in sample.modified..self.iteration {
+                sample.value = mutate(sample.value, self.rng.gen());
+            }
+        }
+        sample.modified = self.iteration;
+        sample.value
+    }
+}
+
+/// Kelemen's small step: a symmetric perturbation between 1/1024 and 1/64, with exponentially
+/// more small ones than large ones, wrapping around the unit interval.
+fn mutate(value: f64, u: f64) -> f64 {
+    const S1: f64 = 1.0 / 1024.0;
+    const S2: f64 = 1.0 / 64.0;
+    let (u, sign) = if u < 0.5 {
+        (2.0 * u, 1.0)
+    } else {
+        (2.0 * u - 1.0, -1.0)
+    };
+    let value = value + sign * S2 * (-(S2 / S1).ln() * u).exp();
+    let value = value - value.floor();
+    // rounding can land a tiny negative value on one
+    if value < 1.0 {
+        value
+    } else {
+        0.0
+    }
+}
+
+impl Sampler for MltSampler {
+    /// Does nothing, as the point in primary sample space picks the pixel.
+    fn start_pixel_sample(&mut self, _pixel: (u32, u32), _index: u32) {}
+
+    fn get_1d(&mut self) -> f64 {
+        self.next()
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.next(), self.next())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::integrator::tests::lit_scene;
+    use crate::integrator::PathTracer;
+    use crate::sampler::IndependentSampler;
+    use crate::vec3::Point3;
+
+    fn draw(sampler: &mut MltSampler, n: usize) -> Vec<f64> {
+        (0..n).map(|_| sampler.get_1d()).collect()
+    }
+
+    #[test]
+    fn test_same_seed_replays_path() {
+        let first = draw(&mut MltSampler::new(7, 0.3), 20);
+        let second = draw(&mut MltSampler::new(7, 0.3), 20);
+        assert_eq!(first, second);
+        assert!(first.iter().all(|u| (0.0..1.0).contains(u)));
+    }
+
+    #[test]
+    fn test_reject_restores_point() {
+        let mut sampler = MltSampler::new(3, 0.3);
+        let start = draw(&mut sampler, 10);
+        for _ in 0..5 {
+            sampler.start_iteration();
+            let proposed = draw(&mut sampler, 12);
+            assert_ne!(proposed[..10], start[..]);
+            sampler.reject();
+        }
+        // a small step from the start point stays close to it
+        sampler.large_step_probability = 0.0;
+        sampler.start_iteration();
+        let proposed = draw(&mut sampler, 10);
+        for (a, b) in start.iter().zip(&proposed) {
+            let distance = (a - b).abs();
+            assert!(distance.min(1.0 - distance) <= 1.0 / 64.0 + 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_brightness_agrees_with_path_tracer() {
+        let scene = lit_scene();
+        let camera = Camera::new(
+            Point3::new(0.0, 3.0, 0.0),
+            Point3::zero(),
+            Vec3::new(0.0, 0.0, -1.0),
+            60.0,
+            1.0,
+            0.0,
+            3.0,
+            0.0,
+            1.0,
+        );
+        let n = 4;
+        let image = Mlt::new(camera, 8, 20000, 16, 0).render(
+            &scene,
+            n,
+            n,
+            Filter::Box { radius: 0.5 },
+            64,
+            || {},
+        );
+        let mlt = image.iter().flatten().map(|pixel| pixel.x).sum::<f64>() / (n * n) as f64;
+
+        let mut sampler = IndependentSampler::new(0);
+        let samples = 20000;
+        let path = (0..samples)
+            .map(|_| {
+                let ray = camera.get_ray(sampler.get_1d(), sampler.get_1d(), &mut sampler);
+                PathTracer::new(8).li(&ray, &scene, &mut sampler).x
+            })
+            .sum::<f64>()
+            / samples as f64;
+        assert!(path > 0.0);
+        assert!(
+            (mlt - path).abs() < 0.05 * path,
+            "mlt {} path {}",
+            mlt,
+            path
+        );
+    }
+}
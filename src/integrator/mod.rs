@@ -0,0 +1,133 @@
+pub mod ambient_occlusion;
+pub mod bdpt;
+pub mod debug;
+pub mod mlt;
+pub mod path;
+pub mod photon;
+pub mod recursive;
+pub mod spectral;
+pub mod whitted;
+
+pub use ambient_occlusion::AmbientOcclusion;
+pub use bdpt::Bdpt;
+pub use debug::{DebugMode, DebugView};
+pub use mlt::{Mlt, MltSampler};
+pub use path::{PathTracer, RadianceClamp};
+pub use photon::{PhotonMapper, Sppm};
+pub use recursive::Recursive;
+pub use spectral::SpectralPathTracer;
+pub use whitted::Whitted;
+
+use crate::film::LightingSample;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::scene::Scene;
+use crate::vec3::Vec3;
+
+/// Radiance an integrator adds to the pixel at a film position other than the one it was asked
+/// about, with film positions as in `Camera`. Splats are scaled like regular samples, by one
+/// over the samples per pixel.
+#[derive(Debug, Copy, Clone)]
+pub struct Splat {
+    pub film: (f64, f64),
+    pub radiance: Vec3<f64>,
+}
+
+/// A light transport algorithm: estimates the radiance arriving at the camera along a ray.
+/// All randomness is drawn from `sampler` so callers decide how samples are generated.
+pub trait Integrator: Sync {
+    fn li(&self, ray: &Ray, scene: &Scene, sampler: &mut dyn Sampler) -> Vec3<f64>;
+
+    /// Like `li`, for integrators that also trace paths from the lights and contribute to
+    /// whichever pixel those reach, pushing such contributions onto `splats`.
+    fn li_splat(
+        &self,
+        ray: &Ray,
+        scene: &Scene,
+        sampler: &mut dyn Sampler,
+        _splats: &mut Vec<Splat>,
+    ) -> Vec3<f64> {
+        self.li(ray, scene, sampler)
+    }
+
+    /// Like `li_splat`, also splitting the radiance by how it reached the camera into
+    /// `lighting` for the lighting AOVs. Integrators that can't, see `splits_lighting`, leave it
+    /// untouched.
+    fn li_aovs(
+        &self,
+        ray: &Ray,
+        scene: &Scene,
+        sampler: &mut dyn Sampler,
+        splats: &mut Vec<Splat>,
+        _lighting: &mut LightingSample,
+    ) -> Vec3<f64> {
+        self.li_splat(ray, scene, sampler, splats)
+    }
+
+    fn splits_lighting(&self) -> bool {
+        false
+    }
+}
+
+/// Weight of a sample from the strategy with density `f_pdf` when it is combined with a
+/// strategy of density `g_pdf` (Veach's power heuristic with beta = 2).
+pub fn power_heuristic(f_pdf: f64, g_pdf: f64) -> f64 {
+    let f2 = f_pdf * f_pdf;
+    let g2 = g_pdf * g_pdf;
+    if f2.is_infinite() {
+        return 1.0;
+    }
+    if f2 + g2 == 0.0 {
+        return 0.0;
+    }
+    f2 / (f2 + g2)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::material::{DiffuseLight, Lambertian};
+    use crate::objects::hittable::HittableList;
+    use crate::objects::{Quad, Sphere};
+    use crate::sampler::IndependentSampler;
+    use crate::scene::Background;
+    use crate::vec3::Point3;
+
+    /// Grey ground below a downward facing square light, under a black sky.
+    pub(crate) fn lit_scene() -> Scene {
+        let mut world = HittableList::new();
+        world
+            .push(Sphere::new(
+                Point3::new(0.0, -1000.0, 0.0),
+                1000.0,
+                Lambertian::new(Vec3::new(0.5, 0.5, 0.5)),
+            ))
+            .push(Quad::new(
+                Point3::new(-1.0, 4.0, -1.0),
+                Vec3::new(2.0, 0.0, 0.0),
+                Vec3::new(0.0, 0.0, 2.0),
+                DiffuseLight::new(Vec3::new(4.0, 4.0, 4.0)),
+            ));
+        Scene::new(world, Background::Color(Vec3::zero()))
+    }
+
+    /// Mean radiance of `n` estimates along a ray from above pointing down at the ground.
+    pub(crate) fn mean_ground_radiance(integrator: &dyn Integrator, n: u32) -> Vec3<f64> {
+        let scene = lit_scene();
+        let ray = Ray::new(Point3::new(0.5, 2.0, 3.0), Vec3::new(0.0, -2.0, -3.0), 0.0);
+        let mut sampler = IndependentSampler::new(0);
+        let mut sum = Vec3::zero();
+        for _ in 0..n {
+            sum += integrator.li(&ray, &scene, &mut sampler);
+        }
+        sum / n as f64
+    }
+
+    #[test]
+    fn test_power_heuristic() {
+        assert_eq!(power_heuristic(1.0, 1.0), 0.5);
+        assert_eq!(power_heuristic(0.0, 0.0), 0.0);
+        assert_eq!(power_heuristic(f64::INFINITY, 1.0), 1.0);
+        assert!((power_heuristic(2.0, 1.0) - 0.8).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,307 @@
+use crate::film::LightingSample;
+use crate::integrator::{power_heuristic, Integrator, Splat};
+use crate::objects::hittable::HitRecord;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::scene::Scene;
+use crate::spectrum::SampledSpectrum;
+use crate::vec3::Vec3;
+
+// bounces before russian roulette may end a path
+pub(super) const MIN_BOUNCES: u32 = 3;
+// share of diffuse scattering above which a bounce counts as diffuse for path regularisation
+pub(super) const DIFFUSE_BOUNCE: f64 = 0.5;
+
+/// Most radiance a single sample may add through direct and through indirect lighting, brighter
+/// contributions being scaled down to it. Clamping loses a little energy but takes out most
+/// fireflies. Light seen directly by the camera is never clamped.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RadianceClamp {
+    pub direct: f64,
+    pub indirect: f64,
+}
+
+impl RadianceClamp {
+    pub const NONE: Self = Self {
+        direct: f64::INFINITY,
+        indirect: f64::INFINITY,
+    };
+
+    /// `radiance` that bounced off `bounces` surfaces on its way to the camera, scaled so that
+    /// no component is over the limit and keeping its hue.
+    pub fn apply(&self, radiance: Vec3<f64>, bounces: u32) -> Vec3<f64> {
+        radiance * self.scale(radiance.max_component(), bounces)
+    }
+
+    /// Like `apply`, for the radiance of a spectral path at its wavelengths.
+    pub fn apply_spectrum(&self, radiance: SampledSpectrum, bounces: u32) -> SampledSpectrum {
+        radiance * self.scale(radiance.max_component(), bounces)
+    }
+
+    fn scale(&self, max: f64, bounces: u32) -> f64 {
+        let limit = match bounces {
+            0 => return 1.0,
+            1 => self.direct,
+            _ => self.indirect,
+        };
+        if max > limit {
+            limit / max
+        } else {
+            1.0
+        }
+    }
+}
+
+/// Unidirectional path tracer combining BSDF and light sampling with multiple importance
+/// sampling, ending paths with russian roulette or at `max_depth` bounces.
+pub struct PathTracer {
+    max_depth: u32,
+    clamp: RadianceClamp,
+    regularization: f64,
+}
+
+impl PathTracer {
+    pub fn new(max_depth: u32) -> Self {
+        Self {
+            max_depth,
+            clamp: RadianceClamp::NONE,
+            regularization: 0.0,
+        }
+    }
+
+    pub fn with_clamp(mut self, clamp: RadianceClamp) -> Self {
+        self.clamp = clamp;
+        self
+    }
+
+    /// Path regularisation: after a diffuse bounce, surfaces are made at least as rough as
+    /// `roughness`, see `Material::regularized`. Caustics, which a diffuse surface only finds
+    /// by chance through a smooth one, come out blurred but without fireflies. Zero turns it off.
+    pub fn with_regularization(mut self, roughness: f64) -> Self {
+        self.regularization = roughness;
+        self
+    }
+
+    /// Radiance along `camera_ray`, also split up into `lighting` when given one.
+    fn trace(
+        &self,
+        camera_ray: &Ray,
+        scene: &Scene,
+        sampler: &mut dyn Sampler,
+        mut lighting: Option<&mut LightingSample>,
+    ) -> Vec3<f64> {
+        let mut radiance = Vec3::zero();
+        let mut throughput = Vec3::new(1.0, 1.0, 1.0);
+        let mut ray = *camera_ray;
+        // camera rays and delta bounces see emission at full weight as light sampling can't
+        // find it
+        let mut specular_bounce = true;
+        let mut bsdf_pdf = 0.0;
+        // of the first surface hit, by which lighting is split
+        let mut diffuse_fraction = 0.0;
+        let mut after_diffuse = false;
+
+        for depth in 0..self.max_depth {
+            // t_min 0.001 to ignore hits very near to 0 to avoid shadow acne
+            let found = scene.hit_object(&ray, 0.001, f64::INFINITY);
+            let wo = -ray.dir.normalize();
+            if let (0, Some((_, hit))) = (depth, &found) {
+                diffuse_fraction = hit.material.diffuse_fraction(hit, &wo);
+            }
+            // light that bounced off `bounces` surfaces on its way to the camera
+            let mut add = |contribution: Vec3<f64>, bounces: u32, light: Option<usize>| {
+                let contribution = self.clamp.apply(contribution, bounces);
+                radiance += contribution;
+                if let Some(lighting) = lighting.as_deref_mut() {
+                    let group = light.and_then(|light| scene.light_groups.group(light));
+                    lighting.add(contribution, bounces, diffuse_fraction, group);
+                }
+            };
+
+            let Some((object, hit)) = found else {
+                add(throughput * scene.background.color(&ray.dir), depth, None);
+                break;
+            };
+
+            let emitted = hit.material.emitted(&hit);
+            if !emitted.near_zero() {
+                let weight = if specular_bounce {
+                    1.0
+                } else {
+                    power_heuristic(bsdf_pdf, scene.light_pdf(&ray.orig, &ray.dir))
+                };
+                add(throughput * emitted * weight, depth, Some(object));
+            }
+
+            let regularized = if after_diffuse && self.regularization > 0.0 {
+                hit.material.regularized(self.regularization)
+            } else {
+                None
+            };
+            let hit = match &regularized {
+                Some(material) => HitRecord {
+                    material: material.as_ref(),
+                    ..hit
+                },
+                None => hit,
+            };
+
+            if !hit.material.is_delta() {
+                if let Some((light, direct)) = direct_lighting(scene, &hit, &wo, ray.time, sampler)
+                {
+                    add(throughput * direct, depth + 1, Some(light));
+                }
+            }
+
+            let Some(sample) = hit
+                .material
+                .sample(&hit, &wo, sampler.get_1d(), sampler.get_2d())
+            else {
+                break;
+            };
+            throughput *= sample.weight(&hit.normal);
+            if self.regularization > 0.0 {
+                after_diffuse |= hit.material.diffuse_fraction(&hit, &wo) >= DIFFUSE_BOUNCE;
+            }
+            specular_bounce = sample.delta;
+            bsdf_pdf = sample.pdf;
+            ray = Ray::new(hit.point, sample.wi, ray.time);
+
+            // russian roulette, survivors are boosted to keep the estimate unbiased
+            if depth + 1 >= MIN_BOUNCES {
+                let survival = throughput.max_component().min(0.95);
+                if sampler.get_1d() >= survival {
+                    break;
+                }
+                throughput = throughput / survival;
+            }
+        }
+
+        radiance
+    }
+}
+
+/// Direct lighting at a hit from one light sample and a shadow ray, weighted against the chance
+/// of the BSDF sample finding the same light, along with the index of the light sampled.
+pub fn direct_lighting(
+    scene: &Scene,
+    hit: &HitRecord,
+    wo: &Vec3<f64>,
+    time: f64,
+    sampler: &mut dyn Sampler,
+) -> Option<(usize, Vec3<f64>)> {
+    let (index, light, light_pdf) =
+        scene.sample_light(&hit.point, sampler.get_1d(), sampler.get_2d())?;
+    let wi = (light.point - hit.point).normalize();
+    let f = hit.material.eval(hit, wo, &wi);
+    if f.near_zero() || !scene.unoccluded(&hit.point, &light.point, time) {
+        return None;
+    }
+    let weight = power_heuristic(light_pdf, hit.material.pdf(hit, wo, &wi));
+    Some((
+        index,
+        f * light.material.emitted(&light) * (wi.dot(&hit.normal).abs() * weight / light_pdf),
+    ))
+}
+
+impl Integrator for PathTracer {
+    fn li(&self, ray: &Ray, scene: &Scene, sampler: &mut dyn Sampler) -> Vec3<f64> {
+        self.trace(ray, scene, sampler, None)
+    }
+
+    fn li_aovs(
+        &self,
+        ray: &Ray,
+        scene: &Scene,
+        sampler: &mut dyn Sampler,
+        _splats: &mut Vec<Splat>,
+        lighting: &mut LightingSample,
+    ) -> Vec3<f64> {
+        self.trace(ray, scene, sampler, Some(lighting))
+    }
+
+    fn splits_lighting(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::integrator::tests::{lit_scene, mean_ground_radiance};
+    use crate::integrator::Recursive;
+    use crate::sampler::IndependentSampler;
+    use crate::vec3::Point3;
+
+    #[test]
+    fn test_agrees_with_recursive_tracer() {
+        let path = mean_ground_radiance(&PathTracer::new(8), 2000);
+        let recursive = mean_ground_radiance(&Recursive::new(8), 20000);
+        assert!(path.x > 0.0);
+        assert!(
+            (path.x - recursive.x).abs() < 0.1 * path.x,
+            "path {} recursive {}",
+            path.x,
+            recursive.x
+        );
+    }
+
+    #[test]
+    fn test_clamp_limits_each_sample() {
+        let clamp = RadianceClamp {
+            direct: 1.0,
+            indirect: 0.5,
+        };
+        assert_eq!(
+            clamp.apply(Vec3::new(4.0, 2.0, 0.0), 1),
+            Vec3::new(1.0, 0.5, 0.0)
+        );
+        assert_eq!(
+            clamp.apply(Vec3::new(4.0, 2.0, 0.0), 2),
+            Vec3::new(0.5, 0.25, 0.0)
+        );
+        assert_eq!(
+            clamp.apply(Vec3::new(4.0, 2.0, 0.0), 0),
+            Vec3::new(4.0, 2.0, 0.0)
+        );
+
+        let scene = lit_scene();
+        let ray = Ray::new(Point3::new(0.5, 2.0, 3.0), Vec3::new(0.0, -2.0, -3.0), 0.0);
+        let tracer = PathTracer::new(8).with_clamp(RadianceClamp {
+            direct: 0.01,
+            indirect: 0.01,
+        });
+        let mut sampler = IndependentSampler::new(0);
+        // the light is reached by a light sample and by a BSDF sample, each clamped
+        for _ in 0..200 {
+            assert!(tracer.li(&ray, &scene, &mut sampler).max_component() <= 0.02 + 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_lighting_adds_up_to_radiance() {
+        let scene = lit_scene();
+        let ray = Ray::new(Point3::new(0.5, 2.0, 3.0), Vec3::new(0.0, -2.0, -3.0), 0.0);
+        let tracer = PathTracer::new(8);
+        let mut sampler = IndependentSampler::new(0);
+        let mut direct = Vec3::zero();
+        for _ in 0..200 {
+            let mut lighting = LightingSample::new(1);
+            let radiance =
+                tracer.li_aovs(&ray, &scene, &mut sampler, &mut Vec::new(), &mut lighting);
+            let parts = lighting.emission
+                + lighting.diffuse_direct
+                + lighting.diffuse_indirect
+                + lighting.specular_direct
+                + lighting.specular_indirect;
+            assert!((parts - radiance).length() < 1e-9);
+            // the sky is black, so all of it is from the one light
+            assert!((lighting.light_groups[0] - radiance).length() < 1e-9);
+            // the ground is lambertian and nothing else reflects
+            assert_eq!(lighting.specular_direct, Vec3::zero());
+            assert_eq!(lighting.diffuse_indirect, Vec3::zero());
+            direct += lighting.diffuse_direct;
+        }
+        assert!(direct.x > 0.0);
+    }
+}
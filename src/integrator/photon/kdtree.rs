@@ -0,0 +1,130 @@
+use crate::vec3::Point3;
+
+/// Static kd-tree over points, stored as an implicit balanced tree: each node is the median of
+/// its slice along the axis it splits, with its children in the halves either side.
+pub struct KdTree<T> {
+    items: Vec<([f64; 3], T)>,
+    axes: Vec<u8>,
+}
+
+impl<T> KdTree<T> {
+    pub fn new(items: impl IntoIterator<Item = (Point3<f64>, T)>) -> Self {
+        let mut items: Vec<([f64; 3], T)> = items
+            .into_iter()
+            .map(|(point, item)| (<[f64; 3]>::from(point), item))
+            .collect();
+        let mut axes = vec![0; items.len()];
+        build(&mut items, &mut axes);
+        Self { items, axes }
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Calls `visit` with every item within `radius` of `center` and its squared distance.
+    pub fn for_each_within(
+        &self,
+        center: &Point3<f64>,
+        radius: f64,
+        mut visit: impl FnMut(&T, f64),
+    ) {
+        let center = <[f64; 3]>::from(*center);
+        self.query(0, self.items.len(), &center, radius * radius, &mut visit);
+    }
+
+    fn query(
+        &self,
+        lo: usize,
+        hi: usize,
+        center: &[f64; 3],
+        radius2: f64,
+        visit: &mut impl FnMut(&T, f64),
+    ) {
+        if lo >= hi {
+            return;
+        }
+        let mid = lo + (hi - lo) / 2;
+        let (point, item) = &self.items[mid];
+        let dist2: f64 = (0..3).map(|i| (center[i] - point[i]).powi(2)).sum();
+        if dist2 <= radius2 {
+            visit(item, dist2);
+        }
+        let axis = self.axes[mid] as usize;
+        let d = center[axis] - point[axis];
+        let (near, far) = if d <= 0.0 {
+            ((lo, mid), (mid + 1, hi))
+        } else {
+            ((mid + 1, hi), (lo, mid))
+        };
+        self.query(near.0, near.1, center, radius2, visit);
+        if d * d <= radius2 {
+            self.query(far.0, far.1, center, radius2, visit);
+        }
+    }
+}
+
+/// Arranges a slice into the implicit tree, splitting along its widest axis at each level.
+fn build<T>(items: &mut [([f64; 3], T)], axes: &mut [u8]) {
+    if items.len() <= 1 {
+        return;
+    }
+    let mut min = [f64::INFINITY; 3];
+    let mut max = [f64::NEG_INFINITY; 3];
+    for (point, _) in items.iter() {
+        for i in 0..3 {
+            min[i] = min[i].min(point[i]);
+            max[i] = max[i].max(point[i]);
+        }
+    }
+    let axis = (0..3)
+        .max_by(|&a, &b| (max[a] - min[a]).total_cmp(&(max[b] - min[b])))
+        .unwrap();
+
+    let mid = items.len() / 2;
+    items.select_nth_unstable_by(mid, |a, b| a.0[axis].total_cmp(&b.0[axis]));
+    axes[mid] = axis as u8;
+    let (left, right) = items.split_at_mut(mid);
+    let (left_axes, right_axes) = axes.split_at_mut(mid);
+    build(left, left_axes);
+    build(&mut right[1..], &mut right_axes[1..]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn test_matches_brute_force() {
+        let mut rng = rand::thread_rng();
+        let points: Vec<Point3<f64>> = (0..500)
+            .map(|_| Point3::new(rng.gen(), rng.gen(), rng.gen()))
+            .collect();
+        let tree = KdTree::new(points.iter().enumerate().map(|(i, p)| (*p, i)));
+        assert_eq!(tree.len(), 500);
+
+        for _ in 0..20 {
+            let center = Point3::new(rng.gen(), rng.gen(), rng.gen());
+            let radius = 0.2;
+            let mut found = Vec::new();
+            tree.for_each_within(&center, radius, |&i, _| found.push(i));
+            found.sort();
+            let expected: Vec<usize> = (0..points.len())
+                .filter(|&i| (points[i] - center).length() <= radius)
+                .collect();
+            assert_eq!(found, expected);
+        }
+    }
+
+    #[test]
+    fn test_empty() {
+        let tree: KdTree<()> = KdTree::new(Vec::new());
+        assert!(tree.is_empty());
+        tree.for_each_within(&Point3::zero(), 1.0, |_, _| panic!("nothing to visit"));
+    }
+}
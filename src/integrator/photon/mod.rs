@@ -0,0 +1,320 @@
+pub mod kdtree;
+pub mod sppm;
+
+pub use kdtree::KdTree;
+pub use sppm::Sppm;
+
+use crate::integrator::Integrator;
+use crate::objects::hittable::HitRecord;
+use crate::objects::Camera;
+use crate::ray::Ray;
+use crate::sampler::{hash, IndependentSampler, Sampler};
+use crate::scene::Scene;
+use crate::vec3::{Point3, Vec3};
+use rayon::prelude::*;
+use std::f64::consts::PI;
+
+// photon paths traced per parallel task
+const PHOTON_BATCH: usize = 4096;
+
+/// Light left on a non-delta surface by a path traced from a light.
+#[derive(Debug, Copy, Clone)]
+pub struct Photon {
+    /// Unit direction back towards where the photon came from.
+    pub wi: Vec3<f64>,
+    /// Normal of the surface on the side the photon arrived from.
+    pub normal: Vec3<f64>,
+    /// Throughput of the path, to be divided by the number of paths emitted.
+    pub power: Vec3<f64>,
+}
+
+/// Traces `count` paths from the scene's lights, bouncing through every kind of surface, and
+/// returns the photons they leave on non-delta surfaces after their first bounce. Light
+/// arriving straight from a light is left to direct lighting. Each path is traced at a time
+/// within `camera`'s shutter interval, like camera rays. The photons only depend on `seed`.
+pub fn trace_photons(
+    scene: &Scene,
+    camera: &Camera,
+    count: usize,
+    max_depth: u32,
+    seed: u64,
+) -> Vec<(Point3<f64>, Photon)> {
+    (0..count.div_ceil(PHOTON_BATCH))
+        .into_par_iter()
+        .flat_map_iter(|batch| {
+            let mut sampler = IndependentSampler::new(hash(&[seed, batch as u64]));
+            let mut photons = Vec::new();
+            let paths = PHOTON_BATCH.min(count - batch * PHOTON_BATCH);
+            for _ in 0..paths {
+                trace_photon(scene, camera, max_depth, &mut sampler, &mut photons);
+            }
+            photons
+        })
+        .collect()
+}
+
+fn trace_photon(
+    scene: &Scene,
+    camera: &Camera,
+    max_depth: u32,
+    sampler: &mut dyn Sampler,
+    photons: &mut Vec<(Point3<f64>, Photon)>,
+) {
+    let time = camera.sample_time(sampler.get_1d());
+    let Some(emission) = scene.lights.sample_emission(
+        &scene.world,
+        sampler.get_1d(),
+        sampler.get_2d(),
+        sampler.get_2d(),
+    ) else {
+        return;
+    };
+    let mut power = emission.weight();
+    let mut ray = Ray::new(emission.hit.point, emission.dir, time);
+
+    for depth in 0..max_depth {
+        let Some(hit) = scene.hit(&ray, 0.001, f64::INFINITY) else {
+            return;
+        };
+        let wo = -ray.dir.normalize();
+        if depth > 0 && !hit.material.is_delta() {
+            photons.push((
+                hit.point,
+                Photon {
+                    wi: wo,
+                    normal: hit.normal,
+                    power,
+                },
+            ));
+        }
+
+        let Some(sample) = hit
+            .material
+            .sample(&hit, &wo, sampler.get_1d(), sampler.get_2d())
+        else {
+            return;
+        };
+        let scattered = power * sample.weight(&hit.normal);
+        // russian roulette keeping the power of surviving photons about the same
+        let survival = (scattered.max_component() / power.max_component()).min(1.0);
+        if sampler.get_1d() >= survival {
+            return;
+        }
+        power = scattered / survival;
+        ray = Ray::new(hit.point, sample.wi, ray.time);
+    }
+}
+
+/// Photons from one batch of light paths, in a kd-tree for density estimation.
+pub struct PhotonMap {
+    tree: KdTree<Photon>,
+    emitted: usize,
+}
+
+impl PhotonMap {
+    pub fn new(scene: &Scene, camera: &Camera, emitted: usize, max_depth: u32, seed: u64) -> Self {
+        Self {
+            tree: KdTree::new(trace_photons(scene, camera, emitted, max_depth, seed)),
+            emitted,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    /// Sum of the BSDF weighted power of the photons within `radius` of a hit, and how many
+    /// there were. Photons that landed on the other side of the surface are skipped.
+    pub fn gather(&self, hit: &HitRecord, wo: &Vec3<f64>, radius: f64) -> (Vec3<f64>, usize) {
+        let mut flux = Vec3::zero();
+        let mut count = 0;
+        self.tree.for_each_within(&hit.point, radius, |photon, _| {
+            if photon.normal.dot(&hit.normal) <= 0.0 {
+                return;
+            }
+            flux += hit.material.eval(hit, wo, &photon.wi) * photon.power;
+            count += 1;
+        });
+        (flux, count)
+    }
+
+    /// Radiance reflected towards `wo` estimated from the density of photons within `radius`.
+    pub fn estimate(&self, hit: &HitRecord, wo: &Vec3<f64>, radius: f64) -> Vec3<f64> {
+        let (flux, _) = self.gather(hit, wo, radius);
+        flux / (PI * radius * radius * self.emitted as f64)
+    }
+}
+
+/// Where a camera path first reaches a surface photons are left on.
+pub(super) struct VisiblePoint<'a> {
+    hit: HitRecord<'a>,
+    wo: Vec3<f64>,
+    /// Throughput of the camera path up to the point.
+    beta: Vec3<f64>,
+}
+
+/// Follows a camera ray through delta surfaces to the first other surface, adding the light
+/// seen on the way and the direct lighting there to `radiance`.
+fn visible_point<'a>(
+    camera_ray: &Ray,
+    scene: &'a Scene,
+    max_depth: u32,
+    sampler: &mut dyn Sampler,
+    radiance: &mut Vec3<f64>,
+) -> Option<VisiblePoint<'a>> {
+    let mut beta = Vec3::new(1.0, 1.0, 1.0);
+    let mut ray = *camera_ray;
+    for _ in 0..max_depth {
+        // t_min 0.001 to ignore hits very near to 0 to avoid shadow acne
+        let Some(hit) = scene.hit(&ray, 0.001, f64::INFINITY) else {
+            *radiance += beta * scene.background.color(&ray.dir);
+            return None;
+        };
+        let wo = -ray.dir.normalize();
+        // only camera rays and delta bounces get here, which light sampling can't find
+        *radiance += beta * hit.material.emitted(&hit);
+
+        if !hit.material.is_delta() {
+            *radiance += beta * direct_lighting(scene, &hit, &wo, ray.time, sampler);
+            return Some(VisiblePoint { hit, wo, beta });
+        }
+        let sample = hit
+            .material
+            .sample(&hit, &wo, sampler.get_1d(), sampler.get_2d())?;
+        beta *= sample.weight(&hit.normal);
+        ray = Ray::new(hit.point, sample.wi, ray.time);
+    }
+    None
+}
+
+/// Direct lighting from one light sample and a shadow ray. Nothing else finds the lights from
+/// a visible point, so there is no weighting against BSDF sampling.
+fn direct_lighting(
+    scene: &Scene,
+    hit: &HitRecord,
+    wo: &Vec3<f64>,
+    time: f64,
+    sampler: &mut dyn Sampler,
+) -> Vec3<f64> {
+    let Some((_, light, pdf)) = scene.sample_light(&hit.point, sampler.get_1d(), sampler.get_2d())
+    else {
+        return Vec3::zero();
+    };
+    let wi = (light.point - hit.point).normalize();
+    let f = hit.material.eval(hit, wo, &wi);
+    if f.near_zero() || !scene.unoccluded(&hit.point, &light.point, time) {
+        return Vec3::zero();
+    }
+    f * light.material.emitted(&light) * (wi.dot(&hit.normal).abs() / pdf)
+}
+
+/// Photon mapping with a single photon map: direct lighting is sampled, and everything that has
+/// bounced at least once, caustics through glass and metal included, comes from the density of
+/// photons within `radius` of the first non-delta surface a camera ray reaches.
+///
+/// Photons are only emitted by the scene's lights, which are all direct lighting samples, so the
+/// background lights nothing and is only seen directly and through delta surfaces.
+pub struct PhotonMapper {
+    map: PhotonMap,
+    radius: f64,
+    max_depth: u32,
+}
+
+impl PhotonMapper {
+    /// Traces `photons` light paths through `scene` up front, which must be the scene later
+    /// rendered through `camera`.
+    pub fn new(
+        scene: &Scene,
+        camera: &Camera,
+        photons: usize,
+        radius: f64,
+        max_depth: u32,
+        seed: u64,
+    ) -> Self {
+        Self {
+            map: PhotonMap::new(scene, camera, photons, max_depth, seed),
+            radius,
+            max_depth,
+        }
+    }
+}
+
+impl Integrator for PhotonMapper {
+    fn li(&self, ray: &Ray, scene: &Scene, sampler: &mut dyn Sampler) -> Vec3<f64> {
+        let mut radiance = Vec3::zero();
+        if let Some(point) = visible_point(ray, scene, self.max_depth, sampler, &mut radiance) {
+            radiance += point.beta * self.map.estimate(&point.hit, &point.wo, self.radius);
+        }
+        radiance
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::integrator::tests::{lit_scene, mean_ground_radiance};
+    use crate::material::{Dielectric, DiffuseLight, Lambertian};
+    use crate::objects::hittable::HittableList;
+    use crate::objects::{Quad, Sphere};
+    use crate::scene::Background;
+
+    fn camera() -> Camera {
+        Camera::new(
+            Point3::new(0.0, 3.0, 0.0),
+            Point3::zero(),
+            Vec3::new(0.0, 0.0, -1.0),
+            60.0,
+            1.0,
+            0.0,
+            3.0,
+            0.0,
+            1.0,
+        )
+    }
+
+    #[test]
+    fn test_direct_lighting_matches_path_tracer() {
+        // the ground in the lit scene only receives direct lighting, which photons don't carry
+        let mapper = PhotonMapper::new(&lit_scene(), &camera(), 10000, 0.1, 8, 0);
+        let direct = mean_ground_radiance(&mapper, 2000);
+        let path = mean_ground_radiance(&crate::integrator::PathTracer::new(8), 2000);
+        assert!((direct.x - path.x).abs() < 0.1 * path.x);
+    }
+
+    #[test]
+    fn test_glass_sphere_focuses_caustic() {
+        let mut world = HittableList::new();
+        world
+            .push(Sphere::new(
+                Point3::new(0.0, -1000.0, 0.0),
+                1000.0,
+                Lambertian::new(Vec3::new(0.5, 0.5, 0.5)),
+            ))
+            .push(Sphere::new(
+                Point3::new(0.0, 2.0, 0.0),
+                0.5,
+                Dielectric::new(1.5),
+            ))
+            .push(Quad::new(
+                Point3::new(-0.5, 6.0, -0.5),
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(0.0, 0.0, 1.0),
+                DiffuseLight::new(Vec3::new(10.0, 10.0, 10.0)),
+            ));
+        let scene = Scene::new(world, Background::Color(Vec3::zero()));
+        let map = PhotonMap::new(&scene, &camera(), 100_000, 8, 0);
+        assert!(!map.is_empty());
+
+        // more light is focused below the sphere than lands further out
+        let density = |x: f64| {
+            let ray = Ray::new(Point3::new(x, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0), 0.0);
+            let hit = scene.hit(&ray, 0.001, f64::INFINITY).unwrap();
+            map.estimate(&hit, &-ray.dir, 0.1).x
+        };
+        assert!(density(0.0) > 2.0 * density(3.0));
+    }
+}
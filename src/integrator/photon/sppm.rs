@@ -0,0 +1,167 @@
+use crate::integrator::photon::{visible_point, PhotonMap};
+use crate::objects::Camera;
+use crate::sampler::{hash, IndependentSampler, Sampler};
+use crate::scene::Scene;
+use crate::vec3::Vec3;
+use rayon::prelude::*;
+use std::f64::consts::PI;
+
+/// Stochastic progressive photon mapping (Hachisuka and Jensen 2009). Every pass finds a
+/// visible point per pixel and traces a new batch of photons, and each pixel's gathering radius
+/// shrinks as photons accumulate so the estimate converges as passes are added.
+///
+/// Like `PhotonMapper`, only the scene's lights emit photons and are sampled, so the background
+/// lights nothing.
+pub struct Sppm {
+    photons_per_pass: usize,
+    initial_radius: f64,
+    max_depth: u32,
+    seed: u64,
+    /// Fraction of newly gathered photons kept each pass, controlling how fast radii shrink.
+    alpha: f64,
+}
+
+/// Running statistics of one pixel.
+#[derive(Copy, Clone)]
+struct Pixel {
+    radius: f64,
+    photons: f64,
+    /// Accumulated BSDF weighted photon power, scaled as the radius shrinks.
+    tau: Vec3<f64>,
+    /// Emitted and directly reflected light summed over passes.
+    direct: Vec3<f64>,
+}
+
+impl Sppm {
+    pub fn new(photons_per_pass: usize, initial_radius: f64, max_depth: u32, seed: u64) -> Self {
+        Self {
+            photons_per_pass,
+            initial_radius,
+            max_depth,
+            seed,
+            alpha: 2.0 / 3.0,
+        }
+    }
+
+    /// Renders `passes` passes of a `width` by `height` image, calling `on_pass` after each.
+    /// The result is in columns from left to right, each from the top row down, as
+    /// `write_image` takes it.
+    pub fn render(
+        &self,
+        scene: &Scene,
+        camera: &Camera,
+        width: u32,
+        height: u32,
+        passes: u32,
+        mut on_pass: impl FnMut(u32),
+    ) -> Vec<Vec<Vec3<f64>>> {
+        let mut pixels = vec![
+            Pixel {
+                radius: self.initial_radius,
+                photons: 0.0,
+                tau: Vec3::zero(),
+                direct: Vec3::zero(),
+            };
+            (width * height) as usize
+        ];
+
+        for pass in 0..passes {
+            // visible points, indexed like `pixels` with rows from the bottom up
+            let visible: Vec<_> = pixels
+                .par_iter_mut()
+                .enumerate()
+                .map(|(index, pixel)| {
+                    let (i, j) = (index as u32 % width, index as u32 / width);
+                    let mut sampler = IndependentSampler::new(self.seed);
+                    sampler.start_pixel_sample((i, j), pass);
+                    let (u, v) = sampler.get_2d();
+                    let s = (i as f64 + u) / width as f64;
+                    let t = (j as f64 + v) / height as f64;
+                    let ray = camera.get_ray(s, t, &mut sampler);
+                    visible_point(&ray, scene, self.max_depth, &mut sampler, &mut pixel.direct)
+                })
+                .collect();
+
+            let map = PhotonMap::new(
+                scene,
+                camera,
+                self.photons_per_pass,
+                self.max_depth,
+                hash(&[self.seed, pass as u64]),
+            );
+            pixels
+                .par_iter_mut()
+                .zip(visible.par_iter())
+                .for_each(|(pixel, point)| {
+                    let Some(point) = point else {
+                        return;
+                    };
+                    let (flux, count) = map.gather(&point.hit, &point.wo, pixel.radius);
+                    if count == 0 {
+                        return;
+                    }
+                    let count = count as f64;
+                    let photons = pixel.photons + self.alpha * count;
+                    let radius = pixel.radius * (photons / (pixel.photons + count)).sqrt();
+                    let shrink = (radius / pixel.radius).powi(2);
+                    pixel.tau = (pixel.tau + point.beta * flux) * shrink;
+                    pixel.photons = photons;
+                    pixel.radius = radius;
+                });
+            on_pass(pass);
+        }
+
+        let emitted = passes as f64 * self.photons_per_pass as f64;
+        (0..width)
+            .map(|i| {
+                (0..height)
+                    .rev()
+                    .map(|j| {
+                        let pixel = &pixels[(j * width + i) as usize];
+                        pixel.direct / passes as f64
+                            + pixel.tau / (emitted * PI * pixel.radius * pixel.radius)
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::integrator::tests::lit_scene;
+    use crate::objects::hittable::HittableList;
+    use crate::scene::Background;
+    use crate::vec3::Point3;
+
+    fn camera() -> Camera {
+        Camera::new(
+            Point3::new(0.0, 3.0, 0.0),
+            Point3::zero(),
+            Vec3::new(0.0, 0.0, -1.0),
+            60.0,
+            1.0,
+            0.0,
+            3.0,
+            0.0,
+            1.0,
+        )
+    }
+
+    #[test]
+    fn test_image_layout() {
+        let scene = Scene::new(HittableList::new(), Background::sky());
+        let mut passes = 0;
+        let image = Sppm::new(10, 0.1, 4, 0).render(&scene, &camera(), 3, 2, 2, |_| passes += 1);
+        assert_eq!(passes, 2);
+        assert_eq!(image.len(), 3);
+        assert!(image.iter().all(|col| col.len() == 2));
+    }
+
+    #[test]
+    fn test_lit_ground_is_lit() {
+        let image = Sppm::new(1000, 0.1, 4, 0).render(&lit_scene(), &camera(), 4, 4, 4, |_| {});
+        assert!(image.iter().flatten().all(|pixel| pixel.x > 0.0));
+    }
+}
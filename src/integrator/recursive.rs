@@ -0,0 +1,76 @@
+use crate::integrator::Integrator;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::scene::Scene;
+use crate::vec3::Vec3;
+
+/// The original "Ray Tracing in One Weekend" tracer: follows one BSDF sampled ray per bounce
+/// and only finds light by hitting it, recursing until `max_depth`.
+pub struct Recursive {
+    max_depth: u32,
+}
+
+impl Recursive {
+    pub fn new(max_depth: u32) -> Self {
+        Self { max_depth }
+    }
+
+    fn color(
+        &self,
+        ray_in: &Ray,
+        scene: &Scene,
+        depth: u32,
+        sampler: &mut dyn Sampler,
+    ) -> Vec3<f64> {
+        // stop when we exceed the max ray bounce limit
+        if depth == 0 {
+            return Vec3::zero();
+        }
+
+        // t_min 0.001 to ignore hits very near to 0 to avoid shadow acne
+        if let Some(hit) = scene.hit(ray_in, 0.001, f64::INFINITY) {
+            let emitted = hit.material.emitted(&hit);
+            let wo = -ray_in.dir.normalize();
+            return match hit
+                .material
+                .sample(&hit, &wo, sampler.get_1d(), sampler.get_2d())
+            {
+                Some(sample) => {
+                    let scattered = Ray::new(hit.point, sample.wi, ray_in.time);
+                    emitted
+                        + sample.weight(&hit.normal)
+                            * self.color(&scattered, scene, depth - 1, sampler)
+                }
+                _ => emitted,
+            };
+        }
+
+        scene.background.color(&ray_in.dir)
+    }
+}
+
+impl Integrator for Recursive {
+    fn li(&self, ray: &Ray, scene: &Scene, sampler: &mut dyn Sampler) -> Vec3<f64> {
+        self.color(ray, scene, self.max_depth, sampler)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::objects::hittable::HittableList;
+    use crate::sampler::IndependentSampler;
+    use crate::scene::Background;
+    use crate::vec3::Point3;
+
+    #[test]
+    fn test_miss_returns_background() {
+        let scene = Scene::new(
+            HittableList::new(),
+            Background::Color(Vec3::new(0.1, 0.2, 0.3)),
+        );
+        let ray = Ray::new(Point3::zero(), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let color = Recursive::new(4).li(&ray, &scene, &mut IndependentSampler::new(0));
+        assert_eq!(color, Vec3::new(0.1, 0.2, 0.3));
+    }
+}
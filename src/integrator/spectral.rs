@@ -0,0 +1,200 @@
+use crate::integrator::path::{DIFFUSE_BOUNCE, MIN_BOUNCES};
+use crate::integrator::{power_heuristic, Integrator, RadianceClamp};
+use crate::objects::hittable::HitRecord;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::scene::Scene;
+use crate::spectrum::{RgbToSpectrumTable, SampledSpectrum, SampledWavelengths};
+use crate::vec3::Vec3;
+
+/// Path tracer like `PathTracer` that carries a few wavelengths per path instead of RGB,
+/// upsampling the RGB of materials and lights to spectra as it goes and converting back at the
+/// end. Dispersive materials split paths by wavelength, so past them only the hero wavelength
+/// is followed.
+pub struct SpectralPathTracer {
+    max_depth: u32,
+    table: RgbToSpectrumTable,
+    clamp: RadianceClamp,
+    regularization: f64,
+}
+
+impl SpectralPathTracer {
+    /// `table` converts colours to and from the working colour space.
+    pub fn new(max_depth: u32, table: RgbToSpectrumTable) -> Self {
+        Self {
+            max_depth,
+            table,
+            clamp: RadianceClamp::NONE,
+            regularization: 0.0,
+        }
+    }
+
+    /// See `PathTracer::with_clamp`, limiting the radiance at each of the path's wavelengths.
+    pub fn with_clamp(mut self, clamp: RadianceClamp) -> Self {
+        self.clamp = clamp;
+        self
+    }
+
+    /// See `PathTracer::with_regularization`. Regularised glass no longer disperses.
+    pub fn with_regularization(mut self, roughness: f64) -> Self {
+        self.regularization = roughness;
+        self
+    }
+
+    fn spectrum(&self, rgb: Vec3<f64>, wavelengths: &SampledWavelengths) -> SampledSpectrum {
+        self.table.spectrum(rgb, wavelengths)
+    }
+
+    /// Direct lighting at a hit from one light sample, as `path::direct_lighting`.
+    fn direct_lighting(
+        &self,
+        scene: &Scene,
+        hit: &HitRecord,
+        wo: &Vec3<f64>,
+        time: f64,
+        wavelengths: &SampledWavelengths,
+        sampler: &mut dyn Sampler,
+    ) -> SampledSpectrum {
+        let none = SampledSpectrum::constant(0.0);
+        let Some((_, light, light_pdf)) =
+            scene.sample_light(&hit.point, sampler.get_1d(), sampler.get_2d())
+        else {
+            return none;
+        };
+        let wi = (light.point - hit.point).normalize();
+        let f = hit.material.eval(hit, wo, &wi);
+        if f.near_zero() || !scene.unoccluded(&hit.point, &light.point, time) {
+            return none;
+        }
+        let weight = power_heuristic(light_pdf, hit.material.pdf(hit, wo, &wi));
+        self.spectrum(f, wavelengths)
+            * self.spectrum(light.material.emitted(&light), wavelengths)
+            * (wi.dot(&hit.normal).abs() * weight / light_pdf)
+    }
+}
+
+impl Integrator for SpectralPathTracer {
+    fn li(&self, camera_ray: &Ray, scene: &Scene, sampler: &mut dyn Sampler) -> Vec3<f64> {
+        let mut wavelengths = SampledWavelengths::sample_visible(sampler.get_1d());
+        let mut radiance = SampledSpectrum::constant(0.0);
+        let mut throughput = SampledSpectrum::constant(1.0);
+        let mut ray = *camera_ray;
+        let mut specular_bounce = true;
+        let mut bsdf_pdf = 0.0;
+        let mut after_diffuse = false;
+
+        for depth in 0..self.max_depth {
+            let Some(hit) = scene.hit(&ray, 0.001, f64::INFINITY) else {
+                radiance += self.clamp.apply_spectrum(
+                    throughput * self.spectrum(scene.background.color(&ray.dir), &wavelengths),
+                    depth,
+                );
+                break;
+            };
+            let wo = -ray.dir.normalize();
+
+            let emitted = hit.material.emitted(&hit);
+            if !emitted.near_zero() {
+                let weight = if specular_bounce {
+                    1.0
+                } else {
+                    power_heuristic(bsdf_pdf, scene.light_pdf(&ray.orig, &ray.dir))
+                };
+                radiance += self.clamp.apply_spectrum(
+                    throughput * self.spectrum(emitted, &wavelengths) * weight,
+                    depth,
+                );
+            }
+
+            let regularized = if after_diffuse && self.regularization > 0.0 {
+                hit.material.regularized(self.regularization)
+            } else {
+                None
+            };
+            let hit = match &regularized {
+                Some(material) => HitRecord {
+                    material: material.as_ref(),
+                    ..hit
+                },
+                None => hit,
+            };
+
+            if !hit.material.is_delta() {
+                radiance += self.clamp.apply_spectrum(
+                    throughput
+                        * self.direct_lighting(scene, &hit, &wo, ray.time, &wavelengths, sampler),
+                    depth + 1,
+                );
+            }
+
+            if hit.material.is_dispersive() {
+                wavelengths.terminate_secondary();
+            }
+            let Some(sample) = hit.material.sample_wavelength(
+                &hit,
+                &wo,
+                sampler.get_1d(),
+                sampler.get_2d(),
+                wavelengths.hero(),
+            ) else {
+                break;
+            };
+            throughput *= self.spectrum(sample.weight(&hit.normal), &wavelengths);
+            if self.regularization > 0.0 {
+                after_diffuse |= hit.material.diffuse_fraction(&hit, &wo) >= DIFFUSE_BOUNCE;
+            }
+            specular_bounce = sample.delta;
+            bsdf_pdf = sample.pdf;
+            ray = Ray::new(hit.point, sample.wi, ray.time);
+
+            if depth + 1 >= MIN_BOUNCES {
+                let survival = throughput.max_component().min(0.95);
+                if sampler.get_1d() >= survival {
+                    break;
+                }
+                throughput = throughput / survival;
+            }
+        }
+
+        self.table.to_rgb(radiance, &wavelengths)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::integrator::tests::mean_ground_radiance;
+    use crate::integrator::PathTracer;
+    use crate::vec3::ColorSpace;
+
+    #[test]
+    fn test_grey_scene_agrees_with_path_tracer() {
+        let table = RgbToSpectrumTable::new(ColorSpace::Srgb, 8);
+        let spectral = mean_ground_radiance(&SpectralPathTracer::new(8, table), 4000);
+        let path = mean_ground_radiance(&PathTracer::new(8), 4000);
+        for (spectral, path) in [
+            (spectral.x, path.x),
+            (spectral.y, path.y),
+            (spectral.z, path.z),
+        ] {
+            assert!(
+                (spectral - path).abs() < 0.1 * path,
+                "spectral {} path {}",
+                spectral,
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn test_clamp_takes_out_bright_samples() {
+        let table = || RgbToSpectrumTable::new(ColorSpace::Srgb, 8);
+        let clamped = SpectralPathTracer::new(8, table()).with_clamp(RadianceClamp {
+            direct: 0.05,
+            indirect: 0.05,
+        });
+        let clamped = mean_ground_radiance(&clamped, 1000);
+        let unclamped = mean_ground_radiance(&SpectralPathTracer::new(8, table()), 1000);
+        assert!(clamped.y > 0.0 && clamped.y < 0.5 * unclamped.y);
+    }
+}
@@ -0,0 +1,87 @@
+use crate::integrator::Integrator;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::scene::Scene;
+use crate::vec3::Vec3;
+
+/// Whitted-style ray tracer: direct lighting with one shadow ray per light at every surface,
+/// and recursion only through delta reflection and refraction. Rough surfaces get no indirect
+/// light, which makes it fast and noise free apart from soft shadows.
+pub struct Whitted {
+    max_depth: u32,
+}
+
+impl Whitted {
+    pub fn new(max_depth: u32) -> Self {
+        Self { max_depth }
+    }
+
+    fn color(
+        &self,
+        ray_in: &Ray,
+        scene: &Scene,
+        depth: u32,
+        sampler: &mut dyn Sampler,
+    ) -> Vec3<f64> {
+        if depth == 0 {
+            return Vec3::zero();
+        }
+        let Some(hit) = scene.hit(ray_in, 0.001, f64::INFINITY) else {
+            return scene.background.color(&ray_in.dir);
+        };
+        let wo = -ray_in.dir.normalize();
+        let mut radiance = hit.material.emitted(&hit);
+
+        if !hit.material.is_delta() {
+            for light in scene.lights.iter(&scene.world) {
+                let Some((light_hit, pdf)) = light.sample(&hit.point, sampler.get_2d()) else {
+                    continue;
+                };
+                let wi = (light_hit.point - hit.point).normalize();
+                let f = hit.material.eval(&hit, &wo, &wi);
+                if f.near_zero() || !scene.unoccluded(&hit.point, &light_hit.point, ray_in.time) {
+                    continue;
+                }
+                radiance +=
+                    f * light_hit.material.emitted(&light_hit) * (wi.dot(&hit.normal).abs() / pdf);
+            }
+            return radiance;
+        }
+
+        if let Some(sample) = hit
+            .material
+            .sample(&hit, &wo, sampler.get_1d(), sampler.get_2d())
+        {
+            let scattered = Ray::new(hit.point, sample.wi, ray_in.time);
+            radiance +=
+                sample.weight(&hit.normal) * self.color(&scattered, scene, depth - 1, sampler);
+        }
+        radiance
+    }
+}
+
+impl Integrator for Whitted {
+    fn li(&self, ray: &Ray, scene: &Scene, sampler: &mut dyn Sampler) -> Vec3<f64> {
+        self.color(ray, scene, self.max_depth, sampler)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::integrator::tests::mean_ground_radiance;
+    use crate::integrator::PathTracer;
+
+    #[test]
+    fn test_direct_lighting_matches_path_tracer() {
+        // the lit scene has no indirect light, so both only see direct lighting
+        let whitted = mean_ground_radiance(&Whitted::new(8), 2000);
+        let path = mean_ground_radiance(&PathTracer::new(8), 2000);
+        assert!(
+            (whitted.x - path.x).abs() < 0.1 * path.x,
+            "whitted {} path {}",
+            whitted.x,
+            path.x
+        );
+    }
+}
@@ -1,6 +1,16 @@
+pub mod denoise;
+pub mod film;
+pub mod integrator;
+pub mod light;
 pub mod material;
 pub mod objects;
 pub mod ray;
+pub mod render;
+pub mod sampler;
+pub mod scene;
+pub mod spectrum;
+pub mod texture;
+pub mod tonemap;
 pub mod utils;
 pub mod vec3;
 pub mod write;
@@ -0,0 +1,265 @@
+use crate::objects::hittable::{HitRecord, Hittable, HittableList};
+use crate::vec3::utils::sample_cosine_hemisphere;
+use crate::vec3::{Onb, Point3, Vec3};
+use std::f64::consts::PI;
+
+/// Light leaving a point on a light, see `LightList::sample_emission`.
+pub struct EmissionSample<'a> {
+    /// Front facing hit record of the point, with the outward normal.
+    pub hit: HitRecord<'a>,
+    /// Index of the light in `HittableList::objects`.
+    pub object: usize,
+    pub dir: Vec3<f64>,
+    pub le: Vec3<f64>,
+    /// Area density of the point, including the probability of picking the light.
+    pub pdf_pos: f64,
+    /// Solid angle density of the direction.
+    pub pdf_dir: f64,
+}
+
+impl EmissionSample<'_> {
+    /// Throughput `le * cos / (pdf_pos * pdf_dir)` of a path starting with this sample.
+    pub fn weight(&self) -> Vec3<f64> {
+        self.le * (self.dir.dot(&self.hit.normal).abs() / (self.pdf_pos * self.pdf_dir))
+    }
+}
+
+/// The emissive objects of a world that can be sampled directly, stored as indices into
+/// `HittableList::objects`. Only top level objects are considered.
+pub struct LightList {
+    indices: Vec<usize>,
+}
+
+impl LightList {
+    pub fn new(world: &HittableList) -> Self {
+        let indices = world
+            .objects
+            .iter()
+            .enumerate()
+            .filter(|(_, object)| object.is_emissive())
+            .map(|(i, _)| i)
+            .collect();
+        Self { indices }
+    }
+
+    pub fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    /// The light objects themselves, in world order.
+    pub fn iter<'a>(&'a self, world: &'a HittableList) -> impl Iterator<Item = &'a dyn Hittable> {
+        self.indices.iter().map(|&i| world.objects[i].as_ref())
+    }
+
+    /// Picks a light uniformly with `u_light`, returning its index into `HittableList::objects`
+    /// and the probability of picking it.
+    pub fn pick(&self, u_light: f64) -> Option<(usize, f64)> {
+        if self.is_empty() {
+            return None;
+        }
+        let i = ((u_light * self.len() as f64) as usize).min(self.len() - 1);
+        Some((self.indices[i], 1.0 / self.len() as f64))
+    }
+
+    /// Probability of `pick` choosing the world object at `index`, zero if it isn't a light.
+    pub fn pmf(&self, index: usize) -> f64 {
+        if self.indices.contains(&index) {
+            1.0 / self.len() as f64
+        } else {
+            0.0
+        }
+    }
+
+    /// Picks a light uniformly with `u_light` and samples a point on it, returning the light's
+    /// index into `HittableList::objects` along with the point. The returned density is in solid
+    /// angle and includes the probability of picking the light.
+    pub fn sample<'a>(
+        &self,
+        world: &'a HittableList,
+        origin: &Point3<f64>,
+        u_light: f64,
+        u: (f64, f64),
+    ) -> Option<(usize, HitRecord<'a>, f64)> {
+        let (index, pmf) = self.pick(u_light)?;
+        let (hit, pdf) = world.objects[index].sample(origin, u)?;
+        Some((index, hit, pdf * pmf))
+    }
+
+    /// Samples light leaving a light, for tracing paths from the lights: picks a light with
+    /// `u_light`, a point uniformly over its area with `u_pos` and a cosine weighted direction
+    /// from its front face with `u_dir`.
+    pub fn sample_emission<'a>(
+        &self,
+        world: &'a HittableList,
+        u_light: f64,
+        u_pos: (f64, f64),
+        u_dir: (f64, f64),
+    ) -> Option<EmissionSample<'a>> {
+        let (object, pmf) = self.pick(u_light)?;
+        let light = &world.objects[object];
+        let hit = light.sample_area(u_pos)?;
+        let local = sample_cosine_hemisphere(u_dir);
+        let pdf_dir = local.z / PI;
+        let le = hit.material.emitted(&hit);
+        if pdf_dir <= 0.0 || le.near_zero() {
+            return None;
+        }
+        Some(EmissionSample {
+            hit,
+            object,
+            dir: Onb::new(&hit.normal).to_world(&local),
+            le,
+            pdf_pos: pmf / light.area(),
+            pdf_dir,
+        })
+    }
+
+    /// Density of `sample` producing the direction `dir` from `origin`.
+    pub fn pdf_value(&self, world: &HittableList, origin: &Point3<f64>, dir: &Vec3<f64>) -> f64 {
+        if self.is_empty() {
+            return 0.0;
+        }
+        let sum: f64 = self
+            .indices
+            .iter()
+            .map(|&i| world.objects[i].pdf_value(origin, dir))
+            .sum();
+        sum / self.len() as f64
+    }
+}
+
+/// Lights gathered into named groups, whose light is rendered into AOV layers of its own so
+/// that it can be rebalanced in compositing. Every light starts in a group of its own, named
+/// after its place in the `LightList`.
+pub struct LightGroups {
+    /// Index in `HittableList::objects` and group name of each light.
+    lights: Vec<(usize, String)>,
+    /// Group names in the order of their first light.
+    names: Vec<String>,
+}
+
+impl LightGroups {
+    pub fn new(lights: &LightList) -> Self {
+        let lights: Vec<_> = lights
+            .indices
+            .iter()
+            .enumerate()
+            .map(|(i, &index)| (index, i.to_string()))
+            .collect();
+        let names = lights.iter().map(|(_, name)| name.clone()).collect();
+        Self { lights, names }
+    }
+
+    /// Moves the `light`th light of the `LightList`, whose group is named `light` to begin
+    /// with, into the group `name`. Groups left without lights are dropped.
+    ///
+    /// Panics if there are no more than `light` lights.
+    pub fn assign(&mut self, light: usize, name: &str) {
+        self.lights[light].1 = name.to_string();
+        self.names.clear();
+        for (_, group) in &self.lights {
+            if !self.names.contains(group) {
+                self.names.push(group.clone());
+            }
+        }
+    }
+
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    /// Index in `names` of the group of the light at `index` in `HittableList::objects`, if
+    /// it's a light.
+    pub fn group(&self, index: usize) -> Option<usize> {
+        let (_, name) = self.lights.iter().find(|(light, _)| *light == index)?;
+        self.names.iter().position(|group| group == name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::{DiffuseLight, Lambertian};
+    use crate::objects::{Quad, Sphere};
+
+    fn world() -> HittableList {
+        let mut world = HittableList::new();
+        world
+            .push(Sphere::new(
+                Point3::new(0.0, -100.0, 0.0),
+                99.0,
+                Lambertian::new(Vec3::new(0.5, 0.5, 0.5)),
+            ))
+            .push(Quad::new(
+                Point3::new(-0.5, 2.0, -0.5),
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(0.0, 0.0, 1.0),
+                DiffuseLight::new(Vec3::new(4.0, 4.0, 4.0)),
+            ))
+            .push(Sphere::new(
+                Point3::new(3.0, 3.0, 0.0),
+                0.5,
+                DiffuseLight::new(Vec3::new(4.0, 4.0, 4.0)),
+            ));
+        world
+    }
+
+    #[test]
+    fn test_only_emissive_objects_are_lights() {
+        assert_eq!(LightList::new(&world()).len(), 2);
+        assert!(LightList::new(&HittableList::new()).is_empty());
+    }
+
+    #[test]
+    fn test_pick_matches_pmf() {
+        let lights = LightList::new(&world());
+        let (index, pmf) = lights.pick(0.9).unwrap();
+        assert_eq!(index, 2);
+        assert_eq!(pmf, 0.5);
+        assert_eq!(lights.pmf(2), 0.5);
+        assert_eq!(lights.pmf(0), 0.0);
+    }
+
+    #[test]
+    fn test_light_groups() {
+        let lights = LightList::new(&world());
+        let mut groups = LightGroups::new(&lights);
+        assert_eq!(groups.names(), ["0", "1"]);
+        assert_eq!(groups.group(2), Some(1));
+        assert_eq!(groups.group(0), None);
+        groups.assign(0, "key");
+        groups.assign(1, "key");
+        assert_eq!(groups.names(), ["key"]);
+        assert_eq!(groups.group(2), Some(0));
+    }
+
+    #[test]
+    fn test_sample_emission_leaves_front_face() {
+        let world = world();
+        let lights = LightList::new(&world);
+        let sample = lights
+            .sample_emission(&world, 0.1, (0.3, 0.7), (0.2, 0.9))
+            .unwrap();
+        assert_eq!(sample.object, 1);
+        assert!(sample.dir.dot(&sample.hit.normal) > 0.0);
+        // half the selection probability over the unit quad, cosine weighted about -y
+        assert_eq!(sample.pdf_pos, 0.5);
+        assert!((sample.pdf_dir - -sample.dir.y / std::f64::consts::PI).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_sample_includes_selection_probability() {
+        let world = world();
+        let lights = LightList::new(&world);
+        let origin = Point3::zero();
+        let (index, hit, pdf) = lights.sample(&world, &origin, 0.1, (0.5, 0.5)).unwrap();
+        assert_eq!(index, 1);
+        let dir = hit.point - origin;
+        assert!((lights.pdf_value(&world, &origin, &dir) - pdf).abs() < 1e-9);
+        assert!(hit.material.emitted(&hit).x > 0.0);
+    }
+}
@@ -1,24 +1,55 @@
 use dotenv::dotenv;
 use indicatif::{ProgressBar, ProgressStyle};
-use rand::Rng;
-use rayon::prelude::*;
-use raytracer::material::{Dielectric, Lambertian, Metal};
-use raytracer::objects::hittable::{Hittable, HittableList};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use raytracer::denoise::{Denoiser, Guides};
+use raytracer::film::{reject_outliers, Aov, AovImage, AovLayer, Film, Filter};
+use raytracer::integrator::debug::heatmap;
+use raytracer::integrator::{
+    AmbientOcclusion, Bdpt, DebugMode, DebugView, Integrator, Mlt, PathTracer, PhotonMapper,
+    RadianceClamp, Recursive, SpectralPathTracer, Sppm, Whitted,
+};
+use raytracer::material::{Dielectric, DiffuseLight, Lambertian, Metal, RefractiveIndex};
+use raytracer::objects::hittable::HittableList;
+use raytracer::objects::quad::quad_box;
 use raytracer::objects::sphere::MovingSphere;
-use raytracer::objects::{Camera, Sphere};
-use raytracer::ray::Ray;
-use raytracer::vec3::{Color, Point3, Vec3};
-use raytracer::write::write_image;
+use raytracer::objects::{Camera, Quad, Sphere};
+use raytracer::render::{
+    render_pass, render_with_aovs, tiles, AdaptiveSampling, Checkpoint, RenderOutput,
+    RenderSettings, Tile, TileOrder,
+};
+use raytracer::sampler::{
+    HaltonSampler, IndependentSampler, Sampler, SobolSampler, StratifiedSampler,
+};
+use raytracer::scene::{Background, Scene};
+use raytracer::spectrum::RgbToSpectrumTable;
+use raytracer::tonemap::{OutputSpace, OutputTransform, ToneMap};
+use raytracer::vec3::{Color, ColorSpace, Point3, Vec3};
+use raytracer::write::{is_hdr_image, write_exr_layers, write_hdr_image, write_image};
 use std::env;
+use std::fmt::Display;
+use std::process;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Instant;
 
-fn random_scene() -> HittableList {
-    let mut rng = rand::thread_rng();
+/// The cover scene of the first book. When `lit` a small light replaces the sky, so that the
+/// glass spheres cast caustics. The small spheres are placed at random from `seed`, and the
+/// glass ones have the index `glass`.
+fn random_scene(
+    lit: bool,
+    seed: u64,
+    working: ColorSpace,
+    glass: RefractiveIndex,
+) -> (Scene, Camera) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let to_working = ColorSpace::Srgb.conversion(working);
     let origin = Vec3::new(4.0, 0.2, 0.0);
     let mut world: HittableList = HittableList::new();
     world.push(Sphere::new(
         Point3::new(0.0, -1000.0, 0.0),
         1000.0,
-        Lambertian::new(Vec3::new(0.5, 0.5, 0.5)),
+        Lambertian::new(to_working * Vec3::new(0.5, 0.5, 0.5)),
     ));
     for a in -11..11 {
         for b in -11..11 {
@@ -37,11 +68,14 @@ fn random_scene() -> HittableList {
                         0.0,
                         1.0,
                         0.2,
-                        Lambertian::new(Point3::new(
-                            rng.gen::<f64>() * rng.gen::<f64>(),
-                            rng.gen::<f64>() * rng.gen::<f64>(),
-                            rng.gen::<f64>() * rng.gen::<f64>(),
-                        )),
+                        Lambertian::new(
+                            to_working
+                                * Point3::new(
+                                    rng.gen::<f64>() * rng.gen::<f64>(),
+                                    rng.gen::<f64>() * rng.gen::<f64>(),
+                                    rng.gen::<f64>() * rng.gen::<f64>(),
+                                ),
+                        ),
                     ));
                 } else if choose_material < 0.95 {
                     // metal
@@ -49,17 +83,18 @@ fn random_scene() -> HittableList {
                         center,
                         0.2,
                         Metal::new(
-                            Vec3::new(
-                                0.5 * (1.0 + rng.gen::<f64>()),
-                                0.5 * (1.0 + rng.gen::<f64>()),
-                                0.5 * (1.0 + rng.gen::<f64>()),
-                            ),
+                            to_working
+                                * Vec3::new(
+                                    0.5 * (1.0 + rng.gen::<f64>()),
+                                    0.5 * (1.0 + rng.gen::<f64>()),
+                                    0.5 * (1.0 + rng.gen::<f64>()),
+                                ),
                             0.5 * rng.gen::<f64>(),
                         ),
                     ));
                 } else {
                     // glass
-                    world.push(Sphere::new(center, 0.2, Dielectric::new(1.5)));
+                    world.push(Sphere::new(center, 0.2, Dielectric::with_index(glass)));
                 }
             }
         }
@@ -67,131 +102,1001 @@ fn random_scene() -> HittableList {
     world.push(Sphere::new(
         Vec3::new(0.0, 1.0, 0.0),
         1.0,
-        Dielectric::new(1.5),
+        Dielectric::with_index(glass),
     ));
     world.push(Sphere::new(
         Vec3::new(-4.0, 1.0, 0.0),
         1.0,
-        Lambertian::new(Vec3::new(0.4, 0.2, 0.1)),
+        Lambertian::new(to_working * Vec3::new(0.4, 0.2, 0.1)),
     ));
     world.push(Sphere::new(
         Vec3::new(4.0, 1.0, 0.0),
         1.0,
-        Metal::new(Vec3::new(0.7, 0.6, 0.5), 0.0),
+        Metal::new(to_working * Vec3::new(0.7, 0.6, 0.5), 0.0),
     ));
+    let background = if lit {
+        world.push(Sphere::new(
+            Point3::new(4.0, 10.0, 2.0),
+            1.5,
+            DiffuseLight::new(to_working * Vec3::new(40.0, 40.0, 40.0)),
+        ));
+        Background::Color(Vec3::zero())
+    } else {
+        Background::Sky {
+            horizon: to_working * Vec3::new(1.0, 1.0, 1.0),
+            zenith: to_working * Vec3::new(0.5, 0.7, 1.0),
+        }
+    };
+
+    let lookfrom = Point3::new(13.0, 2.0, 3.0);
+    let lookat = Point3::zero();
+    let cam = Camera::new(
+        lookfrom,
+        lookat,
+        Vec3::new(0.0, 1.0, 0.0),
+        20.0,
+        16.0 / 9.0,
+        0.1,
+        10.0,
+        0.0,
+        1.0,
+    );
+    (
+        Scene::new(world, background).with_working_space(working),
+        cam,
+    )
+}
+
+fn cornell_box(working: ColorSpace, glass: RefractiveIndex) -> (Scene, Camera) {
+    let to_working = ColorSpace::Srgb.conversion(working);
+    let red = Lambertian::new(to_working * Vec3::new(0.65, 0.05, 0.05));
+    // shared, so that the walls and box have one material ID
+    let white = Arc::new(Lambertian::new(to_working * Vec3::new(0.73, 0.73, 0.73)));
+    let green = Lambertian::new(to_working * Vec3::new(0.12, 0.45, 0.15));
+    let light = DiffuseLight::new(to_working * Vec3::new(15.0, 15.0, 15.0));
+
+    let mut world = HittableList::new();
     world
+        .push(Quad::new(
+            Point3::new(555.0, 0.0, 0.0),
+            Vec3::new(0.0, 555.0, 0.0),
+            Vec3::new(0.0, 0.0, 555.0),
+            green,
+        ))
+        .push(Quad::new(
+            Point3::new(0.0, 0.0, 555.0),
+            Vec3::new(0.0, 555.0, 0.0),
+            Vec3::new(0.0, 0.0, -555.0),
+            red,
+        ))
+        .push(Quad::new(
+            Point3::new(343.0, 554.0, 332.0),
+            Vec3::new(-130.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, -105.0),
+            light,
+        ))
+        .push(Quad::new(
+            Point3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 555.0),
+            Vec3::new(555.0, 0.0, 0.0),
+            white.clone(),
+        ))
+        .push(Quad::new(
+            Point3::new(555.0, 555.0, 555.0),
+            Vec3::new(-555.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, -555.0),
+            white.clone(),
+        ))
+        .push(Quad::new(
+            Point3::new(0.0, 0.0, 555.0),
+            Vec3::new(0.0, 555.0, 0.0),
+            Vec3::new(555.0, 0.0, 0.0),
+            white.clone(),
+        ))
+        .push(quad_box(
+            Point3::new(265.0, 0.0, 295.0),
+            Point3::new(430.0, 330.0, 460.0),
+            white.clone(),
+        ))
+        .push(Sphere::new(
+            Point3::new(190.0, 90.0, 190.0),
+            90.0,
+            Dielectric::with_index(glass),
+        ));
+
+    let cam = Camera::new(
+        Point3::new(278.0, 278.0, -800.0),
+        Point3::new(278.0, 278.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        40.0,
+        1.0,
+        0.0,
+        10.0,
+        0.0,
+        1.0,
+    );
+    (
+        Scene::new(world, Background::Color(Vec3::zero())).with_working_space(working),
+        cam,
+    )
 }
 
-fn color(ray_in: &Ray, world: &HittableList, depth: u32) -> Vec3<f64> {
-    // stop when we exceed the max ray bounce limit
-    if depth <= 0 {
-        return Vec3::zero();
+/// Reads a setting if it's set, exiting with an error naming it when its value doesn't parse.
+fn setting<T: FromStr>(name: &str) -> Option<T>
+where
+    T::Err: Display,
+{
+    debug_assert!(
+        SETTINGS.iter().any(|&(known, _)| known == name),
+        "{} is missing from SETTINGS",
+        name
+    );
+    let value = env::var(name).ok()?;
+    match value.parse() {
+        Ok(parsed) => Some(parsed),
+        Err(e) => {
+            eprintln!("Invalid {} {}: {}", name, value, e);
+            process::exit(1);
+        }
     }
+}
 
-    // t_min 0.001 to ignore hits very near to 0 to avoid shadow acne
-    match world.hit(ray_in, 0.001, f64::INFINITY) {
-        Some(hit) => {
-            return match hit.material.scatter(ray_in, &hit) {
-                Some((scattered, attenuation)) => attenuation * color(&scattered, world, depth - 1),
-                _ => Vec3::zero(),
+/// Reads an optional setting, falling back to `default` when it isn't set.
+fn setting_or<T: FromStr>(name: &str, default: T) -> T
+where
+    T::Err: Display,
+{
+    setting(name).unwrap_or(default)
+}
+
+/// Reads a setting `.env` has to give, exiting with an error when it's missing.
+fn required_setting<T: FromStr>(name: &str) -> T
+where
+    T::Err: Display,
+{
+    setting(name).unwrap_or_else(|| {
+        eprintln!("{} isn't set, see .env.example", name);
+        process::exit(1);
+    })
+}
+
+/// Colours along each side of the grid the spectral integrator upsamples from, fitted at startup.
+const SPECTRUM_TABLE_RESOLUTION: usize = 16;
+
+/// Integrators that clamp radiance and regularise paths, see `PathTracer::with_clamp`.
+const FIREFLY_INTEGRATORS: [&str; 2] = ["path", "spectral"];
+
+/// Integrators that photons light, which the background doesn't emit.
+const PHOTON_INTEGRATORS: [&str; 2] = ["photon", "sppm"];
+
+/// Picks the light transport algorithm or debug view named by the `INTEGRATOR` setting.
+#[allow(clippy::too_many_arguments)]
+fn integrator(
+    name: &str,
+    max_depth: u32,
+    scene: &Scene,
+    camera: &Camera,
+    working: ColorSpace,
+    clamp: RadianceClamp,
+    regularization: f64,
+    seed: u64,
+) -> Option<Box<dyn Integrator>> {
+    Some(match name {
+        "path" => Box::new(
+            PathTracer::new(max_depth)
+                .with_clamp(clamp)
+                .with_regularization(regularization),
+        ),
+        "spectral" => Box::new(
+            SpectralPathTracer::new(
+                max_depth,
+                RgbToSpectrumTable::new(working, SPECTRUM_TABLE_RESOLUTION),
+            )
+            .with_clamp(clamp)
+            .with_regularization(regularization),
+        ),
+        "bdpt" => Box::new(Bdpt::new(*camera, max_depth)),
+        "photon" => Box::new(PhotonMapper::new(
+            scene,
+            camera,
+            setting_or("PHOTONS", 200_000),
+            setting_or("PHOTON_RADIUS", 0.1),
+            max_depth,
+            seed,
+        )),
+        "recursive" => Box::new(Recursive::new(max_depth)),
+        "ao" => Box::new(AmbientOcclusion::new(
+            1,
+            setting_or("AO_DISTANCE", f64::INFINITY),
+        )),
+        "whitted" => Box::new(Whitted::new(max_depth)),
+        "normals" => Box::new(DebugView::new(DebugMode::Normal)),
+        "depth" => Box::new(DebugView::new(DebugMode::Depth {
+            max_distance: setting_or("DEPTH_DISTANCE", 20.0),
+        })),
+        "albedo" => Box::new(DebugView::new(DebugMode::Albedo)),
+        "uv" => Box::new(DebugView::new(DebugMode::Uv)),
+        "object-id" => Box::new(DebugView::new(DebugMode::ObjectId)),
+        "bounces" => Box::new(DebugView::new(DebugMode::Bounces { max_depth })),
+        _ => return None,
+    })
+}
+
+/// Picks how the random numbers of pixel samples are generated, named by the `SAMPLER` setting.
+fn sampler(name: &str, samples_per_pixel: u32, seed: u64) -> Option<Box<dyn Sampler>> {
+    Some(match name {
+        "independent" => Box::new(IndependentSampler::new(seed)),
+        "stratified" => Box::new(StratifiedSampler::new(samples_per_pixel, true, seed)),
+        "halton" => Box::new(HaltonSampler::new(seed)),
+        "sobol" => Box::new(SobolSampler::new(samples_per_pixel, seed)),
+        _ => return None,
+    })
+}
+
+/// Picks the reconstruction filter named by the `FILTER` setting, each with its usual radius
+/// unless given one. Parameters besides the radius are the ones recommended for each.
+fn filter(name: &str, radius: Option<f64>) -> Option<Filter> {
+    Some(match name {
+        "box" => Filter::Box {
+            radius: radius.unwrap_or(0.5),
+        },
+        "tent" => Filter::Tent {
+            radius: radius.unwrap_or(1.0),
+        },
+        "gaussian" => {
+            let radius = radius.unwrap_or(1.5);
+            Filter::Gaussian {
+                radius,
+                sigma: radius / 3.0,
             }
         }
-        _ => {}
+        "mitchell" => Filter::Mitchell {
+            radius: radius.unwrap_or(2.0),
+            b: 1.0 / 3.0,
+            c: 1.0 / 3.0,
+        },
+        "lanczos" => {
+            let radius = radius.unwrap_or(3.0);
+            Filter::Lanczos {
+                radius,
+                tau: radius,
+            }
+        }
+        _ => return None,
+    })
+}
+
+/// Picks the order image tiles are rendered in, named by the `TILE_ORDER` setting.
+fn tile_order(name: &str) -> Option<TileOrder> {
+    Some(match name {
+        "scanline" => TileOrder::Scanline,
+        "spiral" => TileOrder::Spiral,
+        "hilbert" => TileOrder::Hilbert,
+        _ => return None,
+    })
+}
+
+/// Parses a `CROP` setting of `x,y,width,height` in pixels from the top left of the full
+/// resolution image, into the window of a `width` by `height` image scaled from it by `scale`
+/// that covers it, in the coordinates tiles use.
+fn crop_window(value: &str, scale: f64, width: u32, height: u32) -> Option<Tile> {
+    let values: Vec<f64> = value
+        .split(',')
+        .map(|value| value.trim().parse().ok())
+        .collect::<Option<_>>()?;
+    let [x, y, crop_width, crop_height] = values[..] else {
+        return None;
+    };
+    let left = ((x * scale).floor() as u32).min(width);
+    let right = (((x + crop_width) * scale).ceil() as u32).min(width);
+    let top = ((y * scale).floor() as u32).min(height);
+    let bottom = (((y + crop_height) * scale).ceil() as u32).min(height);
+    (left < right && top < bottom).then_some(Tile {
+        x: left,
+        y: height - bottom,
+        width: right - left,
+        height: bottom - top,
+    })
+}
+
+/// Picks the tone mapping operator named by the `TONE_MAP` setting.
+fn tone_map(name: &str) -> Option<ToneMap> {
+    Some(match name {
+        "clamp" => ToneMap::Clamp,
+        "reinhard" => ToneMap::Reinhard {
+            white: setting_or("REINHARD_WHITE", 4.0),
+        },
+        "hable" => ToneMap::Hable,
+        "aces" => ToneMap::Aces,
+        "agx" => ToneMap::Agx,
+        _ => return None,
+    })
+}
+
+/// Picks the working colour space named by the `WORKING_SPACE` setting, which scenes are
+/// rendered in.
+fn color_space(name: &str) -> Option<ColorSpace> {
+    Some(match name {
+        "srgb" | "rec709" => ColorSpace::Srgb,
+        "acescg" => ColorSpace::AcesCg,
+        "rec2020" => ColorSpace::Rec2020,
+        _ => return None,
+    })
+}
+
+/// Picks what images are written in, named by the `OUTPUT_SPACE` setting.
+fn output_space(name: &str) -> Option<OutputSpace> {
+    Some(match name {
+        "srgb" => OutputSpace::Srgb,
+        "p3" => OutputSpace::DisplayP3,
+        "linear" => OutputSpace::Linear,
+        _ => return None,
+    })
+}
+
+/// Picks an AOV by its name in the `AOVS` setting.
+fn aov(name: &str) -> Option<Aov> {
+    Some(match name {
+        "albedo" => Aov::Albedo,
+        "normal" => Aov::Normal,
+        "depth" => Aov::Depth,
+        "position" => Aov::Position,
+        "object-id" => Aov::ObjectId,
+        "material-id" => Aov::MaterialId,
+        "emission" => Aov::Emission,
+        "diffuse-direct" => Aov::DiffuseDirect,
+        "diffuse-indirect" => Aov::DiffuseIndirect,
+        "specular-direct" => Aov::SpecularDirect,
+        "specular-indirect" => Aov::SpecularIndirect,
+        "light-groups" => Aov::LightGroups,
+        _ => return None,
+    })
+}
+
+/// Parses a `name:light` entry of the `LIGHT_GROUPS` setting, with `light` below `lights`.
+fn light_group(entry: &str, lights: usize) -> Option<(&str, usize)> {
+    let (name, light) = entry.split_once(':')?;
+    let light = light.trim().parse().ok().filter(|&light| light < lights)?;
+    Some((name.trim(), light)).filter(|(name, _)| !name.is_empty())
+}
+
+/// Writes linear radiance to an image file, through `transform` to 8 bits per channel, or as
+/// is to high dynamic range formats, with `aovs` as layers of an OpenEXR file.
+fn write_pixels(
+    pixels: &[Vec<Vec3<f64>>],
+    aovs: &[AovImage],
+    transform: &OutputTransform,
+    filename: &str,
+) {
+    if !aovs.is_empty() {
+        return write_exr_layers(pixels, aovs, transform.working, filename);
+    }
+    if is_hdr_image(filename) {
+        return write_hdr_image(pixels, transform.working, filename);
     }
+    let colors = pixels
+        .iter()
+        .map(|col| {
+            col.iter()
+                .map(|&pixel| {
+                    let encoded = transform.apply(pixel) * 255.0;
+                    Color::new(
+                        encoded.x.round() as u8,
+                        encoded.y.round() as u8,
+                        encoded.z.round() as u8,
+                    )
+                })
+                .collect()
+        })
+        .collect();
+    write_image(colors, filename)
+}
 
-    let unit_direction = ray_in.dir.normalize();
-    let t = 0.5 * (unit_direction.y + 1.0);
-    Vec3::new(1.0, 1.0, 1.0) * (1.0 - t) + (Vec3::new(0.5, 0.7, 1.0) * t)
+fn progress_bar(len: u64) -> ProgressBar {
+    let bar = ProgressBar::new(len);
+    bar.set_style(
+        ProgressStyle::default_bar()
+            .template("[{elapsed} elapsed] {wide_bar} {percent}% [{eta} remaining] rendering"),
+    );
+    bar
 }
 
-fn main() {
-    // env vars
-    dotenv().ok();
-    let is_antialiasing_enabled = env::var("ANTIALIASING_ENABLED")
-        .unwrap()
-        .parse::<bool>()
-        .unwrap();
-    let antialiasing_samples_per_pixel = env::var("ANTIALIASING_SAMPLES_PER_PIXEL")
-        .unwrap()
-        .parse::<u32>()
-        .unwrap();
-    let max_depth = env::var("MAX_DEPTH").unwrap().parse::<u32>().unwrap();
+/// Applies `--some-setting value` arguments as `SOME_SETTING` env vars so they override `.env`,
+/// returning the remaining positional arguments, or what's wrong with them.
+fn apply_setting_args(args: &[String]) -> Result<Vec<String>, String> {
+    let mut positional = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.strip_prefix("--") {
+            Some(name) => {
+                let setting = name.replace('-', "_").to_uppercase();
+                if !SETTINGS.iter().any(|&(known, _)| known == setting) {
+                    return Err(format!("Unknown setting {}", arg));
+                }
+                let value = iter
+                    .next()
+                    .ok_or_else(|| format!("Missing value for {}", arg))?;
+                env::set_var(setting, value);
+            }
+            None => positional.push(arg.clone()),
+        }
+    }
+    Ok(positional)
+}
+
+/// Every setting, which `--some-setting` arguments have to name, and whether the image depends
+/// on it besides those in `RenderSettings` and the seed, for checkpoints to keep so that
+/// resuming continues the same image.
+const SETTINGS: [(&str, bool); 42] = [
+    ("ANTIALIASING_ENABLED", false),
+    ("ANTIALIASING_SAMPLES_PER_PIXEL", false),
+    ("MAX_DEPTH", true),
+    ("SEED", false),
+    ("SCENE", true),
+    ("WORKING_SPACE", true),
+    ("DISPERSION", true),
+    ("INTEGRATOR", true),
+    ("AO_DISTANCE", true),
+    ("DEPTH_DISTANCE", true),
+    ("PHOTONS", true),
+    ("PHOTON_RADIUS", true),
+    ("MLT_BOOTSTRAP", false),
+    ("MLT_CHAINS", false),
+    ("SAMPLER", true),
+    ("NOISE_THRESHOLD", false),
+    ("MIN_SAMPLES_PER_PIXEL", false),
+    ("SAMPLE_HEATMAP", false),
+    ("FILTER", true),
+    ("FILTER_RADIUS", true),
+    ("CLAMP_DIRECT", true),
+    ("CLAMP_INDIRECT", true),
+    ("REGULARIZATION", true),
+    ("OUTLIER_THRESHOLD", false),
+    ("RESOLUTION_SCALE", true),
+    ("CROP", true),
+    ("CROP_OUTPUT", false),
+    ("EXPOSURE", false),
+    ("WHITE_BALANCE", false),
+    ("TONE_MAP", false),
+    ("REINHARD_WHITE", false),
+    ("OUTPUT_SPACE", false),
+    ("AOVS", true),
+    ("LIGHT_GROUPS", true),
+    ("DENOISE", true),
+    ("TILE_SIZE", false),
+    ("TILE_ORDER", false),
+    ("SAMPLES_PER_PASS", false),
+    ("SNAPSHOT_INTERVAL", false),
+    ("TIME_BUDGET", false),
+    ("CHECKPOINT", false),
+    ("RESUME", false),
+];
+
+/// The settings checkpoints keep, see `SETTINGS`.
+fn checkpoint_settings() -> impl Iterator<Item = &'static str> {
+    SETTINGS
+        .iter()
+        .filter(|(_, kept)| *kept)
+        .map(|&(name, _)| name)
+}
 
+/// Sets the settings a checkpoint was rendered with as env vars, over any given for the resumed
+/// render.
+fn restore_settings(checkpoint: &Checkpoint) {
+    let settings = checkpoint.settings;
+    env::set_var("ANTIALIASING_ENABLED", settings.antialiasing.to_string());
+    env::set_var(
+        "ANTIALIASING_SAMPLES_PER_PIXEL",
+        settings.samples_per_pixel.to_string(),
+    );
+    match settings.adaptive {
+        Some(adaptive) => {
+            env::set_var("NOISE_THRESHOLD", adaptive.noise_threshold.to_string());
+            env::set_var("MIN_SAMPLES_PER_PIXEL", adaptive.min_samples.to_string());
+        }
+        None => env::set_var("NOISE_THRESHOLD", "0"),
+    }
+    env::set_var("TILE_SIZE", settings.tile_size.to_string());
+    env::set_var(
+        "TILE_ORDER",
+        format!("{:?}", settings.tile_order).to_lowercase(),
+    );
+    env::set_var("SEED", checkpoint.seed.to_string());
+    for (name, value) in &checkpoint.extra {
+        match value {
+            Some(value) => env::set_var(name, value),
+            None => env::remove_var(name),
+        }
+    }
+}
+
+fn main() {
     // args
     let args: Vec<String> = env::args().collect();
-
-    if args.len() != 2 {
-        eprintln!("Incorrect arguments, usage: {} <filename>", args[0]);
+    let positional = match apply_setting_args(&args[1..]) {
+        Ok(positional) => positional,
+        Err(e) => {
+            eprintln!("{}, usage: {} [--setting value]... <filename>", e, args[0]);
+            return;
+        }
+    };
+    let [filename] = positional.as_slice() else {
+        eprintln!(
+            "Incorrect arguments, usage: {} [--setting value]... <filename>",
+            args[0]
+        );
         return;
-    }
+    };
+    // env vars, command line settings take precedence as dotenv doesn't override
+    dotenv().ok();
 
-    let filename = &args[1];
+    // a resumed render takes the settings of its checkpoint over the command line's and
+    // `.env`'s, unsetting those it was rendered without
+    let resume = env::var("RESUME").ok();
+    let checkpoint = match resume.as_deref().map(Checkpoint::read) {
+        Some(Ok(checkpoint)) => {
+            restore_settings(&checkpoint);
+            Some(checkpoint)
+        }
+        Some(Err(e)) => {
+            eprintln!("Error reading checkpoint {}: {}", resume.unwrap(), e);
+            return;
+        }
+        None => None,
+    };
+    let checkpoint_filename = env::var("CHECKPOINT").ok().or(resume);
+
+    let is_antialiasing_enabled: bool = required_setting("ANTIALIASING_ENABLED");
+    let antialiasing_samples_per_pixel: u32 = required_setting("ANTIALIASING_SAMPLES_PER_PIXEL");
+    let max_depth: u32 = required_setting("MAX_DEPTH");
+    let seed = setting_or("SEED", 0);
+    // colours are given in sRGB and converted into the working space
+    let working_space_name = setting_or("WORKING_SPACE", "srgb".to_string());
+    let Some(working_space) = color_space(&working_space_name) else {
+        eprintln!(
+            "Unknown working colour space {}, expected one of srgb, acescg, rec2020",
+            working_space_name
+        );
+        return;
+    };
+    // dispersion only shows when rendering spectrally
+    let dispersion = setting_or("DISPERSION", "none".to_string());
+    let glass = match dispersion.as_str() {
+        "none" => RefractiveIndex::Constant(1.5),
+        "cauchy" => RefractiveIndex::SF11_CAUCHY,
+        "sellmeier" => RefractiveIndex::SF11_SELLMEIER,
+        _ => {
+            eprintln!(
+                "Unknown dispersion {}, expected one of none, cauchy, sellmeier",
+                dispersion
+            );
+            return;
+        }
+    };
+    // World and camera
+    let (mut scene, cam) = match env::var("SCENE").as_deref() {
+        Ok("cornell") => cornell_box(working_space, glass),
+        Ok("random-lit") => random_scene(true, seed, working_space, glass),
+        _ => random_scene(false, seed, working_space, glass),
+    };
 
     // Image
-    let aspect_ratio = 16.0 / 9.0;
-    let image_width: u32 = 400;
-    let image_height: u32 = (image_width as f64 / aspect_ratio) as u32;
+    // scaling the resolution keeps the framing, as the camera is given positions in the image
+    let resolution_scale: f64 = setting_or("RESOLUTION_SCALE", 1.0);
+    let image_width = ((400.0 * resolution_scale).round() as u32).max(1);
+    let image_height = ((image_width as f64 / cam.aspect_ratio()) as u32).max(1);
+    let crop = match env::var("CROP") {
+        Ok(value) => {
+            let Some(window) = crop_window(&value, resolution_scale, image_width, image_height)
+            else {
+                eprintln!(
+                    "Invalid crop window {}, expected x,y,width,height within the image",
+                    value
+                );
+                return;
+            };
+            Some(window)
+        }
+        Err(_) => None,
+    };
+    // the crop window is written on its own, or in the full frame with the rest black
+    let crop_full_frame = match setting_or("CROP_OUTPUT", "window".to_string()).as_str() {
+        "window" => false,
+        "frame" => true,
+        other => {
+            eprintln!("Unknown crop output {}, expected window or frame", other);
+            return;
+        }
+    };
     let samples_per_pixel = if is_antialiasing_enabled {
         antialiasing_samples_per_pixel
     } else {
         1
     };
 
-    // World
-    let world = random_scene();
+    let filter_name = setting_or("FILTER", "box".to_string());
+    let Some(filter) = filter(&filter_name, setting("FILTER_RADIUS")) else {
+        eprintln!(
+            "Unknown filter {}, expected one of box, tent, gaussian, mitchell, lanczos",
+            filter_name
+        );
+        return;
+    };
 
-    // Camera
-    let lookfrom = Point3::new(13.0, 2.0, 3.0);
-    let lookat = Point3::zero();
-    let cam = Camera::new(
-        lookfrom,
-        lookat,
-        Vec3::new(0.0, 1.0, 0.0),
-        20.0,
-        aspect_ratio,
-        0.1,
-        10.0,
-        0.0,
-        1.0,
-    );
+    // output, which checkpoints don't keep so a resumed render can be developed differently
+    let tone_map_name = setting_or("TONE_MAP", "clamp".to_string());
+    let Some(tone_map) = tone_map(&tone_map_name) else {
+        eprintln!(
+            "Unknown tone map {}, expected one of clamp, reinhard, hable, aces, agx",
+            tone_map_name
+        );
+        return;
+    };
+    let output_space_name = setting_or("OUTPUT_SPACE", "srgb".to_string());
+    let Some(output_space) = output_space(&output_space_name) else {
+        eprintln!(
+            "Unknown output colour space {}, expected one of srgb, p3, linear",
+            output_space_name
+        );
+        return;
+    };
+    let transform = OutputTransform {
+        exposure: setting_or("EXPOSURE", 0.0),
+        white_balance: setting("WHITE_BALANCE"),
+        tone_map,
+        working: working_space,
+        output: output_space,
+    };
+    // AOVs are written as layers of an OpenEXR file alongside the image
+    let aov_names = setting_or("AOVS", String::new());
+    let mut aovs = Vec::new();
+    for name in aov_names.split(',').filter(|name| !name.is_empty()) {
+        let Some(aov) = aov(name.trim()) else {
+            eprintln!(
+                "Unknown AOV {}, expected a list of albedo, normal, depth, position, object-id, \
+                 material-id, emission, diffuse-direct, diffuse-indirect, specular-direct, \
+                 specular-indirect, light-groups",
+                name
+            );
+            return;
+        };
+        aovs.push(aov);
+    }
+    // lights are in groups of their own named by their number unless put together, as in
+    // key:0,key:1,fill:2
+    let light_group_names = setting_or("LIGHT_GROUPS", String::new());
+    for entry in light_group_names
+        .split(',')
+        .filter(|entry| !entry.is_empty())
+    {
+        let Some((name, light)) = light_group(entry.trim(), scene.lights.len()) else {
+            eprintln!(
+                "Invalid light group {}, expected a list of name:light with the scene's {} \
+                 lights numbered from 0",
+                entry,
+                scene.lights.len()
+            );
+            return;
+        };
+        scene = scene.with_light_group(light, name);
+    }
+    if !aovs.is_empty() && !filename.to_lowercase().ends_with(".exr") {
+        eprintln!("AOVs are written as layers of an OpenEXR file, expected an .exr filename");
+        return;
+    }
+    // lone pixels this many times brighter than all their neighbours are darkened, 0 for none,
+    // as the image is written rather than on the film
+    let outlier_threshold = setting_or("OUTLIER_THRESHOLD", 0.0);
+    // the denoiser is guided by AOVs, rendered for it whether or not they are written
+    let denoise_strength = setting_or("DENOISE", 0.0);
+    let mut render_aovs = aovs.clone();
+    if denoise_strength > 0.0 {
+        for guide in [Aov::Albedo, Aov::Normal, Aov::Depth] {
+            if !render_aovs.contains(&guide) {
+                render_aovs.push(guide);
+            }
+        }
+    }
 
     // Render
-    let bar = ProgressBar::new(image_width as u64);
-    bar.set_style(
-        ProgressStyle::default_bar()
-            .template("[{elapsed} elapsed] {wide_bar} {percent}% [{eta} remaining] rendering"),
-    );
-
-    let scene: Vec<Vec<Color>> = (0..image_width)
-        .into_par_iter()
-        .map(|i| {
-            let mut rng = rand::thread_rng();
-            let col = (0..image_height)
-                .rev()
-                .map(|j| {
-                    let mut pixel_color = Vec3::zero();
-                    for _s in 0..samples_per_pixel {
-                        let (u_ran, v_ran) = if is_antialiasing_enabled {
-                            (rng.gen(), rng.gen())
-                        } else {
-                            (0.0, 0.0)
-                        };
-                        let u = ((i as f64) + u_ran) / (image_width as f64 - 1.0);
-                        let v = ((j as f64) + v_ran) / (image_height as f64 - 1.0);
-                        let ray = cam.get_ray(u, v);
-                        pixel_color += color(&ray, &world, max_depth);
+    let integrator_name = setting_or("INTEGRATOR", "path".to_string());
+    let clamp = RadianceClamp {
+        direct: setting_or("CLAMP_DIRECT", f64::INFINITY),
+        indirect: setting_or("CLAMP_INDIRECT", f64::INFINITY),
+    };
+    let regularization = setting_or("REGULARIZATION", 0.0);
+    if (clamp != RadianceClamp::NONE || regularization > 0.0)
+        && !FIREFLY_INTEGRATORS.contains(&integrator_name.as_str())
+    {
+        eprintln!(
+            "Clamping and path regularisation aren't supported by the {} integrator, expected \
+             path or spectral",
+            integrator_name
+        );
+        return;
+    }
+    if PHOTON_INTEGRATORS.contains(&integrator_name.as_str()) && !scene.background.is_black() {
+        eprintln!(
+            "The {} integrator only gathers light from the scene's lights, expected a scene with \
+             a black background such as random-lit or cornell",
+            integrator_name
+        );
+        return;
+    }
+    if integrator_name == "sppm" || integrator_name == "mlt" {
+        if !aovs.is_empty() {
+            eprintln!(
+                "AOVs aren't supported by the {} integrator",
+                integrator_name
+            );
+            return;
+        }
+        if denoise_strength > 0.0 {
+            eprintln!(
+                "Denoising isn't supported by the {} integrator",
+                integrator_name
+            );
+            return;
+        }
+        if checkpoint_filename.is_some() {
+            eprintln!(
+                "Checkpoints aren't supported by the {} integrator",
+                integrator_name
+            );
+            return;
+        }
+        if crop.is_some() {
+            eprintln!(
+                "Crop windows aren't supported by the {} integrator",
+                integrator_name
+            );
+            return;
+        }
+    }
+    let (pixels, aov_images) = if integrator_name == "sppm" {
+        // sppm renders whole passes of the image, one sample per pixel each
+        let sppm = Sppm::new(
+            setting_or("PHOTONS", 200_000),
+            setting_or("PHOTON_RADIUS", 0.1),
+            max_depth,
+            seed,
+        );
+        let bar = progress_bar(samples_per_pixel as u64);
+        let mut pixels = sppm.render(
+            &scene,
+            &cam,
+            image_width,
+            image_height,
+            samples_per_pixel,
+            |_| bar.inc(1),
+        );
+        bar.finish();
+        if outlier_threshold > 0.0 {
+            reject_outliers(&mut pixels, &mut [], outlier_threshold, scene.working);
+        }
+        (pixels, Vec::new())
+    } else if integrator_name == "mlt" {
+        // mlt spreads its mutations over the whole image, samples per pixel sets their number
+        let chains = setting_or("MLT_CHAINS", 1000);
+        let mlt = Mlt::new(
+            cam,
+            max_depth,
+            setting_or("MLT_BOOTSTRAP", 100_000),
+            chains,
+            seed,
+        );
+        let bar = progress_bar(chains as u64);
+        let mut pixels = mlt.render(
+            &scene,
+            image_width,
+            image_height,
+            filter,
+            samples_per_pixel,
+            || bar.inc(1),
+        );
+        bar.finish();
+        if outlier_threshold > 0.0 {
+            reject_outliers(&mut pixels, &mut [], outlier_threshold, scene.working);
+        }
+        (pixels, Vec::new())
+    } else {
+        let Some(integrator) = integrator(
+            &integrator_name,
+            max_depth,
+            &scene,
+            &cam,
+            working_space,
+            clamp,
+            regularization,
+            seed,
+        ) else {
+            eprintln!(
+                "Unknown integrator {}, expected one of path, spectral, bdpt, mlt, photon, sppm, \
+                 recursive, ao, whitted, normals, depth, albedo, uv, object-id, bounces",
+                integrator_name
+            );
+            return;
+        };
+        if aovs.iter().any(Aov::is_lighting) && !integrator.splits_lighting() {
+            eprintln!(
+                "Lighting AOVs aren't supported by the {} integrator, expected path",
+                integrator_name
+            );
+            return;
+        }
+        let sampler_name = setting_or("SAMPLER", "sobol".to_string());
+        if sampler(&sampler_name, samples_per_pixel, seed).is_none() {
+            eprintln!(
+                "Unknown sampler {}, expected one of independent, stratified, halton, sobol",
+                sampler_name
+            );
+            return;
+        }
+        let tile_order_name = setting_or("TILE_ORDER", "spiral".to_string());
+        let Some(tile_order) = tile_order(&tile_order_name) else {
+            eprintln!(
+                "Unknown tile order {}, expected one of scanline, spiral, hilbert",
+                tile_order_name
+            );
+            return;
+        };
+        // adaptive sampling is on when given a noise threshold
+        let noise_threshold = setting_or("NOISE_THRESHOLD", 0.0);
+        let adaptive = (noise_threshold > 0.0).then(|| AdaptiveSampling {
+            min_samples: setting_or("MIN_SAMPLES_PER_PIXEL", 16),
+            noise_threshold,
+        });
+        let settings = RenderSettings {
+            width: image_width,
+            height: image_height,
+            samples_per_pixel,
+            antialiasing: is_antialiasing_enabled,
+            filter,
+            adaptive,
+            tile_size: setting_or("TILE_SIZE", 16),
+            tile_order,
+            crop,
+        };
+        let new_sampler = || sampler(&sampler_name, samples_per_pixel, seed).unwrap();
+        // progressive renders take passes of this many samples per pixel, as do checkpointed ones
+        let samples_per_pass = setting_or("SAMPLES_PER_PASS", 0);
+        let denoiser = Denoiser::new(denoise_strength).with_working_space(scene.working);
+        let developed = |output: RenderOutput| {
+            let mut output = match crop {
+                Some(window) if !crop_full_frame => output.crop(window),
+                _ => output,
+            };
+            if outlier_threshold > 0.0 {
+                reject_outliers(
+                    &mut output.pixels,
+                    &mut output.aovs,
+                    outlier_threshold,
+                    scene.working,
+                );
+            }
+            if denoise_strength > 0.0 {
+                if let Some(guides) = Guides::from_output(&output) {
+                    output.pixels = denoiser.denoise(&output.pixels, &guides);
+                }
+            }
+            output.aovs.retain(|image| aovs.contains(&image.layer.aov));
+            output
+        };
+        let output = if samples_per_pass == 0 && checkpoint_filename.is_none() {
+            let window = crop.unwrap_or(Tile {
+                x: 0,
+                y: 0,
+                width: image_width,
+                height: image_height,
+            });
+            let tile_count =
+                tiles(window.width, window.height, settings.tile_size, tile_order).len();
+            let bar = progress_bar(tile_count as u64);
+            let output = render_with_aovs(
+                &scene,
+                &cam,
+                integrator.as_ref(),
+                &new_sampler,
+                settings,
+                &render_aovs,
+                |_| bar.inc(1),
+            );
+            bar.finish();
+            output
+        } else {
+            let samples_per_pass = if samples_per_pass == 0 {
+                samples_per_pixel
+            } else {
+                samples_per_pass
+            };
+            let mut film = match checkpoint {
+                Some(checkpoint) => {
+                    if (checkpoint.settings.width, checkpoint.settings.height)
+                        != (image_width, image_height)
+                    {
+                        eprintln!("Checkpoint is of a different image size");
+                        return;
+                    }
+                    if checkpoint.film.aov_layers()
+                        != AovLayer::expand(&render_aovs, scene.light_groups.names())
+                    {
+                        eprintln!("Checkpoint has different AOVs");
+                        return;
                     }
-                    // divide color by number of samples per pixel and gamma correct for gamma 2
-                    let scale = 1.0 / (samples_per_pixel as f64);
-                    Color::from((pixel_color * scale).sqrt().clamp(0.0, 0.999) * 256.0)
+                    checkpoint.film.with_working_space(scene.working)
+                }
+                None => Film::new(image_width, image_height, filter)
+                    .with_aovs(&render_aovs, scene.light_groups.names())
+                    .with_working_space(scene.working),
+            };
+            let extra = checkpoint_settings()
+                .map(|name| (name.to_string(), env::var(name).ok()))
+                .collect::<Vec<_>>();
+            let write_checkpoint = |film: Film| {
+                let Some(checkpoint_filename) = &checkpoint_filename else {
+                    return film;
+                };
+                let checkpoint = Checkpoint {
+                    settings,
+                    seed,
+                    extra: extra.clone(),
+                    film,
+                };
+                if let Err(e) = checkpoint.write(checkpoint_filename) {
+                    eprintln!("Error writing checkpoint {}: {}", checkpoint_filename, e)
+                }
+                checkpoint.film
+            };
+
+            let snapshot_interval = setting_or("SNAPSHOT_INTERVAL", 0.0);
+            let time_budget = setting_or("TIME_BUDGET", f64::INFINITY);
+            let start = Instant::now();
+            let mut last_snapshot = start;
+            let passes = (samples_per_pixel - film.samples_per_pixel()).div_ceil(samples_per_pass);
+            let bar = progress_bar(passes as u64);
+            for pass in 1..=passes {
+                render_pass(
+                    &scene,
+                    &cam,
+                    integrator.as_ref(),
+                    &new_sampler,
+                    settings,
+                    &mut film,
+                    samples_per_pass,
+                    |_| {},
+                );
+                bar.inc(1);
+                if start.elapsed().as_secs_f64() >= time_budget {
+                    break;
+                }
+                // the last pass is written below
+                if pass < passes && last_snapshot.elapsed().as_secs_f64() >= snapshot_interval {
+                    let output = developed(film.image());
+                    write_pixels(&output.pixels, &output.aovs, &transform, filename);
+                    film = write_checkpoint(film);
+                    last_snapshot = Instant::now();
+                }
+            }
+            bar.finish();
+            write_checkpoint(film).image()
+        };
+        let output = developed(output);
+        if let Ok(heatmap_filename) = env::var("SAMPLE_HEATMAP") {
+            let colors = output
+                .samples
+                .iter()
+                .map(|col| {
+                    col.iter()
+                        .map(|&count| {
+                            Color::from(heatmap(count as f64 / samples_per_pixel as f64) * 255.0)
+                        })
+                        .collect()
                 })
                 .collect();
-            bar.inc(1);
-            col
-        })
-        .collect();
-
-    bar.finish();
+            write_image(colors, &heatmap_filename);
+        }
+        (output.pixels, output.aovs)
+    };
 
-    write_image(scene, filename)
+    write_pixels(&pixels, &aov_images, &transform, filename)
 }
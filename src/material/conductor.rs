@@ -0,0 +1,212 @@
+use crate::material::fresnel::fresnel_conductor;
+use crate::material::microfacet::TrowbridgeReitz;
+use crate::material::{reflect, BsdfSample, Material};
+use crate::objects::hittable::HitRecord;
+use crate::vec3::{Onb, Vec3};
+
+/// Rough metal using a GGX microfacet distribution and the conductor Fresnel term for a complex
+/// index of refraction `eta + ik` given per RGB channel.
+#[derive(Clone, Copy)]
+pub struct Conductor {
+    eta: Vec3<f64>,
+    k: Vec3<f64>,
+    distribution: TrowbridgeReitz,
+}
+
+impl Conductor {
+    pub fn new(eta: Vec3<f64>, k: Vec3<f64>, roughness: f64) -> Self {
+        Self {
+            eta,
+            k,
+            distribution: TrowbridgeReitz::from_roughness(roughness),
+        }
+    }
+
+    pub fn gold(roughness: f64) -> Self {
+        Self::new(
+            Vec3::new(0.143119, 0.374957, 1.44248),
+            Vec3::new(3.98316, 2.38572, 1.60322),
+            roughness,
+        )
+    }
+
+    pub fn copper(roughness: f64) -> Self {
+        Self::new(
+            Vec3::new(0.200438, 0.924033, 1.10221),
+            Vec3::new(3.91295, 2.45285, 2.14219),
+            roughness,
+        )
+    }
+
+    pub fn silver(roughness: f64) -> Self {
+        Self::new(
+            Vec3::new(0.155265, 0.116723, 0.138342),
+            Vec3::new(4.82835, 3.12225, 2.14696),
+            roughness,
+        )
+    }
+
+    pub fn aluminium(roughness: f64) -> Self {
+        Self::new(
+            Vec3::new(1.65746, 0.880369, 0.521229),
+            Vec3::new(9.22387, 6.26952, 4.837),
+            roughness,
+        )
+    }
+}
+
+impl Material for Conductor {
+    fn eval(&self, hit: &HitRecord, wo: &Vec3<f64>, wi: &Vec3<f64>) -> Vec3<f64> {
+        if self.is_delta() {
+            return Vec3::zero();
+        }
+        let frame = Onb::new(&hit.normal);
+        let (wo, wi) = (frame.to_local(wo), frame.to_local(wi));
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return Vec3::zero();
+        }
+        let wm = (wo + wi).normalize();
+        let f = fresnel_conductor(wo.dot(&wm), &self.eta, &self.k);
+        f * (self.distribution.d(&wm) * self.distribution.g(&wo, &wi) / (4.0 * wo.z * wi.z))
+    }
+
+    fn sample(
+        &self,
+        hit: &HitRecord,
+        wo: &Vec3<f64>,
+        _uc: f64,
+        u: (f64, f64),
+    ) -> Option<BsdfSample> {
+        let frame = Onb::new(&hit.normal);
+        let wo_local = frame.to_local(wo);
+        if wo_local.z <= 0.0 {
+            return None;
+        }
+
+        if self.is_delta() {
+            let wi = Vec3::new(-wo_local.x, -wo_local.y, wo_local.z);
+            return Some(BsdfSample {
+                wi: frame.to_world(&wi),
+                f: fresnel_conductor(wi.z, &self.eta, &self.k) / wi.z,
+                pdf: 1.0,
+                delta: true,
+            });
+        }
+
+        let wm = self.distribution.sample_wm(&wo_local, u);
+        let wi = reflect(-wo_local, wm);
+        if wi.z <= 0.0 {
+            return None;
+        }
+        let wi = frame.to_world(&wi);
+        let pdf = self.pdf(hit, wo, &wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some(BsdfSample {
+            wi,
+            f: self.eval(hit, wo, &wi),
+            pdf,
+            delta: false,
+        })
+    }
+
+    fn pdf(&self, hit: &HitRecord, wo: &Vec3<f64>, wi: &Vec3<f64>) -> f64 {
+        if self.is_delta() {
+            return 0.0;
+        }
+        let frame = Onb::new(&hit.normal);
+        let (wo, wi) = (frame.to_local(wo), frame.to_local(wi));
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return 0.0;
+        }
+        // visible normal density, changed to the reflected direction
+        let wm = (wo + wi).normalize();
+        self.distribution.pdf(&wo, &wm) / (4.0 * wo.dot(&wm))
+    }
+
+    fn is_delta(&self) -> bool {
+        self.distribution.effectively_smooth()
+    }
+
+    fn regularized(&self, roughness: f64) -> Option<Box<dyn Material + '_>> {
+        let distribution = self.distribution.regularized(roughness)?;
+        Some(Box::new(Conductor {
+            distribution,
+            ..*self
+        }))
+    }
+
+    /// Reflectance at normal incidence.
+    fn albedo(&self, _hit: &HitRecord) -> Vec3<f64> {
+        fresnel_conductor(1.0, &self.eta, &self.k)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ray::Ray;
+    use crate::sampler::IndependentSampler;
+    use crate::vec3::Point3;
+
+    #[test]
+    fn test_scatter_reflects_above_surface() {
+        let material = Conductor::gold(0.4);
+        let hit = HitRecord {
+            point: Point3::zero(),
+            normal: Vec3::new(0.0, 1.0, 0.0),
+            material: &material,
+            t: 1.0,
+            u: 0.0,
+            v: 0.0,
+            front_face: true,
+        };
+        let ray = Ray::new(Point3::new(-1.0, 1.0, 0.0), Vec3::new(1.0, -1.0, 0.0), 0.0);
+        let mut sampler = IndependentSampler::new(0);
+        for _ in 0..100 {
+            if let Some((scattered, attenuation)) = material.scatter(&ray, &hit, &mut sampler) {
+                assert!(scattered.dir.y > 0.0);
+                assert!(attenuation.x <= 1.0 && attenuation.y <= 1.0 && attenuation.z <= 1.0);
+            }
+        }
+    }
+
+    #[test]
+    fn test_smooth_gold_is_yellow() {
+        let material = Conductor::gold(0.0);
+        let hit = HitRecord {
+            point: Point3::zero(),
+            normal: Vec3::new(0.0, 0.0, 1.0),
+            material: &material,
+            t: 1.0,
+            u: 0.0,
+            v: 0.0,
+            front_face: true,
+        };
+        let ray = Ray::new(Point3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let mut sampler = IndependentSampler::new(0);
+        let (scattered, attenuation) = material.scatter(&ray, &hit, &mut sampler).unwrap();
+        assert!((scattered.dir - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-12);
+        assert!(attenuation.x > attenuation.z);
+    }
+
+    #[test]
+    fn test_sample_matches_eval_and_pdf() {
+        let material = Conductor::copper(0.5);
+        let hit = HitRecord {
+            point: Point3::zero(),
+            normal: Vec3::new(0.0, 0.0, 1.0),
+            material: &material,
+            t: 1.0,
+            u: 0.0,
+            v: 0.0,
+            front_face: true,
+        };
+        let wo = Vec3::new(0.6, 0.0, 0.8);
+        let sample = material.sample(&hit, &wo, 0.5, (0.4, 0.3)).unwrap();
+        let pdf = material.pdf(&hit, &wo, &sample.wi);
+        assert!((sample.pdf - pdf).abs() < 1e-9 * pdf);
+        assert!(!sample.delta);
+    }
+}
@@ -0,0 +1,77 @@
+/// Index of refraction, constant or varying with wavelength so that refraction splits white
+/// light into its colours.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum RefractiveIndex {
+    Constant(f64),
+    /// Cauchy's equation `a + b / λ²`, with `λ` in micrometres.
+    Cauchy {
+        a: f64,
+        b: f64,
+    },
+    /// Sellmeier's equation `n² = 1 + Σ b λ² / (λ² - c)`, with `λ` in micrometres, which
+    /// glass catalogues give coefficients for.
+    Sellmeier {
+        b: [f64; 3],
+        c: [f64; 3],
+    },
+}
+
+/// Wavelength of the Fraunhofer d line in nanometres, at which glasses' indices are quoted.
+pub const D_LINE: f64 = 587.56;
+
+impl RefractiveIndex {
+    /// Cauchy fit of Schott SF11, a dense flint glass with strong dispersion.
+    pub const SF11_CAUCHY: Self = RefractiveIndex::Cauchy {
+        a: 1.7385,
+        b: 0.01594,
+    };
+
+    /// Schott's Sellmeier coefficients for SF11.
+    pub const SF11_SELLMEIER: Self = RefractiveIndex::Sellmeier {
+        b: [1.73759695, 0.313747346, 1.89878101],
+        c: [0.013188707, 0.0623068142, 155.23629],
+    };
+
+    /// Index at `wavelength` nanometres.
+    pub fn at(&self, wavelength: f64) -> f64 {
+        let micrometres = wavelength / 1000.0;
+        let l2 = micrometres * micrometres;
+        match *self {
+            RefractiveIndex::Constant(index) => index,
+            RefractiveIndex::Cauchy { a, b } => a + b / l2,
+            RefractiveIndex::Sellmeier { b, c } => {
+                let sum: f64 = (0..3).map(|i| b[i] * l2 / (l2 - c[i])).sum();
+                (1.0 + sum).sqrt()
+            }
+        }
+    }
+
+    /// Index used when light isn't traced by wavelength.
+    pub fn nominal(&self) -> f64 {
+        self.at(D_LINE)
+    }
+
+    pub fn is_dispersive(&self) -> bool {
+        !matches!(self, RefractiveIndex::Constant(_))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_models_agree_for_sf11() {
+        // Schott quotes nd = 1.78472
+        for index in [
+            RefractiveIndex::SF11_CAUCHY,
+            RefractiveIndex::SF11_SELLMEIER,
+        ] {
+            assert!((index.nominal() - 1.78472).abs() < 1e-3, "{:?}", index);
+            // blue bends more than red
+            assert!(index.at(450.0) > index.at(650.0));
+            assert!(index.is_dispersive());
+        }
+        assert_eq!(RefractiveIndex::Constant(1.5).at(400.0), 1.5);
+    }
+}
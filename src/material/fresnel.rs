@@ -0,0 +1,78 @@
+use crate::vec3::Vec3;
+
+/// Unpolarised Fresnel reflectance of a dielectric interface. `eta` is the ratio of the index on
+/// the transmitted side over the incident side; a negative `cos_theta_i` means the direction is on
+/// the other side of the interface.
+pub fn fresnel_dielectric(cos_theta_i: f64, eta: f64) -> f64 {
+    let mut cos_theta_i = cos_theta_i.clamp(-1.0, 1.0);
+    let mut eta = eta;
+    if cos_theta_i < 0.0 {
+        eta = 1.0 / eta;
+        cos_theta_i = -cos_theta_i;
+    }
+
+    let sin2_theta_i = 1.0 - cos_theta_i * cos_theta_i;
+    let sin2_theta_t = sin2_theta_i / (eta * eta);
+    if sin2_theta_t >= 1.0 {
+        // total internal reflection
+        return 1.0;
+    }
+    let cos_theta_t = (1.0 - sin2_theta_t).sqrt();
+
+    let r_parallel = (eta * cos_theta_i - cos_theta_t) / (eta * cos_theta_i + cos_theta_t);
+    let r_perpendicular = (cos_theta_i - eta * cos_theta_t) / (cos_theta_i + eta * cos_theta_t);
+    (r_parallel * r_parallel + r_perpendicular * r_perpendicular) / 2.0
+}
+
+/// Unpolarised Fresnel reflectance of a conductor with complex index of refraction `eta + ik`.
+pub fn fresnel_complex(cos_theta_i: f64, eta: f64, k: f64) -> f64 {
+    let cos2 = cos_theta_i.clamp(0.0, 1.0).powi(2);
+    let sin2 = 1.0 - cos2;
+
+    let t0 = eta * eta - k * k - sin2;
+    let a2_plus_b2 = (t0 * t0 + 4.0 * eta * eta * k * k).sqrt();
+    let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+
+    let t1 = a2_plus_b2 + cos2;
+    let t2 = 2.0 * a * cos_theta_i;
+    let r_s = (t1 - t2) / (t1 + t2);
+
+    let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let r_p = r_s * (t3 - t4) / (t3 + t4);
+
+    (r_p + r_s) / 2.0
+}
+
+/// Per channel conductor Fresnel reflectance for RGB `eta` and `k`.
+pub fn fresnel_conductor(cos_theta_i: f64, eta: &Vec3<f64>, k: &Vec3<f64>) -> Vec3<f64> {
+    Vec3::new(
+        fresnel_complex(cos_theta_i, eta.x, k.x),
+        fresnel_complex(cos_theta_i, eta.y, k.y),
+        fresnel_complex(cos_theta_i, eta.z, k.z),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dielectric_normal_incidence() {
+        assert!((fresnel_dielectric(1.0, 1.5) - 0.04).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_dielectric_total_internal_reflection() {
+        assert_eq!(fresnel_dielectric(-0.1, 1.5), 1.0);
+    }
+
+    #[test]
+    fn test_conductor_without_absorption_matches_dielectric() {
+        for cos_theta in [0.1, 0.5, 0.9, 1.0] {
+            let a = fresnel_complex(cos_theta, 1.5, 0.0);
+            let b = fresnel_dielectric(cos_theta, 1.5);
+            assert!((a - b).abs() < 1e-9);
+        }
+    }
+}
@@ -0,0 +1,155 @@
+use crate::vec3::Vec3;
+use std::f64::consts::PI;
+
+/// Trowbridge-Reitz (GGX) microfacet distribution. All directions are in the local shading frame
+/// where the macro surface normal is +z.
+#[derive(Debug, Copy, Clone)]
+pub struct TrowbridgeReitz {
+    alpha_x: f64,
+    alpha_y: f64,
+}
+
+impl TrowbridgeReitz {
+    pub fn new(alpha_x: f64, alpha_y: f64) -> Self {
+        Self { alpha_x, alpha_y }
+    }
+
+    /// Isotropic distribution from a perceptual roughness in [0, 1]. Roughness is squared so
+    /// that equal steps in roughness look like equal steps in blur.
+    pub fn from_roughness(roughness: f64) -> Self {
+        let alpha = Self::roughness_to_alpha(roughness);
+        Self::new(alpha, alpha)
+    }
+
+    pub fn roughness_to_alpha(roughness: f64) -> f64 {
+        let r = roughness.clamp(0.0, 1.0);
+        r * r
+    }
+
+    /// The distribution widened to at least the alpha of `roughness`, or `None` when it is that
+    /// rough already.
+    pub fn regularized(&self, roughness: f64) -> Option<Self> {
+        let alpha = Self::roughness_to_alpha(roughness);
+        (self.alpha_x.min(self.alpha_y) < alpha)
+            .then(|| Self::new(self.alpha_x.max(alpha), self.alpha_y.max(alpha)))
+    }
+
+    /// Below this the lobe is indistinguishable from a mirror and is treated as a delta.
+    pub fn effectively_smooth(&self) -> bool {
+        self.alpha_x.max(self.alpha_y) < 1e-3
+    }
+
+    /// Normal distribution function D(wm).
+    pub fn d(&self, wm: &Vec3<f64>) -> f64 {
+        let cos2_theta = wm.z * wm.z;
+        if cos2_theta == 0.0 {
+            return 0.0;
+        }
+        let e = ((wm.x / self.alpha_x).powi(2) + (wm.y / self.alpha_y).powi(2)) / cos2_theta;
+        1.0 / (PI * self.alpha_x * self.alpha_y * cos2_theta * cos2_theta * (1.0 + e).powi(2))
+    }
+
+    /// Smith auxiliary function Lambda(w).
+    pub fn lambda(&self, w: &Vec3<f64>) -> f64 {
+        let cos2_theta = w.z * w.z;
+        if cos2_theta == 0.0 {
+            return f64::INFINITY;
+        }
+        let alpha2_tan2_theta =
+            ((w.x * self.alpha_x).powi(2) + (w.y * self.alpha_y).powi(2)) / cos2_theta;
+        ((1.0 + alpha2_tan2_theta).sqrt() - 1.0) / 2.0
+    }
+
+    /// Smith masking function G1(w).
+    pub fn g1(&self, w: &Vec3<f64>) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    /// Height-correlated Smith masking-shadowing G(wo, wi).
+    pub fn g(&self, wo: &Vec3<f64>, wi: &Vec3<f64>) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// Distribution of normals visible from `w`.
+    pub fn d_visible(&self, w: &Vec3<f64>, wm: &Vec3<f64>) -> f64 {
+        if w.z == 0.0 {
+            return 0.0;
+        }
+        self.g1(w) / w.z.abs() * self.d(wm) * w.dot(wm).abs()
+    }
+
+    /// PDF of `sample_wm` returning `wm` when viewed from `w`.
+    pub fn pdf(&self, w: &Vec3<f64>, wm: &Vec3<f64>) -> f64 {
+        self.d_visible(w, wm)
+    }
+
+    /// Samples a microfacet normal visible from `w` (Heitz 2018, "Sampling the GGX Distribution
+    /// of Visible Normals").
+    pub fn sample_wm(&self, w: &Vec3<f64>, u: (f64, f64)) -> Vec3<f64> {
+        // stretch the view direction so the distribution becomes a unit hemisphere
+        let mut wh = Vec3::new(self.alpha_x * w.x, self.alpha_y * w.y, w.z).normalize();
+        if wh.z < 0.0 {
+            wh = -wh;
+        }
+        let t1 = if wh.z < 0.99999 {
+            Vec3::new(0.0, 0.0, 1.0).cross(&wh).normalize()
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let t2 = wh.cross(&t1);
+
+        // uniformly distributed point on the disk, warped towards the visible half
+        let r = u.0.sqrt();
+        let phi = 2.0 * PI * u.1;
+        let px = r * phi.cos();
+        let h = (1.0 - px * px).sqrt();
+        let s = (1.0 + wh.z) / 2.0;
+        let py = (1.0 - s) * h + s * r * phi.sin();
+        let pz = (1.0 - px * px - py * py).max(0.0).sqrt();
+
+        let nh = t1 * px + t2 * py + wh * pz;
+        Vec3::new(self.alpha_x * nh.x, self.alpha_y * nh.y, nh.z.max(1e-6)).normalize()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn test_sampled_normals_face_up() {
+        let mut rng = rand::thread_rng();
+        let distribution = TrowbridgeReitz::from_roughness(0.7);
+        let wo = Vec3::new(0.6, 0.0, 0.8);
+        for _ in 0..100 {
+            let wm = distribution.sample_wm(&wo, (rng.gen(), rng.gen()));
+            assert!(wm.z > 0.0);
+            assert!(distribution.pdf(&wo, &wm) >= 0.0);
+        }
+    }
+
+    #[test]
+    fn test_projected_area_normalization() {
+        // integral of D(wm) cos(theta_m) over the hemisphere is 1
+        let distribution = TrowbridgeReitz::from_roughness(0.5);
+        let n = 512;
+        let mut sum = 0.0;
+        for i in 0..n {
+            let cos_theta = (i as f64 + 0.5) / n as f64;
+            let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+            let wm = Vec3::new(sin_theta, 0.0, cos_theta);
+            sum += distribution.d(&wm) * cos_theta * 2.0 * PI / n as f64;
+        }
+        assert!((sum - 1.0).abs() < 1e-2);
+    }
+
+    #[test]
+    fn test_masking_is_at_most_one() {
+        let distribution = TrowbridgeReitz::from_roughness(1.0);
+        let w = Vec3::new(0.0, 0.6, 0.8);
+        assert!(distribution.g1(&w) <= 1.0);
+        assert!(distribution.g(&w, &w) <= distribution.g1(&w));
+        assert_eq!(distribution.g1(&Vec3::new(0.0, 0.0, 1.0)), 1.0);
+    }
+}
@@ -0,0 +1,617 @@
+pub mod conductor;
+pub mod dispersion;
+pub mod fresnel;
+pub mod microfacet;
+pub mod principled;
+pub mod rough_dielectric;
+
+pub use conductor::Conductor;
+pub use dispersion::RefractiveIndex;
+pub use principled::Principled;
+pub use rough_dielectric::RoughDielectric;
+
+use crate::material::dispersion::D_LINE;
+use crate::objects::hittable::HitRecord;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::texture::Texture;
+use crate::vec3::utils::{sample_cosine_hemisphere, sample_uniform_sphere};
+use crate::vec3::{Onb, Vec3};
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+fn reflect(v: Vec3<f64>, n: Vec3<f64>) -> Vec3<f64> {
+    v - (n * v.dot(&n) * 2.0)
+}
+
+fn refract(uv: Vec3<f64>, n: Vec3<f64>, refraction_ratio: f64) -> Vec3<f64> {
+    let cos_theta = (-uv).dot(&n).min(1.0);
+    let r_out_perp = (uv + (n * cos_theta)) * refraction_ratio;
+    let r_out_parallel = n * -(1.0 - (r_out_perp.length().powi(2))).abs().sqrt();
+    r_out_perp + r_out_parallel
+}
+
+/// A direction sampled from a BSDF. For delta lobes `f` already includes the division by the
+/// cosine and `pdf` is the discrete probability of choosing that lobe, so the path weight
+/// `f * |cos| / pdf` is computed the same way for every sample.
+#[derive(Debug, Copy, Clone)]
+pub struct BsdfSample {
+    pub wi: Vec3<f64>,
+    pub f: Vec3<f64>,
+    pub pdf: f64,
+    pub delta: bool,
+}
+
+impl BsdfSample {
+    /// Throughput weight `f * |cos| / pdf` of the sample about the shading normal `n`.
+    pub fn weight(&self, n: &Vec3<f64>) -> Vec3<f64> {
+        self.f * (self.wi.dot(n).abs() / self.pdf)
+    }
+}
+
+/// Surface scattering. Directions are in world space and unit length, both pointing away from
+/// the surface: `wo` towards the viewer (or previous path vertex) and `wi` towards the light.
+/// `hit.normal` always faces `wo`.
+pub trait Material: Sync {
+    /// BSDF value for a pair of directions. Delta lobes never contribute here.
+    fn eval(&self, hit: &HitRecord, wo: &Vec3<f64>, wi: &Vec3<f64>) -> Vec3<f64>;
+
+    /// Samples an incident direction using `uc` to pick between lobes and `u` for the direction.
+    fn sample(&self, hit: &HitRecord, wo: &Vec3<f64>, uc: f64, u: (f64, f64))
+        -> Option<BsdfSample>;
+
+    /// Solid angle density of `sample` returning `wi`. Delta lobes are not included.
+    fn pdf(&self, hit: &HitRecord, wo: &Vec3<f64>, wi: &Vec3<f64>) -> f64;
+
+    /// True when every lobe is a delta, so `eval` and `pdf` are always zero and there is no
+    /// point in sampling lights from this surface.
+    fn is_delta(&self) -> bool {
+        false
+    }
+
+    /// True when scattering depends on wavelength, so that spectral paths through it can only
+    /// follow their hero wavelength.
+    fn is_dispersive(&self) -> bool {
+        false
+    }
+
+    /// Like `sample`, for light of a single `wavelength` in nanometres.
+    fn sample_wavelength(
+        &self,
+        hit: &HitRecord,
+        wo: &Vec3<f64>,
+        uc: f64,
+        u: (f64, f64),
+        _wavelength: f64,
+    ) -> Option<BsdfSample> {
+        self.sample(hit, wo, uc, u)
+    }
+
+    /// Radiance emitted from the hit point back along the incoming ray.
+    fn emitted(&self, _hit: &HitRecord) -> Vec3<f64> {
+        Vec3::zero()
+    }
+
+    fn is_emissive(&self) -> bool {
+        false
+    }
+
+    /// Surface colour at the hit, for debug views and denoising guides rather than shading.
+    /// Materials without a meaningful colour, like clear glass, are white.
+    fn albedo(&self, _hit: &HitRecord) -> Vec3<f64> {
+        Vec3::new(1.0, 1.0, 1.0)
+    }
+
+    /// Share of the light scattered towards `wo` that is diffuse rather than glossy or
+    /// specular, which lighting AOVs are split by.
+    fn diffuse_fraction(&self, _hit: &HitRecord, _wo: &Vec3<f64>) -> f64 {
+        0.0
+    }
+
+    /// A stand-in at least as rough as `roughness`, which path regularisation swaps in after
+    /// diffuse bounces to blur the caustics they would otherwise only find by chance. It may
+    /// borrow the material. `None` when the material is rough enough already or has no rougher
+    /// counterpart.
+    fn regularized(&self, _roughness: f64) -> Option<Box<dyn Material + '_>> {
+        None
+    }
+
+    /// Identifies the material for material IDs: its address, shared by shapes sharing it
+    /// through an `Arc`.
+    fn key(&self) -> usize {
+        self as *const Self as *const () as usize
+    }
+
+    /// Samples a scattered ray and its throughput weight, drawing from `sampler`.
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        hit: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Ray, Vec3<f64>)> {
+        let wo = -ray_in.dir.normalize();
+        let sample = self.sample(hit, &wo, sampler.get_1d(), sampler.get_2d())?;
+        Some((
+            Ray::new(hit.point, sample.wi, ray_in.time),
+            sample.weight(&hit.normal),
+        ))
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Lambertian {
+    albedo: Vec3<f64>,
+}
+
+impl Lambertian {
+    pub fn new(albedo: Vec3<f64>) -> Self {
+        Self { albedo }
+    }
+}
+
+impl Material for Lambertian {
+    fn eval(&self, hit: &HitRecord, wo: &Vec3<f64>, wi: &Vec3<f64>) -> Vec3<f64> {
+        if wo.dot(&hit.normal) <= 0.0 || wi.dot(&hit.normal) <= 0.0 {
+            return Vec3::zero();
+        }
+        self.albedo / PI
+    }
+
+    fn sample(
+        &self,
+        hit: &HitRecord,
+        wo: &Vec3<f64>,
+        _uc: f64,
+        u: (f64, f64),
+    ) -> Option<BsdfSample> {
+        let frame = Onb::new(&hit.normal);
+        let wi = frame.to_world(&sample_cosine_hemisphere(u));
+        let pdf = self.pdf(hit, wo, &wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some(BsdfSample {
+            wi,
+            f: self.eval(hit, wo, &wi),
+            pdf,
+            delta: false,
+        })
+    }
+
+    fn pdf(&self, hit: &HitRecord, _wo: &Vec3<f64>, wi: &Vec3<f64>) -> f64 {
+        wi.dot(&hit.normal).max(0.0) / PI
+    }
+
+    fn albedo(&self, _hit: &HitRecord) -> Vec3<f64> {
+        self.albedo
+    }
+
+    fn diffuse_fraction(&self, _hit: &HitRecord, _wo: &Vec3<f64>) -> f64 {
+        1.0
+    }
+}
+
+/// Metal whose reflection is perturbed by a random offset within a ball of radius `fuzz`
+/// around the mirror direction. Directions pushed below the surface are absorbed.
+pub struct Metal {
+    albedo: Vec3<f64>,
+    fuzz: f64,
+}
+
+impl Metal {
+    pub fn new(albedo: Vec3<f64>, fuzz: f64) -> Self {
+        Self {
+            albedo,
+            fuzz: fuzz.min(1.0),
+        }
+    }
+
+    /// Density of the normalised direction to a uniform point in the fuzz ball, which is the
+    /// length of the ball along `wi` weighted by the squared distance.
+    fn fuzz_pdf(&self, reflected: &Vec3<f64>, wi: &Vec3<f64>) -> f64 {
+        let cos_theta = reflected.dot(wi);
+        let discriminant = cos_theta * cos_theta - 1.0 + self.fuzz * self.fuzz;
+        if cos_theta <= 0.0 || discriminant < 0.0 {
+            return 0.0;
+        }
+        let t0 = (cos_theta - discriminant.sqrt()).max(0.0);
+        let t1 = cos_theta + discriminant.sqrt();
+        (t1.powi(3) - t0.powi(3)) / (4.0 * PI * self.fuzz.powi(3))
+    }
+}
+
+impl Material for Metal {
+    fn eval(&self, hit: &HitRecord, wo: &Vec3<f64>, wi: &Vec3<f64>) -> Vec3<f64> {
+        let cos_theta = wi.dot(&hit.normal);
+        if self.is_delta() || cos_theta <= 0.0 {
+            return Vec3::zero();
+        }
+        // chosen so that f * cos / pdf is the albedo, as for the sampled direction
+        let reflected = reflect(-*wo, hit.normal);
+        self.albedo * (self.fuzz_pdf(&reflected, wi) / cos_theta)
+    }
+
+    fn sample(
+        &self,
+        hit: &HitRecord,
+        wo: &Vec3<f64>,
+        uc: f64,
+        u: (f64, f64),
+    ) -> Option<BsdfSample> {
+        let reflected = reflect(-*wo, hit.normal);
+        if self.is_delta() {
+            return Some(BsdfSample {
+                wi: reflected,
+                f: self.albedo / reflected.dot(&hit.normal).abs(),
+                pdf: 1.0,
+                delta: true,
+            });
+        }
+
+        let offset = sample_uniform_sphere(u) * uc.cbrt() * self.fuzz;
+        let wi = (reflected + offset).normalize();
+        if wi.dot(&hit.normal) <= 0.0 {
+            return None;
+        }
+        let pdf = self.fuzz_pdf(&reflected, &wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some(BsdfSample {
+            wi,
+            f: self.eval(hit, wo, &wi),
+            pdf,
+            delta: false,
+        })
+    }
+
+    fn pdf(&self, hit: &HitRecord, wo: &Vec3<f64>, wi: &Vec3<f64>) -> f64 {
+        if self.is_delta() || wi.dot(&hit.normal) <= 0.0 {
+            return 0.0;
+        }
+        self.fuzz_pdf(&reflect(-*wo, hit.normal), wi)
+    }
+
+    fn is_delta(&self) -> bool {
+        self.fuzz <= 0.0
+    }
+
+    fn regularized(&self, roughness: f64) -> Option<Box<dyn Material + '_>> {
+        (self.fuzz < roughness)
+            .then(|| Box::new(Metal::new(self.albedo, roughness)) as Box<dyn Material>)
+    }
+
+    fn albedo(&self, _hit: &HitRecord) -> Vec3<f64> {
+        self.albedo
+    }
+}
+
+/// Smooth glass, which can disperse light when its index varies with wavelength.
+#[derive(Clone, Copy)]
+pub struct Dielectric {
+    refraction_index: RefractiveIndex,
+}
+
+impl Dielectric {
+    pub fn new(refraction_index: f64) -> Self {
+        Self::with_index(RefractiveIndex::Constant(refraction_index))
+    }
+
+    /// Glass whose index varies with wavelength. Only spectral rendering shows the dispersion,
+    /// otherwise the index at the d line is used.
+    pub fn with_index(refraction_index: RefractiveIndex) -> Self {
+        Self { refraction_index }
+    }
+
+    fn reflectance(&self, cosine: f64, refraction_ratio: f64) -> f64 {
+        let r0 = ((1.0 - refraction_ratio) / (1.0 + refraction_ratio)).powi(2);
+        r0 + (1.0 - r0) * (1.0 - cosine).powi(5)
+    }
+}
+
+impl Material for Dielectric {
+    fn eval(&self, _hit: &HitRecord, _wo: &Vec3<f64>, _wi: &Vec3<f64>) -> Vec3<f64> {
+        Vec3::zero()
+    }
+
+    fn sample(
+        &self,
+        rec: &HitRecord,
+        wo: &Vec3<f64>,
+        uc: f64,
+        u: (f64, f64),
+    ) -> Option<BsdfSample> {
+        self.sample_wavelength(rec, wo, uc, u, D_LINE)
+    }
+
+    fn sample_wavelength(
+        &self,
+        rec: &HitRecord,
+        wo: &Vec3<f64>,
+        uc: f64,
+        _u: (f64, f64),
+        wavelength: f64,
+    ) -> Option<BsdfSample> {
+        let refraction_index = self.refraction_index.at(wavelength);
+        let refraction_ratio = if rec.front_face {
+            1.0 / refraction_index
+        } else {
+            refraction_index
+        };
+
+        let unit_direction = -*wo;
+        let cos_theta = (-unit_direction).dot(&rec.normal).min(1.0);
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+        let cannot_refract = refraction_ratio * sin_theta > 1.0;
+
+        let reflectance = if cannot_refract {
+            1.0
+        } else {
+            self.reflectance(cos_theta, refraction_ratio)
+        };
+
+        let (wi, pdf) = if uc < reflectance {
+            (reflect(unit_direction, rec.normal), reflectance)
+        } else {
+            (
+                refract(unit_direction, rec.normal, refraction_ratio),
+                1.0 - reflectance,
+            )
+        };
+
+        Some(BsdfSample {
+            wi,
+            f: Vec3::new(1.0, 1.0, 1.0) * (pdf / wi.dot(&rec.normal).abs()),
+            pdf,
+            delta: true,
+        })
+    }
+
+    fn pdf(&self, _hit: &HitRecord, _wo: &Vec3<f64>, _wi: &Vec3<f64>) -> f64 {
+        0.0
+    }
+
+    fn is_delta(&self) -> bool {
+        true
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.refraction_index.is_dispersive()
+    }
+
+    fn regularized(&self, roughness: f64) -> Option<Box<dyn Material + '_>> {
+        (roughness > 0.0).then(|| {
+            Box::new(RoughDielectric::new(
+                self.refraction_index.at(D_LINE),
+                roughness,
+            )) as Box<dyn Material>
+        })
+    }
+}
+
+/// Area light emitting from the front face only and absorbing everything that hits it.
+pub struct DiffuseLight {
+    emit: Box<dyn Texture<Vec3<f64>>>,
+}
+
+impl DiffuseLight {
+    pub fn new(emit: impl Texture<Vec3<f64>> + 'static) -> Self {
+        Self {
+            emit: Box::new(emit),
+        }
+    }
+}
+
+impl Material for DiffuseLight {
+    fn eval(&self, _hit: &HitRecord, _wo: &Vec3<f64>, _wi: &Vec3<f64>) -> Vec3<f64> {
+        Vec3::zero()
+    }
+
+    fn sample(
+        &self,
+        _hit: &HitRecord,
+        _wo: &Vec3<f64>,
+        _uc: f64,
+        _u: (f64, f64),
+    ) -> Option<BsdfSample> {
+        None
+    }
+
+    fn pdf(&self, _hit: &HitRecord, _wo: &Vec3<f64>, _wi: &Vec3<f64>) -> f64 {
+        0.0
+    }
+
+    fn emitted(&self, hit: &HitRecord) -> Vec3<f64> {
+        if hit.front_face {
+            self.emit.value(hit.u, hit.v, &hit.point)
+        } else {
+            Vec3::zero()
+        }
+    }
+
+    fn is_emissive(&self) -> bool {
+        true
+    }
+}
+
+/// A material shared by several shapes, which then count as one material for material IDs.
+impl<M: Material + Send> Material for Arc<M> {
+    fn eval(&self, hit: &HitRecord, wo: &Vec3<f64>, wi: &Vec3<f64>) -> Vec3<f64> {
+        (**self).eval(hit, wo, wi)
+    }
+
+    fn sample(
+        &self,
+        hit: &HitRecord,
+        wo: &Vec3<f64>,
+        uc: f64,
+        u: (f64, f64),
+    ) -> Option<BsdfSample> {
+        (**self).sample(hit, wo, uc, u)
+    }
+
+    fn pdf(&self, hit: &HitRecord, wo: &Vec3<f64>, wi: &Vec3<f64>) -> f64 {
+        (**self).pdf(hit, wo, wi)
+    }
+
+    fn is_delta(&self) -> bool {
+        (**self).is_delta()
+    }
+
+    fn is_dispersive(&self) -> bool {
+        (**self).is_dispersive()
+    }
+
+    fn sample_wavelength(
+        &self,
+        hit: &HitRecord,
+        wo: &Vec3<f64>,
+        uc: f64,
+        u: (f64, f64),
+        wavelength: f64,
+    ) -> Option<BsdfSample> {
+        (**self).sample_wavelength(hit, wo, uc, u, wavelength)
+    }
+
+    fn emitted(&self, hit: &HitRecord) -> Vec3<f64> {
+        (**self).emitted(hit)
+    }
+
+    fn is_emissive(&self) -> bool {
+        (**self).is_emissive()
+    }
+
+    fn albedo(&self, hit: &HitRecord) -> Vec3<f64> {
+        (**self).albedo(hit)
+    }
+
+    fn diffuse_fraction(&self, hit: &HitRecord, wo: &Vec3<f64>) -> f64 {
+        (**self).diffuse_fraction(hit, wo)
+    }
+
+    fn regularized(&self, roughness: f64) -> Option<Box<dyn Material + '_>> {
+        (**self).regularized(roughness)
+    }
+
+    fn key(&self) -> usize {
+        (**self).key()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vec3::Point3;
+
+    fn hit_record(material: &dyn Material) -> HitRecord<'_> {
+        HitRecord {
+            point: Point3::zero(),
+            normal: Vec3::new(0.0, 0.0, 1.0),
+            material,
+            t: 1.0,
+            u: 0.0,
+            v: 0.0,
+            front_face: true,
+        }
+    }
+
+    #[test]
+    fn test_lambertian_sample_matches_eval_and_pdf() {
+        let material = Lambertian::new(Vec3::new(0.5, 0.5, 0.5));
+        let hit = hit_record(&material);
+        let wo = Vec3::new(0.0, 0.6, 0.8);
+        let sample = material.sample(&hit, &wo, 0.5, (0.3, 0.6)).unwrap();
+        assert_eq!(sample.pdf, material.pdf(&hit, &wo, &sample.wi));
+        assert_eq!(sample.f, material.eval(&hit, &wo, &sample.wi));
+        assert!((sample.weight(&hit.normal) - Vec3::new(0.5, 0.5, 0.5)).length() < 1e-12);
+    }
+
+    #[test]
+    fn test_metal_fuzz_pdf_integrates_to_one() {
+        let material = Metal::new(Vec3::new(1.0, 1.0, 1.0), 0.5);
+        let reflected = Vec3::new(0.0, 0.0, 1.0);
+        let n = 4096;
+        let mut sum = 0.0;
+        for i in 0..n {
+            let cos_theta = (i as f64 + 0.5) / n as f64;
+            let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+            let wi = Vec3::new(sin_theta, 0.0, cos_theta);
+            sum += material.fuzz_pdf(&reflected, &wi) * 2.0 * PI / n as f64;
+        }
+        assert!((sum - 1.0).abs() < 1e-2, "integral {}", sum);
+    }
+
+    #[test]
+    fn test_metal_weight_is_albedo() {
+        let material = Metal::new(Vec3::new(0.8, 0.6, 0.2), 0.3);
+        let hit = hit_record(&material);
+        let wo = Vec3::new(0.0, 0.6, 0.8);
+        let sample = material.sample(&hit, &wo, 0.7, (0.2, 0.9)).unwrap();
+        assert!((sample.weight(&hit.normal) - Vec3::new(0.8, 0.6, 0.2)).length() < 1e-9);
+    }
+
+    #[test]
+    fn test_dielectric_is_delta() {
+        let material = Dielectric::new(1.5);
+        let hit = hit_record(&material);
+        let wo = Vec3::new(0.0, 0.0, 1.0);
+        let sample = material.sample(&hit, &wo, 0.99, (0.0, 0.0)).unwrap();
+        assert!(sample.delta);
+        assert!(material.is_delta());
+        assert!(sample.wi.z < 0.0);
+        assert!((sample.weight(&hit.normal) - Vec3::new(1.0, 1.0, 1.0)).length() < 1e-12);
+        assert_eq!(material.pdf(&hit, &wo, &sample.wi), 0.0);
+    }
+
+    #[test]
+    fn test_dispersive_dielectric_bends_blue_more() {
+        let material = Dielectric::with_index(RefractiveIndex::SF11_SELLMEIER);
+        let hit = hit_record(&material);
+        let wo = Vec3::new(0.6, 0.0, 0.8);
+        let refracted = |wavelength| {
+            material
+                .sample_wavelength(&hit, &wo, 0.99, (0.0, 0.0), wavelength)
+                .unwrap()
+                .wi
+        };
+        assert!(material.is_dispersive());
+        assert!(!Dielectric::new(1.5).is_dispersive());
+        // closer to the normal the higher the index
+        assert!(refracted(450.0).x.abs() < refracted(650.0).x.abs());
+    }
+
+    #[test]
+    fn test_regularized_glass_is_rough() {
+        let glass = Dielectric::new(1.5);
+        let rough = glass.regularized(0.3).unwrap();
+        assert!(!rough.is_delta());
+        // regularising what is rough enough already changes nothing
+        assert!(rough.regularized(0.2).is_none());
+        assert!(Metal::new(Vec3::new(1.0, 1.0, 1.0), 0.5)
+            .regularized(0.3)
+            .is_none());
+        assert!(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))
+            .regularized(0.3)
+            .is_none());
+
+        // principled glass blurs its reflection and refraction alike
+        let principled = Principled::glass(1.5);
+        let rough = principled.regularized(0.3).unwrap();
+        let hit = hit_record(&principled);
+        let wo = Vec3::new(0.0, 0.0, 1.0);
+        for wi in [Vec3::new(0.3, 0.0, 0.95), Vec3::new(0.3, 0.0, -0.95)] {
+            let wi = wi.normalize();
+            assert!(rough.pdf(&hit, &wo, &wi) > 10.0 * principled.pdf(&hit, &wo, &wi));
+        }
+        assert_eq!(rough.key(), principled.key());
+    }
+
+    #[test]
+    fn test_diffuse_light_is_one_sided() {
+        let material = DiffuseLight::new(Vec3::new(4.0, 4.0, 4.0));
+        let mut hit = hit_record(&material);
+        assert_eq!(material.emitted(&hit), Vec3::new(4.0, 4.0, 4.0));
+        hit.front_face = false;
+        assert_eq!(material.emitted(&hit), Vec3::zero());
+    }
+}
@@ -0,0 +1,557 @@
+use crate::material::fresnel::fresnel_dielectric;
+use crate::material::microfacet::TrowbridgeReitz;
+use crate::material::rough_dielectric::{dielectric_f, dielectric_pdf};
+use crate::material::{reflect, refract, BsdfSample, Material};
+use crate::objects::hittable::HitRecord;
+use crate::texture::Texture;
+use crate::vec3::utils::sample_cosine_hemisphere;
+use crate::vec3::{ColorSpace, Onb, Vec3};
+use std::f64::consts::PI;
+
+// keeps a roughness of zero finite so every lobe can be evaluated and mixed
+const MIN_ALPHA: f64 = 1e-4;
+
+/// Disney-style principled material (Burley 2012/2015). One set of artist friendly parameters
+/// blends a Burley diffuse base with sheen, a GGX specular layer that turns into a metal with
+/// `metallic`, a GTR1 clearcoat and rough glass transmission. Every parameter accepts a constant
+/// or any `Texture`, which makes it the mapping target for imported glTF/OBJ materials.
+///
+/// ```
+/// use raytracer::material::Principled;
+/// use raytracer::vec3::Vec3;
+///
+/// let car_paint = Principled::new(Vec3::new(0.6, 0.05, 0.05))
+///     .roughness(0.4)
+///     .clearcoat(1.0);
+/// ```
+pub struct Principled {
+    base_color: Box<dyn Texture<Vec3<f64>>>,
+    metallic: Box<dyn Texture<f64>>,
+    roughness: Box<dyn Texture<f64>>,
+    specular: Box<dyn Texture<f64>>,
+    specular_tint: Box<dyn Texture<f64>>,
+    sheen: Box<dyn Texture<f64>>,
+    sheen_tint: Box<dyn Texture<f64>>,
+    clearcoat: Box<dyn Texture<f64>>,
+    clearcoat_gloss: Box<dyn Texture<f64>>,
+    transmission: Box<dyn Texture<f64>>,
+    ior: Box<dyn Texture<f64>>,
+    working: ColorSpace,
+}
+
+impl Principled {
+    pub fn new(base_color: impl Texture<Vec3<f64>> + 'static) -> Self {
+        Self {
+            base_color: Box::new(base_color),
+            metallic: Box::new(0.0),
+            roughness: Box::new(0.5),
+            specular: Box::new(0.5),
+            specular_tint: Box::new(0.0),
+            sheen: Box::new(0.0),
+            sheen_tint: Box::new(0.5),
+            clearcoat: Box::new(0.0),
+            clearcoat_gloss: Box::new(1.0),
+            transmission: Box::new(0.0),
+            ior: Box::new(1.5),
+            working: ColorSpace::Srgb,
+        }
+    }
+
+    /// Rough diffuse surface, the counterpart of `Lambertian`.
+    pub fn diffuse(albedo: Vec3<f64>) -> Self {
+        Self::new(albedo).roughness(1.0).specular(0.0)
+    }
+
+    /// The counterpart of `Metal`, with `roughness` in place of fuzz.
+    pub fn metal(albedo: Vec3<f64>, roughness: f64) -> Self {
+        Self::new(albedo).metallic(1.0).roughness(roughness)
+    }
+
+    /// Clear glass, the counterpart of `Dielectric`.
+    pub fn glass(ior: f64) -> Self {
+        Self::new(Vec3::new(1.0, 1.0, 1.0))
+            .roughness(0.0)
+            .transmission(1.0)
+            .ior(ior)
+    }
+
+    pub fn metallic(mut self, metallic: impl Texture<f64> + 'static) -> Self {
+        self.metallic = Box::new(metallic);
+        self
+    }
+
+    pub fn roughness(mut self, roughness: impl Texture<f64> + 'static) -> Self {
+        self.roughness = Box::new(roughness);
+        self
+    }
+
+    /// Dielectric specular strength, 0.5 is a reflectance of 4% (an index of refraction of 1.5).
+    pub fn specular(mut self, specular: impl Texture<f64> + 'static) -> Self {
+        self.specular = Box::new(specular);
+        self
+    }
+
+    pub fn specular_tint(mut self, specular_tint: impl Texture<f64> + 'static) -> Self {
+        self.specular_tint = Box::new(specular_tint);
+        self
+    }
+
+    pub fn sheen(mut self, sheen: impl Texture<f64> + 'static) -> Self {
+        self.sheen = Box::new(sheen);
+        self
+    }
+
+    pub fn sheen_tint(mut self, sheen_tint: impl Texture<f64> + 'static) -> Self {
+        self.sheen_tint = Box::new(sheen_tint);
+        self
+    }
+
+    pub fn clearcoat(mut self, clearcoat: impl Texture<f64> + 'static) -> Self {
+        self.clearcoat = Box::new(clearcoat);
+        self
+    }
+
+    pub fn clearcoat_gloss(mut self, clearcoat_gloss: impl Texture<f64> + 'static) -> Self {
+        self.clearcoat_gloss = Box::new(clearcoat_gloss);
+        self
+    }
+
+    pub fn transmission(mut self, transmission: impl Texture<f64> + 'static) -> Self {
+        self.transmission = Box::new(transmission);
+        self
+    }
+
+    pub fn ior(mut self, ior: impl Texture<f64> + 'static) -> Self {
+        self.ior = Box::new(ior);
+        self
+    }
+
+    /// Space the colours are in, which the tints and lobe selection weigh luminance by. sRGB by
+    /// default.
+    pub fn working_space(mut self, working: ColorSpace) -> Self {
+        self.working = working;
+        self
+    }
+
+    fn lobes(&self, hit: &HitRecord, wo: &Vec3<f64>) -> Lobes {
+        let (u, v, p) = (hit.u, hit.v, &hit.point);
+        let base_color = self.base_color.value(u, v, p);
+        let metallic = self.metallic.value(u, v, p).clamp(0.0, 1.0);
+        let roughness = self.roughness.value(u, v, p).clamp(0.0, 1.0);
+        let specular = self.specular.value(u, v, p).max(0.0);
+        let specular_tint = self.specular_tint.value(u, v, p).clamp(0.0, 1.0);
+        let sheen = self.sheen.value(u, v, p).max(0.0);
+        let sheen_tint = self.sheen_tint.value(u, v, p).clamp(0.0, 1.0);
+        let clearcoat = self.clearcoat.value(u, v, p).max(0.0);
+        let clearcoat_gloss = self.clearcoat_gloss.value(u, v, p).clamp(0.0, 1.0);
+        let transmission = self.transmission.value(u, v, p).clamp(0.0, 1.0);
+        let ior = self.ior.value(u, v, p);
+
+        let luminance = self.working.luminance(base_color);
+        let tint = if luminance > 0.0 {
+            base_color / luminance
+        } else {
+            Vec3::new(1.0, 1.0, 1.0)
+        };
+        let white = Vec3::new(1.0, 1.0, 1.0);
+        let dielectric_f0 = lerp(white, tint, specular_tint) * (0.08 * specular);
+        let specular_f0 = lerp(dielectric_f0, base_color, metallic);
+        let sheen_color = lerp(white, tint, sheen_tint) * sheen;
+
+        let diffuse_weight = (1.0 - metallic) * (1.0 - transmission);
+        let transmission_weight = (1.0 - metallic) * transmission;
+        let clearcoat_weight = 0.25 * clearcoat;
+
+        let probabilities = [
+            // sheen is evaluated with the diffuse lobe, so it has to be sampled with it too
+            diffuse_weight * (luminance + self.working.luminance(sheen_color)),
+            (1.0 - transmission_weight) * self.working.luminance(schlick(specular_f0, wo.z)),
+            clearcoat_weight * schlick_weight(wo.z).max(0.04),
+            transmission_weight,
+        ];
+        let total: f64 = probabilities.iter().sum();
+        let probabilities = if total > 0.0 {
+            probabilities.map(|p| p / total)
+        } else {
+            [0.0; 4]
+        };
+
+        let alpha = TrowbridgeReitz::roughness_to_alpha(roughness).max(MIN_ALPHA);
+        Lobes {
+            base_color,
+            roughness,
+            specular_f0,
+            sheen_color,
+            diffuse_weight,
+            transmission_weight,
+            clearcoat_weight,
+            clearcoat_alpha: 0.1 * (1.0 - clearcoat_gloss) + 0.001 * clearcoat_gloss,
+            eta: if hit.front_face { ior } else { 1.0 / ior },
+            distribution: TrowbridgeReitz::new(alpha, alpha),
+            probabilities,
+        }
+    }
+}
+
+/// Parameters of the material evaluated at one hit point, with the probability of sampling
+/// each of the diffuse, specular, clearcoat and transmission lobes.
+struct Lobes {
+    base_color: Vec3<f64>,
+    roughness: f64,
+    specular_f0: Vec3<f64>,
+    sheen_color: Vec3<f64>,
+    diffuse_weight: f64,
+    transmission_weight: f64,
+    clearcoat_weight: f64,
+    clearcoat_alpha: f64,
+    eta: f64,
+    distribution: TrowbridgeReitz,
+    probabilities: [f64; 4],
+}
+
+const DIFFUSE: usize = 0;
+const SPECULAR: usize = 1;
+const CLEARCOAT: usize = 2;
+const TRANSMISSION: usize = 3;
+
+impl Lobes {
+    /// BSDF value for local directions; `wo` is always above the surface.
+    fn eval(&self, wo: &Vec3<f64>, wi: &Vec3<f64>) -> Vec3<f64> {
+        let mut f = Vec3::zero();
+
+        if wi.z > 0.0 {
+            let wh = (*wo + *wi).normalize();
+            let cos_d = wi.dot(&wh);
+
+            if self.diffuse_weight > 0.0 {
+                let fd90 = 0.5 + 2.0 * self.roughness * cos_d * cos_d;
+                let burley = (1.0 + (fd90 - 1.0) * schlick_weight(wi.z))
+                    * (1.0 + (fd90 - 1.0) * schlick_weight(wo.z));
+                let diffuse = self.base_color * (burley / PI);
+                let sheen = self.sheen_color * schlick_weight(cos_d);
+                f += (diffuse + sheen) * self.diffuse_weight;
+            }
+
+            let specular_weight = 1.0 - self.transmission_weight;
+            if specular_weight > 0.0 {
+                let d = self.distribution.d(&wh);
+                let g = self.distribution.g(wo, wi);
+                let fresnel = schlick(self.specular_f0, cos_d);
+                f += fresnel * (specular_weight * d * g / (4.0 * wo.z * wi.z));
+            }
+
+            if self.clearcoat_weight > 0.0 {
+                let d = gtr1(wh.z, self.clearcoat_alpha);
+                let g = TrowbridgeReitz::new(0.25, 0.25).g(wo, wi);
+                let fresnel = 0.04 + 0.96 * schlick_weight(cos_d);
+                f += Vec3::new(1.0, 1.0, 1.0)
+                    * (self.clearcoat_weight * fresnel * d * g / (4.0 * wo.z * wi.z));
+            }
+        }
+
+        if self.transmission_weight > 0.0 {
+            let tint = if wi.z < 0.0 {
+                self.base_color
+            } else {
+                Vec3::new(1.0, 1.0, 1.0)
+            };
+            f += tint
+                * (self.transmission_weight * dielectric_f(&self.distribution, self.eta, wo, wi));
+        }
+
+        f
+    }
+
+    /// Solid angle density of `sample` producing `wi`, mixed over all lobes.
+    fn pdf(&self, wo: &Vec3<f64>, wi: &Vec3<f64>) -> f64 {
+        let p = &self.probabilities;
+        let mut pdf = 0.0;
+        if wi.z > 0.0 {
+            let wh = (*wo + *wi).normalize();
+            pdf += p[DIFFUSE] * wi.z / PI;
+            pdf += p[SPECULAR] * self.distribution.pdf(wo, &wh) / (4.0 * wo.dot(&wh));
+            pdf += p[CLEARCOAT] * gtr1(wh.z, self.clearcoat_alpha) * wh.z / (4.0 * wo.dot(&wh));
+        }
+        if p[TRANSMISSION] > 0.0 {
+            pdf += p[TRANSMISSION] * dielectric_pdf(&self.distribution, self.eta, wo, wi);
+        }
+        pdf
+    }
+
+    /// Picks a lobe with `uc` and samples an incident direction from it.
+    fn sample(&self, wo: &Vec3<f64>, uc: f64, u: (f64, f64)) -> Option<Vec3<f64>> {
+        let mut lobe = 0;
+        let mut cdf = 0.0;
+        while lobe < 3 && uc >= cdf + self.probabilities[lobe] {
+            cdf += self.probabilities[lobe];
+            lobe += 1;
+        }
+        let p = self.probabilities[lobe];
+        if p == 0.0 {
+            return None;
+        }
+
+        let wi = match lobe {
+            DIFFUSE => sample_cosine_hemisphere(u),
+            SPECULAR => reflect(-*wo, self.distribution.sample_wm(wo, u)),
+            CLEARCOAT => reflect(-*wo, sample_gtr1(self.clearcoat_alpha, u)),
+            _ => {
+                // reuse the remainder of the lobe selection to pick reflection or refraction
+                let uc = ((uc - cdf) / p).min(1.0 - f64::EPSILON);
+                let wm = self.distribution.sample_wm(wo, u);
+                if uc < fresnel_dielectric(wo.dot(&wm), self.eta) {
+                    reflect(-*wo, wm)
+                } else {
+                    refract(-*wo, wm, 1.0 / self.eta)
+                }
+            }
+        };
+        Some(wi)
+    }
+}
+
+/// The scattering of a `Principled` material, with its specular and transmission lobes made at
+/// least `min_roughness` rough, which is zero unless regularised.
+struct PrincipledBsdf<'a> {
+    material: &'a Principled,
+    min_roughness: f64,
+}
+
+impl PrincipledBsdf<'_> {
+    fn lobes(&self, hit: &HitRecord, wo: &Vec3<f64>) -> Lobes {
+        let mut lobes = self.material.lobes(hit, wo);
+        if let Some(distribution) = lobes.distribution.regularized(self.min_roughness) {
+            lobes.distribution = distribution;
+        }
+        lobes
+    }
+}
+
+impl Material for PrincipledBsdf<'_> {
+    fn eval(&self, hit: &HitRecord, wo: &Vec3<f64>, wi: &Vec3<f64>) -> Vec3<f64> {
+        let frame = Onb::new(&hit.normal);
+        let (wo, wi) = (frame.to_local(wo), frame.to_local(wi));
+        if wo.z <= 0.0 {
+            return Vec3::zero();
+        }
+        self.lobes(hit, &wo).eval(&wo, &wi)
+    }
+
+    fn sample(
+        &self,
+        hit: &HitRecord,
+        wo: &Vec3<f64>,
+        uc: f64,
+        u: (f64, f64),
+    ) -> Option<BsdfSample> {
+        let frame = Onb::new(&hit.normal);
+        let wo = frame.to_local(wo);
+        if wo.z <= 0.0 {
+            return None;
+        }
+
+        let lobes = self.lobes(hit, &wo);
+        let wi = lobes.sample(&wo, uc, u)?;
+        let pdf = lobes.pdf(&wo, &wi);
+        if pdf <= 0.0 || !pdf.is_finite() {
+            return None;
+        }
+        Some(BsdfSample {
+            wi: frame.to_world(&wi),
+            f: lobes.eval(&wo, &wi),
+            pdf,
+            delta: false,
+        })
+    }
+
+    fn pdf(&self, hit: &HitRecord, wo: &Vec3<f64>, wi: &Vec3<f64>) -> f64 {
+        let frame = Onb::new(&hit.normal);
+        let (wo, wi) = (frame.to_local(wo), frame.to_local(wi));
+        if wo.z <= 0.0 {
+            return 0.0;
+        }
+        self.lobes(hit, &wo).pdf(&wo, &wi)
+    }
+
+    fn albedo(&self, hit: &HitRecord) -> Vec3<f64> {
+        self.material.base_color.value(hit.u, hit.v, &hit.point)
+    }
+
+    fn diffuse_fraction(&self, hit: &HitRecord, wo: &Vec3<f64>) -> f64 {
+        let wo = Onb::new(&hit.normal).to_local(wo);
+        if wo.z <= 0.0 {
+            return 0.0;
+        }
+        self.lobes(hit, &wo).probabilities[DIFFUSE]
+    }
+
+    fn key(&self) -> usize {
+        self.material.key()
+    }
+}
+
+impl Principled {
+    fn bsdf(&self) -> PrincipledBsdf<'_> {
+        PrincipledBsdf {
+            material: self,
+            min_roughness: 0.0,
+        }
+    }
+}
+
+impl Material for Principled {
+    fn eval(&self, hit: &HitRecord, wo: &Vec3<f64>, wi: &Vec3<f64>) -> Vec3<f64> {
+        self.bsdf().eval(hit, wo, wi)
+    }
+
+    fn sample(
+        &self,
+        hit: &HitRecord,
+        wo: &Vec3<f64>,
+        uc: f64,
+        u: (f64, f64),
+    ) -> Option<BsdfSample> {
+        self.bsdf().sample(hit, wo, uc, u)
+    }
+
+    fn pdf(&self, hit: &HitRecord, wo: &Vec3<f64>, wi: &Vec3<f64>) -> f64 {
+        self.bsdf().pdf(hit, wo, wi)
+    }
+
+    fn albedo(&self, hit: &HitRecord) -> Vec3<f64> {
+        self.bsdf().albedo(hit)
+    }
+
+    fn diffuse_fraction(&self, hit: &HitRecord, wo: &Vec3<f64>) -> f64 {
+        self.bsdf().diffuse_fraction(hit, wo)
+    }
+
+    fn regularized(&self, roughness: f64) -> Option<Box<dyn Material + '_>> {
+        (roughness > 0.0).then(|| {
+            Box::new(PrincipledBsdf {
+                material: self,
+                min_roughness: roughness,
+            }) as Box<dyn Material + '_>
+        })
+    }
+}
+
+fn lerp(a: Vec3<f64>, b: Vec3<f64>, t: f64) -> Vec3<f64> {
+    a * (1.0 - t) + b * t
+}
+
+fn schlick_weight(cos_theta: f64) -> f64 {
+    (1.0 - cos_theta).clamp(0.0, 1.0).powi(5)
+}
+
+fn schlick(f0: Vec3<f64>, cos_theta: f64) -> Vec3<f64> {
+    lerp(f0, Vec3::new(1.0, 1.0, 1.0), schlick_weight(cos_theta))
+}
+
+/// Generalized Trowbridge-Reitz with gamma = 1, the long tailed clearcoat distribution.
+fn gtr1(cos_theta_h: f64, alpha: f64) -> f64 {
+    if alpha >= 1.0 {
+        return 1.0 / PI;
+    }
+    let alpha2 = alpha * alpha;
+    let t = 1.0 + (alpha2 - 1.0) * cos_theta_h * cos_theta_h;
+    (alpha2 - 1.0) / (PI * alpha2.ln() * t)
+}
+
+fn sample_gtr1(alpha: f64, u: (f64, f64)) -> Vec3<f64> {
+    let alpha2 = alpha * alpha;
+    let cos_theta = ((1.0 - alpha2.powf(1.0 - u.0)) / (1.0 - alpha2))
+        .max(0.0)
+        .sqrt();
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * PI * u.1;
+    Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ray::Ray;
+    use crate::sampler::IndependentSampler;
+    use crate::vec3::Point3;
+    use rand::Rng;
+
+    fn hit_record(material: &dyn Material) -> HitRecord<'_> {
+        HitRecord {
+            point: Point3::zero(),
+            normal: Vec3::new(0.0, 1.0, 0.0),
+            material,
+            t: 1.0,
+            u: 0.0,
+            v: 0.0,
+            front_face: true,
+        }
+    }
+
+    #[test]
+    fn test_white_diffuse_does_not_gain_energy() {
+        // estimate the directional albedo, which must stay close to or below one
+        let material = Principled::diffuse(Vec3::new(1.0, 1.0, 1.0));
+        let hit = hit_record(&material);
+        let ray = Ray::new(Point3::new(0.0, 1.0, 1.0), Vec3::new(0.0, -1.0, -1.0), 0.0);
+        let mut sampler = IndependentSampler::new(0);
+        let n = 20000;
+        let mut sum = Vec3::zero();
+        for _ in 0..n {
+            if let Some((_, attenuation)) = material.scatter(&ray, &hit, &mut sampler) {
+                sum += attenuation;
+            }
+        }
+        let albedo = sum / n as f64;
+        assert!(albedo.x < 1.1, "albedo {}", albedo.x);
+        assert!(albedo.x > 0.8, "albedo {}", albedo.x);
+    }
+
+    #[test]
+    fn test_glass_transmits() {
+        let material = Principled::glass(1.5);
+        let hit = hit_record(&material);
+        let ray = Ray::new(Point3::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0), 0.0);
+        let mut sampler = IndependentSampler::new(0);
+        let transmitted = (0..100)
+            .filter_map(|_| material.scatter(&ray, &hit, &mut sampler))
+            .filter(|(scattered, _)| scattered.dir.y < 0.0)
+            .count();
+        assert!(transmitted > 80);
+    }
+
+    #[test]
+    fn test_sheen_on_black_is_sampled() {
+        let material = Principled::new(Vec3::zero()).specular(0.0).sheen(1.0);
+        let hit = hit_record(&material);
+        let wo = Vec3::new(0.3, 0.2, 0.9).normalize();
+        let lobes = material.lobes(&hit, &wo);
+        assert!(lobes.probabilities[DIFFUSE] > 0.0);
+        let mut rng = rand::thread_rng();
+        let sampled = (0..100)
+            .filter_map(|_| lobes.sample(&wo, rng.gen(), (rng.gen(), rng.gen())))
+            .filter(|wi| ColorSpace::Srgb.luminance(lobes.eval(&wo, wi)) > 0.0)
+            .count();
+        assert!(sampled > 0);
+    }
+
+    #[test]
+    fn test_sampled_directions_have_positive_pdf() {
+        let material = Principled::new(Vec3::new(0.5, 0.4, 0.3))
+            .metallic(0.3)
+            .clearcoat(1.0)
+            .sheen(1.0)
+            .transmission(0.5);
+        let hit = hit_record(&material);
+        let wo = Vec3::new(0.3, 0.2, 0.9).normalize();
+        let lobes = material.lobes(&hit, &wo);
+        let mut rng = rand::thread_rng();
+        for _ in 0..1000 {
+            if let Some(wi) = lobes.sample(&wo, rng.gen(), (rng.gen(), rng.gen())) {
+                if ColorSpace::Srgb.luminance(lobes.eval(&wo, &wi)) > 0.0 {
+                    assert!(lobes.pdf(&wo, &wi) > 0.0);
+                }
+            }
+        }
+    }
+}
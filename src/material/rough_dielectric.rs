@@ -0,0 +1,241 @@
+use crate::material::fresnel::fresnel_dielectric;
+use crate::material::microfacet::TrowbridgeReitz;
+use crate::material::{reflect, refract, BsdfSample, Material};
+use crate::objects::hittable::HitRecord;
+use crate::vec3::{Onb, Vec3};
+
+/// Frosted glass: a dielectric interface whose microfacets follow a GGX distribution, so both the
+/// reflected and the refracted lobe blur with roughness. A roughness of zero matches `Dielectric`.
+#[derive(Clone, Copy)]
+pub struct RoughDielectric {
+    refraction_index: f64,
+    distribution: TrowbridgeReitz,
+}
+
+impl RoughDielectric {
+    pub fn new(refraction_index: f64, roughness: f64) -> Self {
+        Self {
+            refraction_index,
+            distribution: TrowbridgeReitz::from_roughness(roughness),
+        }
+    }
+
+    // the hit normal always faces wo, so eta is relative to that side
+    fn eta(&self, hit: &HitRecord) -> f64 {
+        if hit.front_face {
+            self.refraction_index
+        } else {
+            1.0 / self.refraction_index
+        }
+    }
+}
+
+impl Material for RoughDielectric {
+    fn eval(&self, hit: &HitRecord, wo: &Vec3<f64>, wi: &Vec3<f64>) -> Vec3<f64> {
+        if self.is_delta() {
+            return Vec3::zero();
+        }
+        let frame = Onb::new(&hit.normal);
+        let f = dielectric_f(
+            &self.distribution,
+            self.eta(hit),
+            &frame.to_local(wo),
+            &frame.to_local(wi),
+        );
+        Vec3::new(f, f, f)
+    }
+
+    fn sample(
+        &self,
+        hit: &HitRecord,
+        wo: &Vec3<f64>,
+        uc: f64,
+        u: (f64, f64),
+    ) -> Option<BsdfSample> {
+        let eta = self.eta(hit);
+        let frame = Onb::new(&hit.normal);
+        let wo_local = frame.to_local(wo);
+        if wo_local.z <= 0.0 {
+            return None;
+        }
+
+        let wm = if self.is_delta() {
+            Vec3::new(0.0, 0.0, 1.0)
+        } else {
+            self.distribution.sample_wm(&wo_local, u)
+        };
+        let reflectance = fresnel_dielectric(wo_local.dot(&wm), eta);
+        let wi = if uc < reflectance {
+            reflect(-wo_local, wm)
+        } else {
+            refract(-wo_local, wm, 1.0 / eta)
+        };
+        if wi.z == 0.0 || (uc < reflectance) != (wi.z > 0.0) {
+            return None;
+        }
+
+        if self.is_delta() {
+            let pdf = if wi.z > 0.0 {
+                reflectance
+            } else {
+                1.0 - reflectance
+            };
+            return Some(BsdfSample {
+                wi: frame.to_world(&wi),
+                f: Vec3::new(1.0, 1.0, 1.0) * (pdf / wi.z.abs()),
+                pdf,
+                delta: true,
+            });
+        }
+
+        let pdf = dielectric_pdf(&self.distribution, eta, &wo_local, &wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+        let f = dielectric_f(&self.distribution, eta, &wo_local, &wi);
+        Some(BsdfSample {
+            wi: frame.to_world(&wi),
+            f: Vec3::new(f, f, f),
+            pdf,
+            delta: false,
+        })
+    }
+
+    fn pdf(&self, hit: &HitRecord, wo: &Vec3<f64>, wi: &Vec3<f64>) -> f64 {
+        if self.is_delta() {
+            return 0.0;
+        }
+        let frame = Onb::new(&hit.normal);
+        dielectric_pdf(
+            &self.distribution,
+            self.eta(hit),
+            &frame.to_local(wo),
+            &frame.to_local(wi),
+        )
+    }
+
+    fn is_delta(&self) -> bool {
+        self.distribution.effectively_smooth()
+    }
+
+    fn regularized(&self, roughness: f64) -> Option<Box<dyn Material + '_>> {
+        let distribution = self.distribution.regularized(roughness)?;
+        Some(Box::new(RoughDielectric {
+            distribution,
+            ..*self
+        }))
+    }
+}
+
+/// Rough dielectric BSDF for local directions with `wo` above the surface. `eta` is the index on
+/// the far side of the surface over the index on the `wo` side. Refraction is not scaled by
+/// `1 / eta^2`, matching `Dielectric`, which keeps the BSDF symmetric for light tracing.
+pub(super) fn dielectric_f(
+    distribution: &TrowbridgeReitz,
+    eta: f64,
+    wo: &Vec3<f64>,
+    wi: &Vec3<f64>,
+) -> f64 {
+    let Some((wm, etap)) = half_vector(eta, wo, wi) else {
+        return 0.0;
+    };
+    let d = distribution.d(&wm);
+    let g = distribution.g(wo, wi);
+    let fresnel = fresnel_dielectric(wo.dot(&wm), eta);
+    if wi.z > 0.0 {
+        d * g * fresnel / (4.0 * wi.z * wo.z)
+    } else {
+        let denom = (wi.dot(&wm) + wo.dot(&wm) / etap).powi(2) * wi.z * wo.z;
+        d * (1.0 - fresnel) * g * (wi.dot(&wm) * wo.dot(&wm) / denom).abs()
+    }
+}
+
+/// Density of sampling `wi` with a visible normal followed by a Fresnel weighted choice between
+/// reflection and refraction.
+pub(super) fn dielectric_pdf(
+    distribution: &TrowbridgeReitz,
+    eta: f64,
+    wo: &Vec3<f64>,
+    wi: &Vec3<f64>,
+) -> f64 {
+    let Some((wm, etap)) = half_vector(eta, wo, wi) else {
+        return 0.0;
+    };
+    let fresnel = fresnel_dielectric(wo.dot(&wm), eta);
+    if wi.z > 0.0 {
+        distribution.pdf(wo, &wm) / (4.0 * wo.dot(&wm).abs()) * fresnel
+    } else {
+        let dwm_dwi = wi.dot(&wm).abs() / (wi.dot(&wm) + wo.dot(&wm) / etap).powi(2);
+        distribution.pdf(wo, &wm) * dwm_dwi * (1.0 - fresnel)
+    }
+}
+
+/// Generalised half vector for reflection or refraction, with the relative eta it used.
+fn half_vector(eta: f64, wo: &Vec3<f64>, wi: &Vec3<f64>) -> Option<(Vec3<f64>, f64)> {
+    if wi.z == 0.0 || wo.z <= 0.0 {
+        return None;
+    }
+    let etap = if wi.z > 0.0 { 1.0 } else { eta };
+    let wm = *wi * etap + *wo;
+    if wm.near_zero() {
+        return None;
+    }
+    let mut wm = wm.normalize();
+    if wm.z < 0.0 {
+        wm = -wm;
+    }
+    // discard back facing microfacets
+    if wm.dot(wi) * wi.z < 0.0 || wm.dot(wo) * wo.z < 0.0 {
+        return None;
+    }
+    Some((wm, etap))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ray::Ray;
+    use crate::sampler::IndependentSampler;
+    use crate::vec3::Point3;
+
+    #[test]
+    fn test_scatter_never_gains_energy() {
+        let material = RoughDielectric::new(1.5, 0.3);
+        let hit = HitRecord {
+            point: Point3::zero(),
+            normal: Vec3::new(0.0, 1.0, 0.0),
+            material: &material,
+            t: 1.0,
+            u: 0.0,
+            v: 0.0,
+            front_face: true,
+        };
+        let ray = Ray::new(Point3::new(-1.0, 1.0, 0.0), Vec3::new(1.0, -1.0, 0.0), 0.0);
+        let mut sampler = IndependentSampler::new(0);
+        for _ in 0..100 {
+            if let Some((_, attenuation)) = material.scatter(&ray, &hit, &mut sampler) {
+                assert!(attenuation.x <= 1.0);
+            }
+        }
+    }
+
+    #[test]
+    fn test_sample_matches_pdf() {
+        let material = RoughDielectric::new(1.5, 0.5);
+        let hit = HitRecord {
+            point: Point3::zero(),
+            normal: Vec3::new(0.0, 0.0, 1.0),
+            material: &material,
+            t: 1.0,
+            u: 0.0,
+            v: 0.0,
+            front_face: true,
+        };
+        let wo = Vec3::new(0.0, 0.6, 0.8);
+        // large uc always refracts
+        let sample = material.sample(&hit, &wo, 0.999, (0.3, 0.8)).unwrap();
+        assert!(sample.wi.z < 0.0);
+        let pdf = material.pdf(&hit, &wo, &sample.wi);
+        assert!((sample.pdf - pdf).abs() < 1e-9 * pdf);
+    }
+}
@@ -1,8 +1,12 @@
 use crate::ray::Ray;
-use crate::vec3::utils::random_in_unit_disk;
+use crate::sampler::Sampler;
+use crate::vec3::utils::sample_uniform_disk;
 use crate::vec3::{Point3, Vec3};
-use rand::Rng;
+use std::f64::consts::PI;
 
+/// Thin lens camera. Film positions `(s, t)` run from (0, 0) at the bottom left to (1, 1) at the
+/// top right of the viewport on the plane of focus.
+#[derive(Clone, Copy)]
 pub struct Camera {
     origin: Point3<f64>,
     horizontal: Vec3<f64>,
@@ -10,12 +14,26 @@ pub struct Camera {
     lower_left_corner: Point3<f64>,
     u: Vec3<f64>,
     v: Vec3<f64>,
+    w: Vec3<f64>,
     lens_radius: f64,
+    focus_dist: f64,
+    aspect_ratio: f64,
     time0: f64, // shutter open time
     time1: f64, // shutter close time
 }
 
+/// A point on the lens connected to a point in the scene, see `Camera::sample_importance`.
+#[derive(Debug, Copy, Clone)]
+pub struct ImportanceSample {
+    pub lens_point: Point3<f64>,
+    pub film: (f64, f64),
+    pub importance: f64,
+    /// Solid angle density of the direction from the scene point to `lens_point`.
+    pub pdf: f64,
+}
+
 impl Camera {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         origin: Point3<f64>,
         target: Point3<f64>,
@@ -49,27 +67,179 @@ impl Camera {
             lower_left_corner,
             u,
             v,
+            w,
             lens_radius,
+            focus_dist,
+            aspect_ratio,
             time0,
             time1,
         }
     }
 
-    pub fn get_ray(&self, s: f64, t: f64) -> Ray {
-        let rd = random_in_unit_disk() * self.lens_radius;
+    pub fn aspect_ratio(&self) -> f64 {
+        self.aspect_ratio
+    }
+
+    pub fn get_ray(&self, s: f64, t: f64, sampler: &mut dyn Sampler) -> Ray {
+        self.generate_ray(s, t, sampler.get_2d(), sampler.get_1d())
+    }
+
+    /// Ray through film position `(s, t)` from the lens point picked by `u_lens`, at the time
+    /// picked by `u_time`, all of which are uniform numbers in [0, 1).
+    pub fn generate_ray(&self, s: f64, t: f64, u_lens: (f64, f64), u_time: f64) -> Ray {
+        let rd = sample_uniform_disk(u_lens) * self.lens_radius;
         let offset = self.u * rd.x + self.v * rd.y;
-        let mut rng = rand::thread_rng();
         Ray::new(
             self.origin + offset,
             self.lower_left_corner + (self.horizontal * s) + (self.vertical * t)
                 - self.origin
                 - offset,
-            rng.gen_range(self.time0..self.time1),
+            self.sample_time(u_time),
         )
     }
+
+    /// Time within the shutter interval for a uniform number in [0, 1).
+    pub fn sample_time(&self, u: f64) -> f64 {
+        self.time0 + (self.time1 - self.time0) * u
+    }
+
+    /// Area of the lens, one for a pinhole so that its position density is a plain weight.
+    fn lens_area(&self) -> f64 {
+        if self.lens_radius > 0.0 {
+            PI * self.lens_radius * self.lens_radius
+        } else {
+            1.0
+        }
+    }
+
+    /// Area of the viewport scaled to a distance of one from the lens.
+    fn film_area(&self) -> f64 {
+        self.horizontal.length() * self.vertical.length() / (self.focus_dist * self.focus_dist)
+    }
+
+    /// Film position a ray leaving the lens passes through, if it is within the viewport.
+    fn film_position(&self, ray: &Ray) -> Option<(f64, f64)> {
+        let dir = ray.dir.normalize();
+        let cos_theta = -dir.dot(&self.w);
+        if cos_theta <= 0.0 {
+            return None;
+        }
+        let focus = ray.orig + dir * (self.focus_dist / cos_theta) - self.lower_left_corner;
+        let s = focus.dot(&self.horizontal) / self.horizontal.dot(&self.horizontal);
+        let t = focus.dot(&self.vertical) / self.vertical.dot(&self.vertical);
+        if !(0.0..=1.0).contains(&s) || !(0.0..=1.0).contains(&t) {
+            return None;
+        }
+        Some((s, t))
+    }
+
+    /// Importance emitted along a ray leaving the lens and the film position it belongs to,
+    /// normalised so that it integrates to one over the film and lens.
+    pub fn importance(&self, ray: &Ray) -> Option<(f64, (f64, f64))> {
+        let film = self.film_position(ray)?;
+        let cos_theta = -ray.dir.normalize().dot(&self.w);
+        let cos2_theta = cos_theta * cos_theta;
+        Some((
+            1.0 / (self.film_area() * self.lens_area() * cos2_theta * cos2_theta),
+            film,
+        ))
+    }
+
+    /// Densities of `generate_ray` producing a ray: over the lens area and over solid angle.
+    pub fn pdf_importance(&self, ray: &Ray) -> (f64, f64) {
+        if self.film_position(ray).is_none() {
+            return (0.0, 0.0);
+        }
+        let cos_theta = -ray.dir.normalize().dot(&self.w);
+        (
+            1.0 / self.lens_area(),
+            1.0 / (self.film_area() * cos_theta * cos_theta * cos_theta),
+        )
+    }
+
+    /// Samples a point on the lens to connect `point` to the camera, for tracing paths from the
+    /// lights.
+    pub fn sample_importance(
+        &self,
+        point: &Point3<f64>,
+        u: (f64, f64),
+    ) -> Option<ImportanceSample> {
+        let rd = sample_uniform_disk(u) * self.lens_radius;
+        let lens_point = self.origin + self.u * rd.x + self.v * rd.y;
+        let to_lens = lens_point - *point;
+        let dist = to_lens.length();
+        if dist == 0.0 {
+            return None;
+        }
+        let (importance, film) = self.importance(&Ray::new(lens_point, -to_lens, 0.0))?;
+        let cos_theta = (to_lens / dist).dot(&self.w).abs();
+        Some(ImportanceSample {
+            lens_point,
+            film,
+            importance,
+            pdf: dist * dist / (cos_theta * self.lens_area()),
+        })
+    }
 }
 
-// #[cfg(test)]
-// mod tests {
-//     use super::*;
-// }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn camera(aperture: f64) -> Camera {
+        Camera::new(
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            90.0,
+            2.0,
+            aperture,
+            2.0,
+            0.0,
+            1.0,
+        )
+    }
+
+    #[test]
+    fn test_film_position_round_trips() {
+        let camera = camera(0.5);
+        let ray = camera.generate_ray(0.25, 0.75, (0.3, 0.6), 0.0);
+        let (_, film) = camera.importance(&ray).unwrap();
+        assert!((film.0 - 0.25).abs() < 1e-9);
+        assert!((film.1 - 0.75).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_importance_sample_sees_point() {
+        let camera = camera(0.0);
+        let point = Point3::new(1.0, 0.5, -2.0);
+        let sample = camera.sample_importance(&point, (0.5, 0.5)).unwrap();
+        assert_eq!(sample.lens_point, Point3::zero());
+        // the point is on the plane of focus, a quarter of the way from the centre to the top
+        // right corner
+        assert!((sample.film.0 - 0.625).abs() < 1e-9);
+        assert!((sample.film.1 - 0.625).abs() < 1e-9);
+        let dist2 = point.dot(&point);
+        let cos_theta = 2.0 / dist2.sqrt();
+        assert!((sample.pdf - dist2 / cos_theta).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_importance_integrates_to_one() {
+        // integrate importance * cos over the solid angle of the film for a pinhole
+        let camera = camera(0.0);
+        let n = 200;
+        let mut sum = 0.0;
+        for i in 0..n {
+            for j in 0..n {
+                let (s, t) = ((i as f64 + 0.5) / n as f64, (j as f64 + 0.5) / n as f64);
+                let ray = camera.generate_ray(s, t, (0.0, 0.0), 0.0);
+                let (importance, _) = camera.importance(&ray).unwrap();
+                let (_, pdf) = camera.pdf_importance(&ray);
+                let cos_theta = -ray.dir.normalize().dot(&camera.w);
+                sum += importance * cos_theta / pdf;
+            }
+        }
+        assert!((sum / (n * n) as f64 - 1.0).abs() < 1e-6);
+    }
+}
@@ -2,16 +2,53 @@ use crate::material::Material;
 use crate::ray::Ray;
 use crate::vec3::{Point3, Vec3};
 
+#[derive(Clone, Copy)]
 pub struct HitRecord<'a> {
     pub point: Point3<f64>,
     pub normal: Vec3<f64>,
     pub material: &'a dyn Material,
     pub t: f64,
+    pub u: f64,
+    pub v: f64,
     pub front_face: bool,
 }
 
 pub trait Hittable: Sync {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord>;
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>>;
+
+    /// True for shapes with an emissive material that implement `sample` and `pdf_value`, which
+    /// is what makes them part of a scene's light list.
+    fn is_emissive(&self) -> bool {
+        false
+    }
+
+    /// Samples a point on the shape as seen from `origin`, returned as the hit record the ray
+    /// from `origin` to the point would produce (so `t` is 1), with the solid angle density of
+    /// the direction towards it.
+    fn sample(&self, _origin: &Point3<f64>, _u: (f64, f64)) -> Option<(HitRecord<'_>, f64)> {
+        None
+    }
+
+    /// Solid angle density of `sample` producing the direction `dir` from `origin`.
+    fn pdf_value(&self, _origin: &Point3<f64>, _dir: &Vec3<f64>) -> f64 {
+        0.0
+    }
+
+    /// Surface area of shapes that implement `sample_area`.
+    fn area(&self) -> f64 {
+        0.0
+    }
+
+    /// Samples a point uniformly over the surface, so with density `1 / area`. The record is the
+    /// one a ray arriving from outside would produce: front facing with the outward normal.
+    fn sample_area(&self, _u: (f64, f64)) -> Option<HitRecord<'_>> {
+        None
+    }
+
+    /// The materials of the shape in a fixed order, for numbering them.
+    fn materials(&self) -> Vec<&dyn Material> {
+        Vec::new()
+    }
 }
 
 pub struct HittableList {
@@ -33,19 +70,37 @@ impl HittableList {
     pub fn size(&self) -> usize {
         self.objects.len()
     }
-}
 
-impl Hittable for HittableList {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        let mut hit: Option<HitRecord> = None;
+    /// Closest hit along with the index in `objects` of the object that produced it.
+    pub fn hit_object(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<(usize, HitRecord<'_>)> {
+        let mut hit: Option<(usize, HitRecord)> = None;
         let mut closest = t_max;
 
-        for object in &self.objects {
+        for (index, object) in self.objects.iter().enumerate() {
             if let Some(temp) = object.hit(ray, t_min, closest) {
                 closest = temp.t;
-                hit = Some(temp);
+                hit = Some((index, temp));
             }
         }
         hit
     }
 }
+
+impl Default for HittableList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Hittable for HittableList {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        self.hit_object(ray, t_min, t_max).map(|(_, hit)| hit)
+    }
+
+    fn materials(&self) -> Vec<&dyn Material> {
+        self.objects
+            .iter()
+            .flat_map(|object| object.materials())
+            .collect()
+    }
+}
@@ -1,8 +1,12 @@
 pub mod camera;
 pub mod hittable;
+pub mod quad;
 pub mod rect;
 pub mod sphere;
+pub mod triangle;
 
 pub use camera::Camera;
+pub use quad::Quad;
 pub use rect::Rect;
 pub use sphere::Sphere;
+pub use triangle::Triangle;
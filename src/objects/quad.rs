@@ -0,0 +1,228 @@
+use crate::material::Material;
+use crate::objects::hittable::{HitRecord, Hittable, HittableList};
+use crate::ray::Ray;
+use crate::vec3::{Point3, Vec3};
+
+/// Parallelogram with corner `q` and edges `u` and `v`. The front face is on the side of
+/// `u x v`, which is the side an emissive quad lights.
+pub struct Quad<M: Material> {
+    q: Point3<f64>,
+    u: Vec3<f64>,
+    v: Vec3<f64>,
+    w: Vec3<f64>,
+    normal: Vec3<f64>,
+    d: f64,
+    area: f64,
+    material: M,
+}
+
+impl<M: Material> Quad<M> {
+    pub fn new(q: Point3<f64>, u: Vec3<f64>, v: Vec3<f64>, material: M) -> Self {
+        let n = u.cross(&v);
+        let normal = n.normalize();
+        Self {
+            q,
+            u,
+            v,
+            w: n / n.dot(&n),
+            normal,
+            d: normal.dot(&q),
+            area: n.length(),
+            material,
+        }
+    }
+
+    fn record(&self, dir: &Vec3<f64>, t: f64, point: Point3<f64>, a: f64, b: f64) -> HitRecord<'_> {
+        let mut normal = self.normal;
+        let mut front_face = true;
+        if dir.dot(&normal).is_sign_positive() {
+            normal = -normal;
+            front_face = false;
+        }
+        HitRecord {
+            t,
+            u: a,
+            v: b,
+            point,
+            normal,
+            front_face,
+            material: &self.material,
+        }
+    }
+}
+
+impl<M: Material> Hittable for Quad<M> {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let denom = self.normal.dot(&ray.dir);
+        // parallel to the plane
+        if denom.abs() < 1e-8 {
+            return None;
+        }
+        let t = (self.d - self.normal.dot(&ray.orig)) / denom;
+        if t < t_min || t_max < t {
+            return None;
+        }
+
+        // planar coordinates of the hit point along the edges
+        let point = ray.at(t);
+        let planar = point - self.q;
+        let a = self.w.dot(&planar.cross(&self.v));
+        let b = self.w.dot(&self.u.cross(&planar));
+        if !(0.0..=1.0).contains(&a) || !(0.0..=1.0).contains(&b) {
+            return None;
+        }
+        Some(self.record(&ray.dir, t, point, a, b))
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.material.is_emissive()
+    }
+
+    fn sample(&self, origin: &Point3<f64>, u: (f64, f64)) -> Option<(HitRecord<'_>, f64)> {
+        let point = self.q + self.u * u.0 + self.v * u.1;
+        let dir = point - *origin;
+        let dist2 = dir.dot(&dir);
+        let cos_theta = self.normal.dot(&dir).abs() / dist2.sqrt();
+        if cos_theta < 1e-8 {
+            return None;
+        }
+        let pdf = dist2 / (cos_theta * self.area);
+        Some((self.record(&dir, 1.0, point, u.0, u.1), pdf))
+    }
+
+    fn pdf_value(&self, origin: &Point3<f64>, dir: &Vec3<f64>) -> f64 {
+        let Some(hit) = self.hit(&Ray::new(*origin, *dir, 0.0), 0.001, f64::INFINITY) else {
+            return 0.0;
+        };
+        let dist2 = hit.t * hit.t * dir.dot(dir);
+        let cos_theta = self.normal.dot(dir).abs() / dir.length();
+        dist2 / (cos_theta * self.area)
+    }
+
+    fn area(&self) -> f64 {
+        self.area
+    }
+
+    fn sample_area(&self, u: (f64, f64)) -> Option<HitRecord<'_>> {
+        let point = self.q + self.u * u.0 + self.v * u.1;
+        Some(self.record(&-self.normal, 0.0, point, u.0, u.1))
+    }
+
+    fn materials(&self) -> Vec<&dyn Material> {
+        vec![&self.material]
+    }
+}
+
+/// Axis aligned box with opposite corners `a` and `b`, made of six outward facing quads.
+pub fn quad_box<M: Material + Clone + 'static>(
+    a: Point3<f64>,
+    b: Point3<f64>,
+    material: M,
+) -> HittableList {
+    let min = Point3::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z));
+    let max = Point3::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z));
+    let dx = Vec3::new(max.x - min.x, 0.0, 0.0);
+    let dy = Vec3::new(0.0, max.y - min.y, 0.0);
+    let dz = Vec3::new(0.0, 0.0, max.z - min.z);
+
+    let mut sides = HittableList::new();
+    sides
+        .push(Quad::new(
+            Point3::new(min.x, min.y, max.z),
+            dx,
+            dy,
+            material.clone(),
+        )) // front
+        .push(Quad::new(
+            Point3::new(max.x, min.y, max.z),
+            -dz,
+            dy,
+            material.clone(),
+        )) // right
+        .push(Quad::new(
+            Point3::new(max.x, min.y, min.z),
+            -dx,
+            dy,
+            material.clone(),
+        )) // back
+        .push(Quad::new(
+            Point3::new(min.x, min.y, min.z),
+            dz,
+            dy,
+            material.clone(),
+        )) // left
+        .push(Quad::new(
+            Point3::new(min.x, max.y, max.z),
+            dx,
+            -dz,
+            material.clone(),
+        )) // top
+        .push(Quad::new(
+            Point3::new(min.x, min.y, min.z),
+            dx,
+            dz,
+            material,
+        )); // bottom
+    sides
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+
+    fn unit_quad() -> Quad<Lambertian> {
+        Quad::new(
+            Point3::new(-0.5, -0.5, -1.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Lambertian::new(Vec3::zero()),
+        )
+    }
+
+    #[test]
+    fn test_hit() {
+        let quad = unit_quad();
+        let ray = Ray::new(Point3::zero(), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let hit = quad.hit(&ray, 0.0, f64::INFINITY).unwrap();
+        assert_eq!(hit.t, 1.0);
+        assert_eq!((hit.u, hit.v), (0.5, 0.5));
+        assert!(hit.front_face);
+        assert_eq!(hit.normal, Vec3::new(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn test_miss() {
+        let quad = unit_quad();
+        let ray = Ray::new(Point3::zero(), Vec3::new(1.0, 0.0, -1.0), 0.0);
+        assert!(quad.hit(&ray, 0.0, f64::INFINITY).is_none());
+    }
+
+    #[test]
+    fn test_box_faces_point_outwards() {
+        let sides = quad_box(
+            Point3::new(-1.0, -1.0, -1.0),
+            Point3::new(1.0, 1.0, 1.0),
+            Lambertian::new(Vec3::zero()),
+        );
+        for dir in [
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, -1.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+        ] {
+            let ray = Ray::new(dir * 3.0, -dir, 0.0);
+            let hit = sides.hit(&ray, 0.0, f64::INFINITY).unwrap();
+            assert!(hit.front_face);
+            assert_eq!(hit.t, 2.0);
+        }
+    }
+
+    #[test]
+    fn test_sample_matches_pdf() {
+        let quad = unit_quad();
+        let origin = Point3::new(0.2, 0.1, 0.0);
+        let (hit, pdf) = quad.sample(&origin, (0.3, 0.8)).unwrap();
+        let dir = hit.point - origin;
+        assert!((quad.pdf_value(&origin, &dir) - pdf).abs() < 1e-9);
+    }
+}
@@ -16,7 +16,7 @@ impl<M: Material> Rect<M> {
 }
 
 impl<M: Material> Hittable for Rect<M> {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
         let mut t_min = t_min;
         let mut t_max = t_max;
 
@@ -26,6 +26,8 @@ impl<M: Material> Hittable for Rect<M> {
         let o = <[f64; 3]>::from(ray.orig);
         let mut t0: f64;
         let mut t1: f64;
+        // axis of the slab the ray entered through last, its face is the one hit
+        let mut entry_axis = 0;
 
         for i in 0..3 {
             t0 = (va[i] - o[i]) * inv_d[i];
@@ -33,7 +35,10 @@ impl<M: Material> Hittable for Rect<M> {
             if inv_d[i] < 0.0 {
                 (t0, t1) = (t1, t0)
             }
-            t_min = f64::max(t0, t_min);
+            if t0 > t_min {
+                t_min = t0;
+                entry_axis = i;
+            }
             t_max = f64::min(t1, t_max);
             if t_max <= t_min {
                 return None;
@@ -42,20 +47,32 @@ impl<M: Material> Hittable for Rect<M> {
 
         let t = t_min;
         let point = ray.at(t);
-        let mut normal = point;
-        let mut front_face = true;
-        if ray.dir.dot(&normal).is_sign_positive() {
-            normal = -normal;
-            front_face = false;
-        }
+        let u = (point.x - va[0]) / (vb[0] - va[0]);
+        let v = (point.y - va[1]) / (vb[1] - va[1]);
+        // outward normal of the entered face points against the ray along that axis
+        let mut axis = [0.0; 3];
+        axis[entry_axis] = if inv_d[entry_axis] < 0.0 { 1.0 } else { -1.0 };
+        let outward_normal = Vec3::new(axis[0], axis[1], axis[2]);
+        let front_face = ray.dir.dot(&outward_normal) < 0.0;
+        let normal = if front_face {
+            outward_normal
+        } else {
+            -outward_normal
+        };
         Some(HitRecord {
             t,
+            u,
+            v,
             point,
             normal,
             front_face,
             material: &self.material,
         })
     }
+
+    fn materials(&self) -> Vec<&dyn Material> {
+        vec![&self.material]
+    }
 }
 
 #[cfg(test)]
@@ -76,4 +93,18 @@ mod tests {
         let ray = Ray::new(origin, center, 0.0);
         assert!(rect.hit(&ray, 0.0, f64::INFINITY).is_some())
     }
+
+    #[test]
+    fn test_hit_normal_is_entered_face() {
+        let rect = Rect::new(
+            Point3::new(-1.0, -1.0, -1.0),
+            Point3::new(1.0, 1.0, 1.0),
+            Lambertian::new(Vec3::zero()),
+        );
+        let ray = Ray::new(Point3::new(0.3, 5.0, 0.2), Vec3::new(0.0, -1.0, 0.0), 0.0);
+        let hit = rect.hit(&ray, 0.001, f64::INFINITY).unwrap();
+        assert_eq!(hit.normal, Vec3::new(0.0, 1.0, 0.0));
+        assert!(hit.front_face);
+        assert!((hit.t - 4.0).abs() < 1e-12);
+    }
 }
@@ -1,7 +1,17 @@
 use crate::material::Material;
 use crate::objects::hittable::{HitRecord, Hittable};
 use crate::ray::Ray;
-use crate::vec3::Point3;
+use crate::vec3::utils::sample_uniform_sphere;
+use crate::vec3::{Onb, Point3, Vec3};
+use std::f64::consts::PI;
+
+/// Texture coordinates of a point on the unit sphere: u is the angle around the y axis starting
+/// at -x, v is the angle from -y to +y.
+fn sphere_uv(p: &Point3<f64>) -> (f64, f64) {
+    let theta = (-p.y).acos();
+    let phi = (-p.z).atan2(p.x) + PI;
+    (phi / (2.0 * PI), theta / PI)
+}
 
 pub struct Sphere<M: Material> {
     center: Point3<f64>,
@@ -17,10 +27,55 @@ impl<M: Material> Sphere<M> {
             material,
         }
     }
+
+    /// Hit record for a point on the surface as seen from `origin`.
+    fn record(&self, origin: &Point3<f64>, outward_normal: Vec3<f64>) -> HitRecord<'_> {
+        let point = self.center + outward_normal * self.radius;
+        let dir = point - *origin;
+        let (u, v) = sphere_uv(&outward_normal);
+        let mut normal = outward_normal;
+        let mut front_face = true;
+        if dir.dot(&normal).is_sign_positive() {
+            normal = -normal;
+            front_face = false;
+        }
+        HitRecord {
+            t: 1.0,
+            u,
+            v,
+            point,
+            normal,
+            front_face,
+            material: &self.material,
+        }
+    }
+
+    /// Area density converted to solid angle, used when `origin` is inside the sphere.
+    fn area_pdf(&self, origin: &Point3<f64>, point: &Point3<f64>, normal: &Vec3<f64>) -> f64 {
+        let dir = *point - *origin;
+        let dist2 = dir.dot(&dir);
+        let cos_theta = normal.dot(&dir).abs() / dist2.sqrt();
+        if cos_theta == 0.0 {
+            return 0.0;
+        }
+        dist2 / (cos_theta * 4.0 * PI * self.radius * self.radius)
+    }
+
+    /// Solid angle density of uniformly sampling the cone subtended by the sphere.
+    fn cone_pdf(&self, dist2: f64) -> f64 {
+        let sin2_theta_max = self.radius * self.radius / dist2;
+        // avoid catastrophic cancellation for small or distant spheres
+        let one_minus_cos_theta_max = if sin2_theta_max < 0.00068523 {
+            sin2_theta_max / 2.0
+        } else {
+            1.0 - (1.0 - sin2_theta_max).max(0.0).sqrt()
+        };
+        1.0 / (2.0 * PI * one_minus_cos_theta_max)
+    }
 }
 
 impl<M: Material> Hittable for Sphere<M> {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
         let oc = ray.orig - self.center;
         let a = ray.dir.length() * ray.dir.length();
         let h = oc.dot(&ray.dir);
@@ -45,6 +100,7 @@ impl<M: Material> Hittable for Sphere<M> {
         let point = ray.at(t);
         // normal always points against the incident ray
         let mut normal = (point - self.center) / self.radius;
+        let (u, v) = sphere_uv(&normal);
         let mut front_face = true;
         if ray.dir.dot(&normal).is_sign_positive() {
             normal = -normal;
@@ -52,12 +108,76 @@ impl<M: Material> Hittable for Sphere<M> {
         }
         Some(HitRecord {
             t,
+            u,
+            v,
             point,
             normal,
             front_face,
             material: &self.material,
         })
     }
+
+    fn is_emissive(&self) -> bool {
+        self.material.is_emissive()
+    }
+
+    fn sample(&self, origin: &Point3<f64>, u: (f64, f64)) -> Option<(HitRecord<'_>, f64)> {
+        let to_center = self.center - *origin;
+        let dist2 = to_center.dot(&to_center);
+        let radius2 = self.radius * self.radius;
+
+        if dist2 <= radius2 {
+            let hit = self.record(origin, sample_uniform_sphere(u));
+            let pdf = self.area_pdf(origin, &hit.point, &hit.normal);
+            return if pdf > 0.0 { Some((hit, pdf)) } else { None };
+        }
+
+        // sample a direction in the cone towards the sphere and find the point it sees
+        let dist = dist2.sqrt();
+        let cos_theta_max = (1.0 - radius2 / dist2).max(0.0).sqrt();
+        let cos_theta = (1.0 - u.0) + u.0 * cos_theta_max;
+        let sin2_theta = (1.0 - cos_theta * cos_theta).max(0.0);
+        let ds = dist * cos_theta - (radius2 - dist2 * sin2_theta).max(0.0).sqrt();
+        let cos_alpha = ((dist2 + radius2 - ds * ds) / (2.0 * dist * self.radius)).clamp(-1.0, 1.0);
+        let sin_alpha = (1.0 - cos_alpha * cos_alpha).max(0.0).sqrt();
+        let phi = 2.0 * PI * u.1;
+
+        let frame = Onb::new(&to_center);
+        let outward_normal = frame.to_world(&Vec3::new(
+            -sin_alpha * phi.cos(),
+            -sin_alpha * phi.sin(),
+            -cos_alpha,
+        ));
+        Some((self.record(origin, outward_normal), self.cone_pdf(dist2)))
+    }
+
+    fn pdf_value(&self, origin: &Point3<f64>, dir: &Vec3<f64>) -> f64 {
+        let Some(hit) = self.hit(&Ray::new(*origin, *dir, 0.0), 0.001, f64::INFINITY) else {
+            return 0.0;
+        };
+        let to_center = self.center - *origin;
+        let dist2 = to_center.dot(&to_center);
+        if dist2 <= self.radius * self.radius {
+            self.area_pdf(origin, &hit.point, &hit.normal)
+        } else {
+            self.cone_pdf(dist2)
+        }
+    }
+
+    fn area(&self) -> f64 {
+        4.0 * PI * self.radius * self.radius
+    }
+
+    fn sample_area(&self, u: (f64, f64)) -> Option<HitRecord<'_>> {
+        let outward_normal = sample_uniform_sphere(u);
+        // seen from a point just outside along the normal
+        let outside = self.center + outward_normal * (2.0 * self.radius);
+        Some(self.record(&outside, outward_normal))
+    }
+
+    fn materials(&self) -> Vec<&dyn Material> {
+        vec![&self.material]
+    }
 }
 
 pub struct MovingSphere<M: Material> {
@@ -95,7 +215,7 @@ impl<M: Material> MovingSphere<M> {
 }
 
 impl<M: Material> Hittable for MovingSphere<M> {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
         let oc = ray.orig - self.center(ray.time);
         let a = ray.dir.length() * ray.dir.length();
         let h = oc.dot(&ray.dir);
@@ -120,6 +240,7 @@ impl<M: Material> Hittable for MovingSphere<M> {
         let point = ray.at(t);
         // normal always points against the incident ray
         let mut normal = (point - self.center(ray.time)) / self.radius;
+        let (u, v) = sphere_uv(&normal);
         let mut front_face = true;
         if ray.dir.dot(&normal).is_sign_positive() {
             normal = -normal;
@@ -127,12 +248,18 @@ impl<M: Material> Hittable for MovingSphere<M> {
         }
         Some(HitRecord {
             t,
+            u,
+            v,
             point,
             normal,
             front_face,
             material: &self.material,
         })
     }
+
+    fn materials(&self) -> Vec<&dyn Material> {
+        vec![&self.material]
+    }
 }
 
 #[cfg(test)]
@@ -150,4 +277,31 @@ mod tests {
         let ray = Ray::new(origin, center, 0.0);
         assert!(sphere.hit(&ray, 0.0, f64::INFINITY).is_some())
     }
+
+    #[test]
+    fn test_sample_is_visible_and_matches_pdf() {
+        let material = Lambertian::new(Vec3::zero());
+        let sphere = Sphere::new(Point3::new(0.0, 0.0, -3.0), 1.0, material);
+        let origin = Point3::zero();
+        for u in [(0.1, 0.2), (0.5, 0.5), (0.99, 0.7)] {
+            let (hit, pdf) = sphere.sample(&origin, u).unwrap();
+            assert!(hit.front_face);
+            let dir = hit.point - origin;
+            assert_eq!(hit.t, 1.0);
+            let t = sphere
+                .hit(&Ray::new(origin, dir, 0.0), 0.001, f64::INFINITY)
+                .unwrap()
+                .t;
+            assert!((t - 1.0).abs() < 1e-9);
+            assert!((sphere.pdf_value(&origin, &dir) - pdf).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_sphere_uv() {
+        let (u, v) = sphere_uv(&Point3::new(1.0, 0.0, 0.0));
+        assert_eq!((u, v), (0.5, 0.5));
+        let (_, v) = sphere_uv(&Point3::new(0.0, 1.0, 0.0));
+        assert_eq!(v, 1.0);
+    }
 }
@@ -0,0 +1,168 @@
+use crate::material::Material;
+use crate::objects::hittable::{HitRecord, Hittable};
+use crate::ray::Ray;
+use crate::vec3::{Point3, Vec3};
+
+/// Triangle with counter-clockwise vertices `a`, `b`, `c` around its front face. Texture
+/// coordinates are the barycentric weights of `b` and `c`.
+pub struct Triangle<M: Material> {
+    a: Point3<f64>,
+    ab: Vec3<f64>,
+    ac: Vec3<f64>,
+    normal: Vec3<f64>,
+    area: f64,
+    material: M,
+}
+
+impl<M: Material> Triangle<M> {
+    pub fn new(a: Point3<f64>, b: Point3<f64>, c: Point3<f64>, material: M) -> Self {
+        let ab = b - a;
+        let ac = c - a;
+        let n = ab.cross(&ac);
+        Self {
+            a,
+            ab,
+            ac,
+            normal: n.normalize(),
+            area: n.length() / 2.0,
+            material,
+        }
+    }
+
+    fn record(
+        &self,
+        dir: &Vec3<f64>,
+        t: f64,
+        point: Point3<f64>,
+        b1: f64,
+        b2: f64,
+    ) -> HitRecord<'_> {
+        let mut normal = self.normal;
+        let mut front_face = true;
+        if dir.dot(&normal).is_sign_positive() {
+            normal = -normal;
+            front_face = false;
+        }
+        HitRecord {
+            t,
+            u: b1,
+            v: b2,
+            point,
+            normal,
+            front_face,
+            material: &self.material,
+        }
+    }
+}
+
+impl<M: Material> Hittable for Triangle<M> {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        // Moller-Trumbore
+        let p = ray.dir.cross(&self.ac);
+        let det = self.ab.dot(&p);
+        if det.abs() < 1e-12 {
+            return None;
+        }
+        let inv_det = 1.0 / det;
+        let s = ray.orig - self.a;
+        let b1 = s.dot(&p) * inv_det;
+        if !(0.0..=1.0).contains(&b1) {
+            return None;
+        }
+        let q = s.cross(&self.ab);
+        let b2 = ray.dir.dot(&q) * inv_det;
+        if b2 < 0.0 || b1 + b2 > 1.0 {
+            return None;
+        }
+        let t = self.ac.dot(&q) * inv_det;
+        if t < t_min || t_max < t {
+            return None;
+        }
+        Some(self.record(&ray.dir, t, ray.at(t), b1, b2))
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.material.is_emissive()
+    }
+
+    fn sample(&self, origin: &Point3<f64>, u: (f64, f64)) -> Option<(HitRecord<'_>, f64)> {
+        // uniform barycentrics by folding the unit square
+        let su0 = u.0.sqrt();
+        let b1 = su0 * (1.0 - u.1);
+        let b2 = su0 * u.1;
+        let point = self.a + self.ab * b1 + self.ac * b2;
+
+        let dir = point - *origin;
+        let dist2 = dir.dot(&dir);
+        let cos_theta = self.normal.dot(&dir).abs() / dist2.sqrt();
+        if cos_theta < 1e-8 {
+            return None;
+        }
+        let pdf = dist2 / (cos_theta * self.area);
+        Some((self.record(&dir, 1.0, point, b1, b2), pdf))
+    }
+
+    fn pdf_value(&self, origin: &Point3<f64>, dir: &Vec3<f64>) -> f64 {
+        let Some(hit) = self.hit(&Ray::new(*origin, *dir, 0.0), 0.001, f64::INFINITY) else {
+            return 0.0;
+        };
+        let dist2 = hit.t * hit.t * dir.dot(dir);
+        let cos_theta = self.normal.dot(dir).abs() / dir.length();
+        dist2 / (cos_theta * self.area)
+    }
+
+    fn area(&self) -> f64 {
+        self.area
+    }
+
+    fn sample_area(&self, u: (f64, f64)) -> Option<HitRecord<'_>> {
+        let su0 = u.0.sqrt();
+        let b1 = su0 * (1.0 - u.1);
+        let b2 = su0 * u.1;
+        let point = self.a + self.ab * b1 + self.ac * b2;
+        Some(self.record(&-self.normal, 0.0, point, b1, b2))
+    }
+
+    fn materials(&self) -> Vec<&dyn Material> {
+        vec![&self.material]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+
+    fn triangle() -> Triangle<Lambertian> {
+        Triangle::new(
+            Point3::new(-1.0, -1.0, -1.0),
+            Point3::new(1.0, -1.0, -1.0),
+            Point3::new(0.0, 1.0, -1.0),
+            Lambertian::new(Vec3::zero()),
+        )
+    }
+
+    #[test]
+    fn test_hit() {
+        let ray = Ray::new(Point3::zero(), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let triangle = triangle();
+        let hit = triangle.hit(&ray, 0.0, f64::INFINITY).unwrap();
+        assert!((hit.t - 1.0).abs() < 1e-12);
+        assert!(hit.front_face);
+    }
+
+    #[test]
+    fn test_miss() {
+        let ray = Ray::new(Point3::zero(), Vec3::new(0.9, 0.9, -1.0), 0.0);
+        assert!(triangle().hit(&ray, 0.0, f64::INFINITY).is_none());
+    }
+
+    #[test]
+    fn test_sample_matches_pdf() {
+        let triangle = triangle();
+        let origin = Point3::new(0.0, 0.0, 1.0);
+        let (hit, pdf) = triangle.sample(&origin, (0.6, 0.3)).unwrap();
+        let dir = hit.point - origin;
+        assert!((triangle.pdf_value(&origin, &dir) - pdf).abs() < 1e-9);
+    }
+}
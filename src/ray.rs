@@ -1,5 +1,6 @@
 use crate::vec3::{Point3, Vec3};
 
+#[derive(Debug, Copy, Clone)]
 pub struct Ray {
     pub orig: Point3<f64>,
     pub dir: Vec3<f64>,
@@ -0,0 +1,384 @@
+use crate::film::{Aov, AovLayer, Film, FilmPixel, Filter, PixelStats};
+use crate::render::{AdaptiveSampling, RenderSettings, Tile, TileOrder};
+use crate::vec3::Vec3;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+
+const MAGIC: &[u8; 8] = b"RTCHKPT\0";
+const VERSION: u32 = 4;
+
+/// A render in progress saved to disk, so that it can be continued after a crash. Resuming with
+/// the same scene, integrator and sampler gives the same image as an uninterrupted render, as
+/// the sums and statistics of every pixel are stored exactly.
+pub struct Checkpoint {
+    pub settings: RenderSettings,
+    pub seed: u64,
+    /// Further settings the image depends on, by name, for the caller to restore. `None` for
+    /// those that weren't set, which have to stay unset.
+    pub extra: Vec<(String, Option<String>)>,
+    pub film: Film,
+}
+
+impl Checkpoint {
+    /// Writes the checkpoint to `path` through a temporary file, so that a crash while writing
+    /// leaves the previous checkpoint intact.
+    pub fn write(&self, path: &str) -> io::Result<()> {
+        let temp_path = format!("{}.tmp", path);
+        let mut out = BufWriter::new(File::create(&temp_path)?);
+        out.write_all(MAGIC)?;
+        write_u32(&mut out, VERSION)?;
+
+        let settings = &self.settings;
+        write_u32(&mut out, settings.width)?;
+        write_u32(&mut out, settings.height)?;
+        write_u32(&mut out, settings.samples_per_pixel)?;
+        out.write_all(&[
+            settings.antialiasing as u8,
+            settings.adaptive.is_some() as u8,
+        ])?;
+        let adaptive = settings.adaptive.unwrap_or(AdaptiveSampling {
+            min_samples: 0,
+            noise_threshold: 0.0,
+        });
+        write_u32(&mut out, adaptive.min_samples)?;
+        write_f64(&mut out, adaptive.noise_threshold)?;
+        write_filter(&mut out, settings.filter)?;
+        write_u32(&mut out, settings.tile_size)?;
+        let tile_order = match settings.tile_order {
+            TileOrder::Scanline => 0,
+            TileOrder::Spiral => 1,
+            TileOrder::Hilbert => 2,
+        };
+        out.write_all(&[tile_order, settings.crop.is_some() as u8])?;
+        let crop = settings.crop.unwrap_or(Tile {
+            x: 0,
+            y: 0,
+            width: 0,
+            height: 0,
+        });
+        for value in [crop.x, crop.y, crop.width, crop.height] {
+            write_u32(&mut out, value)?;
+        }
+        out.write_all(&self.seed.to_le_bytes())?;
+
+        write_u32(&mut out, self.extra.len() as u32)?;
+        for (name, value) in &self.extra {
+            write_string(&mut out, name)?;
+            out.write_all(&[value.is_some() as u8])?;
+            write_string(&mut out, value.as_deref().unwrap_or_default())?;
+        }
+
+        let film = &self.film;
+        write_filter(&mut out, film.filter)?;
+        write_u32(&mut out, film.samples_per_pixel)?;
+        write_u32(&mut out, film.aov_layers.len() as u32)?;
+        for layer in &film.aov_layers {
+            let aov = Aov::ALL.iter().position(|&aov| aov == layer.aov).unwrap();
+            out.write_all(&[aov as u8])?;
+            write_string(&mut out, &layer.name)?;
+        }
+        for value in &film.aovs {
+            write_vec3(&mut out, value)?;
+        }
+        for (pixel, splat) in film.pixels.iter().zip(&film.splats) {
+            write_vec3(&mut out, &pixel.sum)?;
+            write_f64(&mut out, pixel.weight_sum)?;
+            write_u32(&mut out, pixel.stats.count)?;
+            write_f64(&mut out, pixel.stats.mean)?;
+            write_f64(&mut out, pixel.stats.m2)?;
+            write_vec3(&mut out, splat)?;
+        }
+        out.into_inner()?.sync_all()?;
+        fs::rename(temp_path, path)
+    }
+
+    pub fn read(path: &str) -> io::Result<Self> {
+        let mut input = BufReader::new(File::open(path)?);
+        let mut magic = [0; 8];
+        input.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("not a render checkpoint"));
+        }
+        let version = read_u32(&mut input)?;
+        if version != VERSION {
+            return Err(invalid_data(&format!(
+                "checkpoint version {}, expected {}",
+                version, VERSION
+            )));
+        }
+
+        let width = read_u32(&mut input)?;
+        let height = read_u32(&mut input)?;
+        let samples_per_pixel = read_u32(&mut input)?;
+        let mut flags = [0; 2];
+        input.read_exact(&mut flags)?;
+        let adaptive = AdaptiveSampling {
+            min_samples: read_u32(&mut input)?,
+            noise_threshold: read_f64(&mut input)?,
+        };
+        let filter = read_filter(&mut input)?;
+        let tile_size = read_u32(&mut input)?;
+        let mut tile_flags = [0; 2];
+        input.read_exact(&mut tile_flags)?;
+        let tile_order = match tile_flags[0] {
+            0 => TileOrder::Scanline,
+            1 => TileOrder::Spiral,
+            2 => TileOrder::Hilbert,
+            _ => return Err(invalid_data("unknown tile order")),
+        };
+        let crop = Tile {
+            x: read_u32(&mut input)?,
+            y: read_u32(&mut input)?,
+            width: read_u32(&mut input)?,
+            height: read_u32(&mut input)?,
+        };
+        let settings = RenderSettings {
+            width,
+            height,
+            samples_per_pixel,
+            antialiasing: flags[0] != 0,
+            filter,
+            adaptive: (flags[1] != 0).then_some(adaptive),
+            tile_size,
+            tile_order,
+            crop: (tile_flags[1] != 0).then_some(crop),
+        };
+        let mut seed = [0; 8];
+        input.read_exact(&mut seed)?;
+        let seed = u64::from_le_bytes(seed);
+
+        let extra_count = read_u32(&mut input)?;
+        let extra = (0..extra_count)
+            .map(|_| {
+                let name = read_string(&mut input)?;
+                let mut is_set = [0];
+                input.read_exact(&mut is_set)?;
+                let value = read_string(&mut input)?;
+                Ok((name, (is_set[0] != 0).then_some(value)))
+            })
+            .collect::<io::Result<_>>()?;
+
+        let mut film = Film::new(width, height, read_filter(&mut input)?);
+        film.samples_per_pixel = read_u32(&mut input)?;
+        let layer_count = read_u32(&mut input)?;
+        for _ in 0..layer_count {
+            let mut aov = [0];
+            input.read_exact(&mut aov)?;
+            let Some(&aov) = Aov::ALL.get(aov[0] as usize) else {
+                return Err(invalid_data("unknown AOV"));
+            };
+            let name = read_string(&mut input)?;
+            film.aov_layers.push(AovLayer { aov, name });
+        }
+        film.aovs = (0..film.pixels.len() * film.aov_layers.len())
+            .map(|_| read_vec3(&mut input))
+            .collect::<io::Result<_>>()?;
+        for index in 0..film.pixels.len() {
+            film.pixels[index] = FilmPixel {
+                sum: read_vec3(&mut input)?,
+                weight_sum: read_f64(&mut input)?,
+                stats: PixelStats {
+                    count: read_u32(&mut input)?,
+                    mean: read_f64(&mut input)?,
+                    m2: read_f64(&mut input)?,
+                },
+            };
+            film.splats[index] = read_vec3(&mut input)?;
+        }
+        if input.read(&mut [0])? != 0 {
+            return Err(invalid_data("trailing data after checkpoint"));
+        }
+
+        Ok(Self {
+            settings,
+            seed,
+            extra,
+            film,
+        })
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn write_u32(out: &mut impl Write, value: u32) -> io::Result<()> {
+    out.write_all(&value.to_le_bytes())
+}
+
+fn write_f64(out: &mut impl Write, value: f64) -> io::Result<()> {
+    out.write_all(&value.to_le_bytes())
+}
+
+fn write_vec3(out: &mut impl Write, value: &Vec3<f64>) -> io::Result<()> {
+    write_f64(out, value.x)?;
+    write_f64(out, value.y)?;
+    write_f64(out, value.z)
+}
+
+/// Writes a filter as its kind and up to three parameters.
+fn write_filter(out: &mut impl Write, filter: Filter) -> io::Result<()> {
+    let (kind, parameters) = match filter {
+        Filter::Box { radius } => (0, [radius, 0.0, 0.0]),
+        Filter::Tent { radius } => (1, [radius, 0.0, 0.0]),
+        Filter::Gaussian { radius, sigma } => (2, [radius, sigma, 0.0]),
+        Filter::Mitchell { radius, b, c } => (3, [radius, b, c]),
+        Filter::Lanczos { radius, tau } => (4, [radius, tau, 0.0]),
+    };
+    out.write_all(&[kind])?;
+    parameters
+        .into_iter()
+        .try_for_each(|parameter| write_f64(out, parameter))
+}
+
+fn write_string(out: &mut impl Write, value: &str) -> io::Result<()> {
+    write_u32(out, value.len() as u32)?;
+    out.write_all(value.as_bytes())
+}
+
+fn read_u32(input: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    input.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_f64(input: &mut impl Read) -> io::Result<f64> {
+    let mut bytes = [0; 8];
+    input.read_exact(&mut bytes)?;
+    Ok(f64::from_le_bytes(bytes))
+}
+
+fn read_vec3(input: &mut impl Read) -> io::Result<Vec3<f64>> {
+    Ok(Vec3::new(
+        read_f64(input)?,
+        read_f64(input)?,
+        read_f64(input)?,
+    ))
+}
+
+fn read_filter(input: &mut impl Read) -> io::Result<Filter> {
+    let mut kind = [0];
+    input.read_exact(&mut kind)?;
+    let [radius, a, b] = [read_f64(input)?, read_f64(input)?, read_f64(input)?];
+    Ok(match kind[0] {
+        0 => Filter::Box { radius },
+        1 => Filter::Tent { radius },
+        2 => Filter::Gaussian { radius, sigma: a },
+        3 => Filter::Mitchell { radius, b: a, c: b },
+        4 => Filter::Lanczos { radius, tau: a },
+        _ => return Err(invalid_data("unknown filter")),
+    })
+}
+
+fn read_string(input: &mut impl Read) -> io::Result<String> {
+    let len = read_u32(input)?;
+    let mut bytes = Vec::new();
+    input.take(len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() != len as usize {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    String::from_utf8(bytes).map_err(|_| invalid_data("checkpoint setting is not UTF-8"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::integrator::tests::lit_scene;
+    use crate::integrator::PathTracer;
+    use crate::render::render_pass;
+    use crate::render::tests::camera;
+    use crate::sampler::{HaltonSampler, Sampler};
+
+    #[test]
+    fn test_resumed_render_matches_uninterrupted() {
+        let filter = Filter::Mitchell {
+            radius: 2.0,
+            b: 1.0 / 3.0,
+            c: 1.0 / 3.0,
+        };
+        let settings = RenderSettings {
+            width: 3,
+            height: 2,
+            samples_per_pixel: 12,
+            antialiasing: true,
+            filter,
+            adaptive: Some(AdaptiveSampling {
+                min_samples: 4,
+                noise_threshold: 0.1,
+            }),
+            tile_size: 2,
+            tile_order: TileOrder::Scanline,
+            crop: Some(Tile {
+                x: 1,
+                y: 0,
+                width: 2,
+                height: 2,
+            }),
+        };
+        let scene = lit_scene();
+        let integrator = PathTracer::new(5);
+        let new_sampler = || Box::new(HaltonSampler::new(3)) as Box<dyn Sampler>;
+        let pass = |film: &mut Film| {
+            render_pass(
+                &scene,
+                &camera(),
+                &integrator,
+                &new_sampler,
+                settings,
+                film,
+                6,
+                |_| {},
+            )
+        };
+        let aovs = [Aov::Albedo, Aov::Depth, Aov::LightGroups];
+        let new_film = || Film::new(3, 2, filter).with_aovs(&aovs, scene.light_groups.names());
+        let mut uninterrupted = new_film();
+        pass(&mut uninterrupted);
+        pass(&mut uninterrupted);
+
+        let mut film = new_film();
+        pass(&mut film);
+        let path = std::env::temp_dir().join("raytracer_test_checkpoint.bin");
+        let path = path.to_str().unwrap();
+        Checkpoint {
+            settings,
+            seed: 3,
+            extra: vec![
+                ("SCENE".to_string(), Some("cornell".to_string())),
+                ("SAMPLER".to_string(), None),
+            ],
+            film,
+        }
+        .write(path)
+        .unwrap();
+        let mut checkpoint = Checkpoint::read(path).unwrap();
+        fs::remove_file(path).unwrap();
+        assert_eq!(checkpoint.seed, 3);
+        assert_eq!(checkpoint.settings.samples_per_pixel, 12);
+        assert_eq!(checkpoint.settings.adaptive.unwrap().min_samples, 4);
+        assert_eq!(checkpoint.settings.tile_order, TileOrder::Scanline);
+        assert_eq!(checkpoint.settings.crop.unwrap().x, 1);
+        assert_eq!(checkpoint.extra[0].1.as_deref(), Some("cornell"));
+        assert_eq!(checkpoint.extra[1], ("SAMPLER".to_string(), None));
+        assert_eq!(checkpoint.film.samples_per_pixel(), 6);
+        assert_eq!(checkpoint.film.filter(), filter);
+        assert_eq!(checkpoint.film.aov_layers()[2].name, "light_0");
+
+        pass(&mut checkpoint.film);
+        let resumed = checkpoint.film.image();
+        let uninterrupted = uninterrupted.image();
+        assert_eq!(resumed.samples, uninterrupted.samples);
+        assert_eq!(resumed.pixels, uninterrupted.pixels);
+        assert_eq!(resumed.aovs, uninterrupted.aovs);
+    }
+
+    #[test]
+    fn test_rejects_other_files() {
+        let path = std::env::temp_dir().join("raytracer_test_not_a_checkpoint.bin");
+        let path = path.to_str().unwrap();
+        fs::write(path, b"P3\n1 1\n255\n0 0 0\n").unwrap();
+        let error = Checkpoint::read(path).err().unwrap();
+        fs::remove_file(path).unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,530 @@
+pub mod checkpoint;
+pub mod tile;
+
+pub use checkpoint::Checkpoint;
+pub use tile::{tiles, Tile, TileOrder};
+
+use crate::film::{add_aovs, Aov, AovImage, AovSample, Film, Filter, FilterSampler};
+use crate::integrator::Integrator;
+use crate::objects::Camera;
+use crate::sampler::Sampler;
+use crate::scene::Scene;
+use crate::vec3::Vec3;
+use rayon::prelude::*;
+
+/// Size and sampling of a rendered image.
+#[derive(Debug, Copy, Clone)]
+pub struct RenderSettings {
+    pub width: u32,
+    pub height: u32,
+    /// Samples per pixel, or the most any pixel takes with adaptive sampling.
+    pub samples_per_pixel: u32,
+    /// Spreads samples over each pixel and around it by the filter to antialias edges, rather
+    /// than through its centre.
+    pub antialiasing: bool,
+    /// Filter of the film `render` makes. `render_pass` takes the one of the film it's given.
+    pub filter: Filter,
+    pub adaptive: Option<AdaptiveSampling>,
+    /// Width and height of the square tiles the image is rendered in, in pixels.
+    pub tile_size: u32,
+    pub tile_order: TileOrder,
+    /// Window of pixels to render, in the coordinates tiles use. The rest of the image is left
+    /// black.
+    pub crop: Option<Tile>,
+}
+
+/// Stops sampling a pixel once its estimate is precise enough, so flat areas like the sky take
+/// few samples and noisy ones take up to the full budget.
+#[derive(Debug, Copy, Clone)]
+pub struct AdaptiveSampling {
+    /// Samples every pixel takes before its noise is judged.
+    pub min_samples: u32,
+    /// Standard error of a pixel's mean luminance relative to the mean at which it stops.
+    /// Means below 0.01 count as 0.01, so that near black pixels can converge.
+    pub noise_threshold: f64,
+}
+
+/// A rendered image and how many samples each of its pixels took.
+pub struct RenderOutput {
+    /// Mean radiance of each pixel, including splats, in columns from left to right, each from
+    /// the top row down, as `write_image` takes it.
+    pub pixels: Vec<Vec<Vec3<f64>>>,
+    /// Samples taken in each pixel, laid out like `pixels`.
+    pub samples: Vec<Vec<u32>>,
+    /// The film's AOV layers, in its order.
+    pub aovs: Vec<AovImage>,
+}
+
+impl RenderOutput {
+    /// Just the pixels in `window`, in the coordinates tiles use.
+    pub fn crop(&self, window: Tile) -> Self {
+        fn crop<T: Copy>(columns: &[Vec<T>], window: Tile) -> Vec<Vec<T>> {
+            columns[window.x as usize..(window.x + window.width) as usize]
+                .iter()
+                .map(|column| {
+                    // columns run from the top row down
+                    let top = column.len() - (window.y + window.height) as usize;
+                    column[top..top + window.height as usize].to_vec()
+                })
+                .collect()
+        }
+        Self {
+            pixels: crop(&self.pixels, window),
+            samples: crop(&self.samples, window),
+            aovs: self
+                .aovs
+                .iter()
+                .map(|aov| AovImage {
+                    layer: aov.layer.clone(),
+                    pixels: crop(&aov.pixels, window),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Renders an image by tracing samples through each pixel with `integrator`, calling `on_tile`
+/// as each tile of pixels is done.
+pub fn render(
+    scene: &Scene,
+    camera: &Camera,
+    integrator: &dyn Integrator,
+    new_sampler: &(dyn Fn() -> Box<dyn Sampler> + Sync),
+    settings: RenderSettings,
+    on_tile: impl Fn(&Tile) + Sync,
+) -> RenderOutput {
+    render_with_aovs(
+        scene,
+        camera,
+        integrator,
+        new_sampler,
+        settings,
+        &[],
+        on_tile,
+    )
+}
+
+/// Like `render`, also rendering `aovs`.
+pub fn render_with_aovs(
+    scene: &Scene,
+    camera: &Camera,
+    integrator: &dyn Integrator,
+    new_sampler: &(dyn Fn() -> Box<dyn Sampler> + Sync),
+    settings: RenderSettings,
+    aovs: &[Aov],
+    on_tile: impl Fn(&Tile) + Sync,
+) -> RenderOutput {
+    let mut film = Film::new(settings.width, settings.height, settings.filter)
+        .with_aovs(aovs, scene.light_groups.names())
+        .with_working_space(scene.working);
+    render_pass(
+        scene,
+        camera,
+        integrator,
+        new_sampler,
+        settings,
+        &mut film,
+        settings.samples_per_pixel,
+        on_tile,
+    );
+    film.image()
+}
+
+/// Adds up to `samples` more samples to every pixel of `film`, stopping at
+/// `settings.samples_per_pixel` in total or when adaptive sampling finds a pixel is done.
+/// Tiles are handed out to threads in `settings.tile_order` with a sampler from `new_sampler`
+/// each, and as samples only depend on the sampler's seed and their pixel and index, the image
+/// is the same however the work is scheduled and however it is split into passes. The film's
+/// AOVs are rendered too, with lighting AOVs from `Integrator::li_aovs`.
+#[allow(clippy::too_many_arguments)]
+pub fn render_pass(
+    scene: &Scene,
+    camera: &Camera,
+    integrator: &dyn Integrator,
+    new_sampler: &(dyn Fn() -> Box<dyn Sampler> + Sync),
+    settings: RenderSettings,
+    film: &mut Film,
+    samples: u32,
+    on_tile: impl Fn(&Tile) + Sync,
+) {
+    let RenderSettings {
+        width,
+        height,
+        samples_per_pixel,
+        antialiasing,
+        filter: _,
+        adaptive,
+        tile_size,
+        tile_order,
+        crop,
+    } = settings;
+    let end = samples_per_pixel.min(film.samples_per_pixel.saturating_add(samples));
+    film.samples_per_pixel = end;
+    let filter_sampler = FilterSampler::new(film.filter);
+    let window = crop.unwrap_or(Tile {
+        x: 0,
+        y: 0,
+        width,
+        height,
+    });
+    let tiles: Vec<Tile> = tiles(window.width, window.height, tile_size, tile_order)
+        .into_iter()
+        .map(|tile| Tile {
+            x: window.x + tile.x,
+            y: window.y + tile.y,
+            ..tile
+        })
+        .collect();
+
+    let layers = film.aov_layers.len();
+    let light_groups = scene.light_groups.names().len();
+
+    // bridged so that tiles are started in order as threads become free
+    let previous: &Film = film;
+    let mut rendered: Vec<_> = tiles
+        .iter()
+        .enumerate()
+        .par_bridge()
+        .map(|(index, tile)| {
+            let mut sampler = new_sampler();
+            let mut pixels = Vec::with_capacity((tile.width * tile.height) as usize);
+            let mut splats = Vec::new();
+            for i in tile.x..tile.x + tile.width {
+                for j in tile.y..tile.y + tile.height {
+                    let index = (i * height + j) as usize;
+                    let mut pixel = previous.pixels[index];
+                    let mut aovs = previous.aovs[index * layers..(index + 1) * layers].to_vec();
+                    while pixel.stats.count < end {
+                        if let Some(adaptive) = adaptive {
+                            if pixel.stats.count >= adaptive.min_samples
+                                && pixel.stats.relative_error() < adaptive.noise_threshold
+                            {
+                                break;
+                            }
+                        }
+                        sampler.start_pixel_sample((i, j), pixel.stats.count);
+                        let filter_sample = filter_sampler.sample(sampler.get_2d());
+                        let (offset, weight) = if antialiasing {
+                            (filter_sample.offset, filter_sample.weight)
+                        } else {
+                            ((0.0, 0.0), 1.0)
+                        };
+                        let u = (i as f64 + 0.5 + offset.0) / width as f64;
+                        let v = (j as f64 + 0.5 + offset.1) / height as f64;
+                        let ray = camera.get_ray(u, v, sampler.as_mut());
+                        let sample = if layers == 0 {
+                            integrator.li_splat(&ray, scene, sampler.as_mut(), &mut splats)
+                        } else {
+                            let mut aov_sample = AovSample::first_hit(&ray, scene, light_groups);
+                            let sample = integrator.li_aovs(
+                                &ray,
+                                scene,
+                                sampler.as_mut(),
+                                &mut splats,
+                                &mut aov_sample.lighting,
+                            );
+                            add_aovs(
+                                &previous.aov_layers,
+                                &mut aovs,
+                                &aov_sample.values(&previous.aov_layers),
+                                weight,
+                                pixel.stats.count == 0,
+                            );
+                            sample
+                        };
+                        pixel.add(sample, weight, previous.working);
+                    }
+                    pixels.push((index, pixel, aovs));
+                }
+            }
+            on_tile(tile);
+            (index, pixels, splats)
+        })
+        .collect();
+
+    // splats are added in tile order, so that their sums come out the same every time
+    rendered.sort_by_key(|&(index, _, _)| index);
+    for (_, pixels, splats) in rendered {
+        for (index, pixel, aovs) in pixels {
+            film.pixels[index] = pixel;
+            film.aovs[index * layers..(index + 1) * layers].copy_from_slice(&aovs);
+        }
+        for splat in splats {
+            film.add_splat_within(window, splat.film, splat.radiance);
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::integrator::tests::lit_scene;
+    use crate::integrator::{Bdpt, PathTracer};
+    use crate::objects::hittable::HittableList;
+    use crate::sampler::SobolSampler;
+    use crate::scene::Background;
+    use crate::vec3::Point3;
+    use std::sync::Mutex;
+
+    pub(crate) fn camera() -> Camera {
+        Camera::new(
+            Point3::new(0.0, 3.0, 0.0),
+            Point3::zero(),
+            Vec3::new(0.0, 0.0, -1.0),
+            60.0,
+            1.0,
+            0.0,
+            3.0,
+            0.0,
+            1.0,
+        )
+    }
+
+    fn render_with_seed(seed: u64) -> Vec<Vec<Vec3<f64>>> {
+        let settings = RenderSettings {
+            width: 6,
+            height: 4,
+            samples_per_pixel: 4,
+            antialiasing: true,
+            filter: Filter::Box { radius: 0.5 },
+            adaptive: None,
+            tile_size: 2,
+            tile_order: TileOrder::Hilbert,
+            crop: None,
+        };
+        // bdpt also splats, which must land the same way every time
+        render(
+            &lit_scene(),
+            &camera(),
+            &Bdpt::new(camera(), 5),
+            &|| Box::new(SobolSampler::new(4, seed)),
+            settings,
+            |_| {},
+        )
+        .pixels
+    }
+
+    fn same(a: &[Vec<Vec3<f64>>], b: &[Vec<Vec3<f64>>]) -> bool {
+        a.iter()
+            .flatten()
+            .zip(b.iter().flatten())
+            .all(|(a, b)| a == b)
+    }
+
+    #[test]
+    fn test_same_seed_renders_same_image() {
+        let image = render_with_seed(1);
+        assert_eq!(image.len(), 6);
+        assert!(image.iter().all(|column| column.len() == 4));
+        assert!(same(&image, &render_with_seed(1)));
+        assert!(!same(&image, &render_with_seed(2)));
+    }
+
+    #[test]
+    fn test_calls_back_once_per_tile() {
+        let settings = RenderSettings {
+            width: 7,
+            height: 5,
+            samples_per_pixel: 1,
+            antialiasing: false,
+            filter: Filter::Box { radius: 0.5 },
+            adaptive: None,
+            tile_size: 3,
+            tile_order: TileOrder::Spiral,
+            crop: None,
+        };
+        let done = Mutex::new(Vec::new());
+        render(
+            &lit_scene(),
+            &camera(),
+            &PathTracer::new(5),
+            &|| Box::new(SobolSampler::new(1, 0)),
+            settings,
+            |tile| done.lock().unwrap().push(*tile),
+        );
+        let mut done = done.into_inner().unwrap();
+        let mut expected = tiles(7, 5, 3, TileOrder::Spiral);
+        let key = |tile: &Tile| (tile.x, tile.y);
+        done.sort_by_key(key);
+        expected.sort_by_key(key);
+        assert_eq!(done, expected);
+    }
+
+    #[test]
+    fn test_crop_renders_only_the_window() {
+        let mut settings = RenderSettings {
+            width: 6,
+            height: 4,
+            samples_per_pixel: 2,
+            antialiasing: true,
+            filter: Filter::Box { radius: 0.5 },
+            adaptive: None,
+            tile_size: 4,
+            tile_order: TileOrder::Spiral,
+            crop: None,
+        };
+        let render = |settings| {
+            render(
+                &lit_scene(),
+                &camera(),
+                &PathTracer::new(5),
+                &|| Box::new(SobolSampler::new(2, 0)),
+                settings,
+                |_| {},
+            )
+        };
+        let full = render(settings);
+        let window = Tile {
+            x: 1,
+            y: 2,
+            width: 3,
+            height: 2,
+        };
+        settings.crop = Some(window);
+        let cropped = render(settings);
+
+        // the window's pixels are the same as in the full render, and the rest are black
+        assert_eq!(cropped.crop(window).pixels, full.crop(window).pixels);
+        let rendered = cropped.samples.iter().flatten().filter(|&&count| count > 0);
+        assert_eq!(rendered.count(), 6);
+        assert_eq!(cropped.crop(window).samples, vec![vec![2; 2]; 3]);
+        assert_eq!(cropped.pixels[0][3], Vec3::zero());
+        // the window is at the top, as tiles count rows from the bottom
+        assert_eq!(cropped.samples[1], vec![2, 2, 0, 0]);
+    }
+
+    #[test]
+    fn test_adaptive_sampling_stops_in_flat_areas() {
+        let settings = RenderSettings {
+            width: 4,
+            height: 4,
+            samples_per_pixel: 256,
+            antialiasing: true,
+            filter: Filter::Box { radius: 0.5 },
+            adaptive: Some(AdaptiveSampling {
+                min_samples: 8,
+                noise_threshold: 0.05,
+            }),
+            tile_size: 16,
+            tile_order: TileOrder::Spiral,
+            crop: None,
+        };
+        let output = render(
+            &lit_scene(),
+            &camera(),
+            &PathTracer::new(5),
+            &|| Box::new(SobolSampler::new(256, 0)),
+            settings,
+            |_| {},
+        );
+        let counts: Vec<u32> = output.samples.into_iter().flatten().collect();
+        assert!(counts.iter().all(|&count| (8..=256).contains(&count)));
+        // the ground is lit unevenly through soft shadows, so only some pixels need many samples
+        assert!(counts.iter().any(|&count| count < 256));
+        assert!(counts.iter().any(|&count| count > 8));
+
+        let flat = render(
+            &Scene::new(HittableList::new(), Background::sky()),
+            &camera(),
+            &PathTracer::new(5),
+            &|| Box::new(SobolSampler::new(256, 0)),
+            settings,
+            |_| {},
+        );
+        assert!(flat.samples.iter().flatten().all(|&count| count == 8));
+    }
+
+    #[test]
+    fn test_aovs_leave_beauty_unchanged() {
+        let settings = RenderSettings {
+            width: 4,
+            height: 3,
+            samples_per_pixel: 4,
+            antialiasing: true,
+            filter: Filter::Box { radius: 0.5 },
+            adaptive: None,
+            tile_size: 2,
+            tile_order: TileOrder::Spiral,
+            crop: None,
+        };
+        let render = |aovs: &[Aov]| {
+            render_with_aovs(
+                &lit_scene(),
+                &camera(),
+                &PathTracer::new(5),
+                &|| Box::new(SobolSampler::new(4, 0)),
+                settings,
+                aovs,
+                |_| {},
+            )
+        };
+        let plain = render(&[]);
+        let output = render(&[Aov::ObjectId, Aov::Normal, Aov::LightGroups]);
+        assert_eq!(output.pixels, plain.pixels);
+        assert!(plain.aovs.is_empty());
+        let names: Vec<&str> = output
+            .aovs
+            .iter()
+            .map(|aov| aov.layer.name.as_str())
+            .collect();
+        assert_eq!(names, ["object_id", "normal", "light_0"]);
+        // looking down at the ground, the first object
+        assert!(output.aovs[0].pixels.iter().flatten().all(|id| id.x == 1.0));
+        assert!(output.aovs[1].pixels.iter().flatten().all(|n| n.y > 0.99));
+        assert!(output.aovs[2]
+            .pixels
+            .iter()
+            .flatten()
+            .all(|light| light.x > 0.0));
+    }
+
+    #[test]
+    fn test_passes_add_up_to_single_render() {
+        let settings = RenderSettings {
+            width: 3,
+            height: 2,
+            samples_per_pixel: 8,
+            antialiasing: true,
+            filter: Filter::Box { radius: 0.5 },
+            adaptive: None,
+            tile_size: 2,
+            tile_order: TileOrder::Hilbert,
+            crop: None,
+        };
+        let scene = lit_scene();
+        let integrator = Bdpt::new(camera(), 5);
+        let new_sampler = || Box::new(SobolSampler::new(8, 0)) as Box<dyn Sampler>;
+        let single = render(
+            &scene,
+            &camera(),
+            &integrator,
+            &new_sampler,
+            settings,
+            |_| {},
+        );
+
+        let mut film = Film::new(3, 2, settings.filter);
+        for pass in 1..=3 {
+            render_pass(
+                &scene,
+                &camera(),
+                &integrator,
+                &new_sampler,
+                settings,
+                &mut film,
+                3,
+                |_| {},
+            );
+            assert_eq!(film.min_samples(), (3 * pass).min(8));
+        }
+        let progressive = film.image();
+        for (a, b) in single
+            .pixels
+            .iter()
+            .flatten()
+            .zip(progressive.pixels.iter().flatten())
+        {
+            assert!((*a - *b).length() < 1e-9 * (1.0 + a.length()));
+        }
+    }
+}
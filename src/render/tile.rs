@@ -0,0 +1,150 @@
+use std::cmp::Ordering;
+
+/// A rectangle of pixels rendered as one piece of work, in pixel coordinates from the bottom
+/// left of the image, as the camera takes them.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Tile {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Tile {
+    pub fn contains(&self, x: u32, y: u32) -> bool {
+        (self.x..self.x + self.width).contains(&x) && (self.y..self.y + self.height).contains(&y)
+    }
+}
+
+/// Order tiles are handed out in, which is the order they appear in as they finish.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TileOrder {
+    /// Rows of tiles from the top of the image down, each from left to right.
+    Scanline,
+    /// Rings of tiles from the centre of the image outwards, where the subject usually is.
+    Spiral,
+    /// Along a Hilbert curve, which keeps tiles rendered close in time close in the image, and
+    /// so the parts of the scene they see in cache.
+    Hilbert,
+}
+
+/// Splits a `width` by `height` image into tiles of up to `size` pixels square, in `order`.
+pub fn tiles(width: u32, height: u32, size: u32, order: TileOrder) -> Vec<Tile> {
+    let size = size.max(1);
+    let columns = width.div_ceil(size);
+    let rows = height.div_ceil(size);
+    let tile = |column: u32, row: u32| Tile {
+        x: column * size,
+        y: row * size,
+        width: size.min(width - column * size),
+        height: size.min(height - row * size),
+    };
+
+    // grid positions, rows counted from the top
+    let mut grid: Vec<(u32, u32)> = match order {
+        TileOrder::Scanline | TileOrder::Spiral => (0..rows)
+            .flat_map(|row| (0..columns).map(move |column| (column, row)))
+            .collect(),
+        TileOrder::Hilbert => {
+            let side = columns.max(rows).next_power_of_two();
+            (0..side as u64 * side as u64)
+                .map(|d| hilbert_point(side, d))
+                .filter(|&(column, row)| column < columns && row < rows)
+                .collect()
+        }
+    };
+    if order == TileOrder::Spiral {
+        // rings around the centre, each walked around by angle
+        let centre = ((columns - 1) as f64 / 2.0, (rows - 1) as f64 / 2.0);
+        let key = |&(column, row): &(u32, u32)| {
+            let dx = column as f64 - centre.0;
+            let dy = row as f64 - centre.1;
+            (dx.abs().max(dy.abs()).round(), dy.atan2(dx))
+        };
+        grid.sort_by(|a, b| {
+            let (a, b) = (key(a), key(b));
+            a.partial_cmp(&b).unwrap_or(Ordering::Equal)
+        });
+    }
+    grid.into_iter()
+        .map(|(column, row)| tile(column, rows - 1 - row))
+        .collect()
+}
+
+/// Point `d` along the Hilbert curve filling a `side` by `side` grid, `side` a power of two.
+fn hilbert_point(side: u32, mut d: u64) -> (u32, u32) {
+    let (mut x, mut y) = (0u64, 0u64);
+    let mut s = 1u64;
+    while s < side as u64 {
+        let rx = 1 & (d / 2);
+        let ry = 1 & (d ^ rx);
+        if ry == 0 {
+            if rx == 1 {
+                x = s - 1 - x;
+                y = s - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        x += s * rx;
+        y += s * ry;
+        d /= 4;
+        s *= 2;
+    }
+    (x as u32, y as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn covers_image_once(tiles: &[Tile], width: u32, height: u32) -> bool {
+        let mut covered = vec![0; (width * height) as usize];
+        for tile in tiles {
+            for i in tile.x..tile.x + tile.width {
+                for j in tile.y..tile.y + tile.height {
+                    covered[(i * height + j) as usize] += 1;
+                }
+            }
+        }
+        covered.iter().all(|&count| count == 1)
+    }
+
+    #[test]
+    fn test_every_order_covers_image_once() {
+        for order in [TileOrder::Scanline, TileOrder::Spiral, TileOrder::Hilbert] {
+            let tiles = tiles(37, 21, 8, order);
+            assert_eq!(tiles.len(), 5 * 3);
+            assert!(covers_image_once(&tiles, 37, 21));
+        }
+    }
+
+    #[test]
+    fn test_scanline_starts_top_left() {
+        let tiles = tiles(20, 20, 8, TileOrder::Scanline);
+        assert_eq!(
+            tiles[0],
+            Tile {
+                x: 0,
+                y: 16,
+                width: 8,
+                height: 4
+            }
+        );
+        assert_eq!(tiles[1].x, 8);
+    }
+
+    #[test]
+    fn test_spiral_starts_in_centre() {
+        let tiles = tiles(40, 40, 8, TileOrder::Spiral);
+        assert_eq!((tiles[0].x, tiles[0].y), (16, 16));
+    }
+
+    #[test]
+    fn test_hilbert_steps_to_neighbouring_tiles() {
+        let tiles = tiles(64, 64, 8, TileOrder::Hilbert);
+        for pair in tiles.windows(2) {
+            let step = pair[0].x.abs_diff(pair[1].x) + pair[0].y.abs_diff(pair[1].y);
+            assert_eq!(step, 8);
+        }
+    }
+}
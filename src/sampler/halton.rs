@@ -0,0 +1,108 @@
+use crate::sampler::{hash, mix_bits, permutation_element, to_unit, Sampler};
+
+const PRIMES: [u64; 64] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131, 137, 139, 149, 151, 157, 163, 167, 173, 179, 181, 191, 193,
+    197, 199, 211, 223, 227, 229, 233, 239, 241, 251, 257, 263, 269, 271, 277, 281, 283, 293, 307,
+    311,
+];
+
+/// The Halton sequence, with the samples of a pixel as its first points and dimension `d` as
+/// the radical inverse in the `d`th prime base. Digits are Owen scrambled differently for each
+/// pixel so that neighbouring pixels don't share a pattern. Dimensions past the table of
+/// primes are independent random numbers.
+pub struct HaltonSampler {
+    seed: u64,
+    pixel: (u32, u32),
+    index: u32,
+    dimension: usize,
+}
+
+impl HaltonSampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            pixel: (0, 0),
+            index: 0,
+            dimension: 0,
+        }
+    }
+
+    fn next(&mut self) -> f64 {
+        let h = hash(&[
+            self.pixel.0 as u64,
+            self.pixel.1 as u64,
+            self.dimension as u64,
+            self.seed,
+        ]);
+        let value = match PRIMES.get(self.dimension) {
+            Some(&base) => owen_scrambled_radical_inverse(base, self.index as u64, h),
+            None => to_unit(mix_bits(h ^ self.index as u64)),
+        };
+        self.dimension += 1;
+        value
+    }
+}
+
+/// The digits of `a` in `base` mirrored about the radix point, with each digit permuted
+/// depending on the digits before it.
+fn owen_scrambled_radical_inverse(base: u64, mut a: u64, hash: u64) -> f64 {
+    let inv_base = 1.0 / base as f64;
+    let mut inv_base_m = 1.0;
+    let mut reversed = 0u64;
+    // continue past the last non-zero digit, as zero digits are scrambled too
+    while 1.0 - inv_base_m < 1.0 {
+        let digit = a % base;
+        a /= base;
+        let digit_hash = mix_bits(hash ^ reversed) as u32;
+        let digit = permutation_element(digit as u32, base as u32, digit_hash) as u64;
+        reversed = reversed * base + digit;
+        inv_base_m *= inv_base;
+    }
+    (reversed as f64 * inv_base_m).min(1.0 - f64::EPSILON / 2.0)
+}
+
+impl Sampler for HaltonSampler {
+    fn start_pixel_sample(&mut self, pixel: (u32, u32), index: u32) {
+        self.pixel = pixel;
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        self.next()
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.next(), self.next())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_first_points_stratify_each_dimension() {
+        // the first 3^2 points in base 3 land one in each ninth of the unit interval
+        let mut sampler = HaltonSampler::new(0);
+        let mut ninths: Vec<u32> = (0..9)
+            .map(|index| {
+                sampler.start_pixel_sample((1, 2), index);
+                sampler.get_1d();
+                (sampler.get_1d() * 9.0) as u32
+            })
+            .collect();
+        ninths.sort();
+        assert_eq!(ninths, (0..9).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_pixels_are_scrambled_differently() {
+        let mut sampler = HaltonSampler::new(0);
+        sampler.start_pixel_sample((0, 0), 1);
+        let a = sampler.get_2d();
+        sampler.start_pixel_sample((0, 1), 1);
+        assert_ne!(a, sampler.get_2d());
+    }
+}
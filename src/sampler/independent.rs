@@ -0,0 +1,55 @@
+use crate::sampler::{hash, Sampler};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// Uniform random numbers with no stratification, from a generator reseeded for every pixel
+/// sample so renders are repeatable.
+pub struct IndependentSampler {
+    seed: u64,
+    rng: StdRng,
+}
+
+impl IndependentSampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl Sampler for IndependentSampler {
+    fn start_pixel_sample(&mut self, pixel: (u32, u32), index: u32) {
+        self.rng = StdRng::seed_from_u64(hash(&[
+            pixel.0 as u64,
+            pixel.1 as u64,
+            index as u64,
+            self.seed,
+        ]));
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        self.rng.gen()
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.rng.gen(), self.rng.gen())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pixel_samples_repeat() {
+        let mut a = IndependentSampler::new(1);
+        let mut b = IndependentSampler::new(1);
+        a.start_pixel_sample((3, 4), 5);
+        b.get_1d();
+        b.start_pixel_sample((3, 4), 5);
+        assert_eq!(a.get_2d(), b.get_2d());
+        b.start_pixel_sample((3, 4), 6);
+        assert_ne!(a.get_1d(), b.get_1d());
+    }
+}
@@ -0,0 +1,114 @@
+pub mod halton;
+pub mod independent;
+pub mod sobol;
+pub mod stratified;
+
+pub use halton::HaltonSampler;
+pub use independent::IndependentSampler;
+pub use sobol::SobolSampler;
+pub use stratified::StratifiedSampler;
+
+/// Source of the random numbers for one pixel sample at a time. Every call takes the next
+/// dimension of the sample, so samplers that spread the samples of a pixel well over their
+/// first dimensions do so for the camera ray and the first bounces.
+pub trait Sampler {
+    /// Starts sample `index` of the pixel at `pixel`, from its first dimension.
+    fn start_pixel_sample(&mut self, pixel: (u32, u32), index: u32);
+
+    /// The next dimension, uniform in [0, 1).
+    fn get_1d(&mut self) -> f64;
+
+    /// The next two dimensions, uniform over the unit square and spread well over it
+    /// together.
+    fn get_2d(&mut self) -> (f64, f64);
+}
+
+/// Scrambles the bits of `v` (the splitmix64 finaliser).
+fn mix_bits(mut v: u64) -> u64 {
+    v = (v ^ (v >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    v = (v ^ (v >> 27)).wrapping_mul(0x94d049bb133111eb);
+    v ^ (v >> 31)
+}
+
+/// Hash of a list of values, for deriving independent seeds from a render's seed.
+pub fn hash(values: &[u64]) -> u64 {
+    values
+        .iter()
+        .fold(0x9e3779b97f4a7c15, |h, &v| mix_bits(h ^ mix_bits(v)))
+}
+
+/// Uniform number in [0, 1) from the top 53 bits of a hash.
+fn to_unit(hash: u64) -> f64 {
+    (hash >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Element `i` of a random permutation of 0..`n` picked by `seed`, without storing the
+/// permutation (Kensler 2013).
+fn permutation_element(mut i: u32, n: u32, seed: u32) -> u32 {
+    let mut w = n.wrapping_sub(1);
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= seed;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= seed >> 16;
+        i ^= (i & w) >> 4;
+        i ^= seed >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= seed >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | seed >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < n {
+            return (i.wrapping_add(seed)) % n;
+        }
+    }
+}
+
+/// Owen scrambling of the bits of a fixed point number in [0, 1): each bit is flipped or not
+/// depending on the bits above it, which keeps the stratification of nets (Laine and Karras'
+/// hash).
+fn owen_scramble(v: u32, seed: u32) -> u32 {
+    let mut v = v.reverse_bits();
+    v ^= v.wrapping_mul(0x3d20adea);
+    v = v.wrapping_add(seed);
+    v = v.wrapping_mul((seed >> 16) | 1);
+    v ^= v.wrapping_mul(0x05526c56);
+    v ^= v.wrapping_mul(0x53a22864);
+    v.reverse_bits()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_permutation_element_is_a_permutation() {
+        for n in [1, 2, 7, 16, 100] {
+            let mut seen: Vec<u32> = (0..n).map(|i| permutation_element(i, n, 12345)).collect();
+            seen.sort();
+            assert_eq!(seen, (0..n).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn test_owen_scramble_keeps_strata() {
+        // scrambling 2^k points spaced 2^-k apart leaves one in each interval of that size
+        let mut intervals: Vec<u32> = (0..16u32)
+            .map(|i| owen_scramble(i << 28, 987) >> 28)
+            .collect();
+        intervals.sort();
+        assert_eq!(intervals, (0..16).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,119 @@
+use crate::sampler::{hash, owen_scramble, permutation_element, Sampler};
+
+/// Owen scrambled Sobol points, padded: every pair of dimensions takes the first two Sobol
+/// dimensions of the pixel's samples in a different random order, so every 2D projection
+/// used together is a (0, 2)-sequence. Stratification is best with a power of two samples per
+/// pixel.
+pub struct SobolSampler {
+    samples_per_pixel: u32,
+    seed: u64,
+    pixel: (u32, u32),
+    index: u32,
+    dimension: u32,
+}
+
+impl SobolSampler {
+    pub fn new(samples_per_pixel: u32, seed: u64) -> Self {
+        Self {
+            samples_per_pixel: samples_per_pixel.max(1),
+            seed,
+            pixel: (0, 0),
+            index: 0,
+            dimension: 0,
+        }
+    }
+
+    /// The current sample's index into the Sobol sequence for this dimension, and seeds for
+    /// scrambling its two coordinates.
+    fn next(&mut self) -> (u32, u64) {
+        let h = hash(&[
+            self.pixel.0 as u64,
+            self.pixel.1 as u64,
+            self.dimension as u64,
+            self.seed,
+        ]);
+        let index = permutation_element(
+            self.index % self.samples_per_pixel,
+            self.samples_per_pixel,
+            h as u32,
+        );
+        (index, h)
+    }
+}
+
+/// First Sobol dimension: the bits of the index mirrored about the radix point.
+fn sobol_0(index: u32) -> u32 {
+    index.reverse_bits()
+}
+
+/// Second Sobol dimension, whose direction numbers come from the polynomial x + 1.
+fn sobol_1(mut index: u32) -> u32 {
+    let mut v = 1u32 << 31;
+    let mut result = 0;
+    while index != 0 {
+        if index & 1 != 0 {
+            result ^= v;
+        }
+        index >>= 1;
+        v ^= v >> 1;
+    }
+    result
+}
+
+fn to_unit(v: u32) -> f64 {
+    v as f64 / (1u64 << 32) as f64
+}
+
+impl Sampler for SobolSampler {
+    fn start_pixel_sample(&mut self, pixel: (u32, u32), index: u32) {
+        self.pixel = pixel;
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let (index, h) = self.next();
+        self.dimension += 1;
+        to_unit(owen_scramble(sobol_0(index), (h >> 32) as u32))
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let (index, h) = self.next();
+        self.dimension += 2;
+        (
+            to_unit(owen_scramble(sobol_0(index), (h >> 32) as u32)),
+            to_unit(owen_scramble(sobol_1(index), (h >> 16) as u32)),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_second_dimension() {
+        let points: Vec<u32> = (0..4).map(|i| sobol_1(i) >> 30).collect();
+        assert_eq!(points, vec![0, 2, 3, 1]);
+    }
+
+    #[test]
+    fn test_pixel_samples_form_a_net() {
+        // 16 points of a (0, 2)-net have one in each cell of any 16 cell grid of intervals
+        let mut sampler = SobolSampler::new(16, 5);
+        let points: Vec<(f64, f64)> = (0..16)
+            .map(|index| {
+                sampler.start_pixel_sample((7, 8), index);
+                sampler.get_2d()
+            })
+            .collect();
+        for (columns, rows) in [(16, 1), (8, 2), (4, 4), (2, 8), (1, 16)] {
+            let mut cells: Vec<u32> = points
+                .iter()
+                .map(|(x, y)| (x * columns as f64) as u32 + columns * (y * rows as f64) as u32)
+                .collect();
+            cells.sort();
+            assert_eq!(cells, (0..16).collect::<Vec<_>>());
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use crate::sampler::{hash, mix_bits, permutation_element, to_unit, Sampler};
+
+/// Divides every dimension of a pixel into one stratum per sample, and every pair of
+/// dimensions into a grid with one cell per sample, with each sample landing in a different one.
+/// Strata are visited in a different random order for each pixel and dimension, and samples are
+/// placed at random within them when jittered, or at their centres otherwise.
+pub struct StratifiedSampler {
+    samples_per_pixel: u32,
+    /// Columns of the 2D grid, which has `samples_per_pixel / x_strata` rows.
+    x_strata: u32,
+    jitter: bool,
+    seed: u64,
+    pixel: (u32, u32),
+    index: u32,
+    dimension: u32,
+}
+
+impl StratifiedSampler {
+    pub fn new(samples_per_pixel: u32, jitter: bool, seed: u64) -> Self {
+        let samples_per_pixel = samples_per_pixel.max(1);
+        // the squarest grid with exactly one cell per sample
+        let x_strata = (1..=(samples_per_pixel as f64).sqrt() as u32)
+            .rev()
+            .find(|x| samples_per_pixel.is_multiple_of(*x))
+            .unwrap_or(1);
+        Self {
+            samples_per_pixel,
+            x_strata,
+            jitter,
+            seed,
+            pixel: (0, 0),
+            index: 0,
+            dimension: 0,
+        }
+    }
+
+    /// Hash of the current pixel and dimension, plus `extra`.
+    fn hash(&self, extra: u64) -> u64 {
+        hash(&[
+            self.pixel.0 as u64,
+            self.pixel.1 as u64,
+            self.dimension as u64,
+            self.seed,
+            extra,
+        ])
+    }
+
+    /// Stratum of the current sample and its offsets within it for the current dimension.
+    fn stratum(&self) -> (u32, f64, f64) {
+        let stratum = permutation_element(
+            self.index % self.samples_per_pixel,
+            self.samples_per_pixel,
+            self.hash(0) as u32,
+        );
+        let (dx, dy) = if self.jitter {
+            let h = self.hash(self.index as u64 + 1);
+            (to_unit(h), to_unit(mix_bits(h)))
+        } else {
+            (0.5, 0.5)
+        };
+        (stratum, dx, dy)
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_pixel_sample(&mut self, pixel: (u32, u32), index: u32) {
+        self.pixel = pixel;
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let (stratum, dx, _) = self.stratum();
+        self.dimension += 1;
+        (stratum as f64 + dx) / self.samples_per_pixel as f64
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let (stratum, dx, dy) = self.stratum();
+        self.dimension += 2;
+        let y_strata = self.samples_per_pixel / self.x_strata;
+        (
+            ((stratum % self.x_strata) as f64 + dx) / self.x_strata as f64,
+            ((stratum / self.x_strata) as f64 + dy) / y_strata as f64,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_samples_fill_every_stratum() {
+        let mut sampler = StratifiedSampler::new(12, true, 3);
+        let mut cells = Vec::new();
+        let mut strata = Vec::new();
+        for index in 0..12 {
+            sampler.start_pixel_sample((5, 6), index);
+            let (x, y) = sampler.get_2d();
+            // 12 samples make a 3 by 4 grid
+            cells.push((x * 3.0) as u32 + 3 * (y * 4.0) as u32);
+            strata.push((sampler.get_1d() * 12.0) as u32);
+        }
+        cells.sort();
+        strata.sort();
+        assert_eq!(cells, (0..12).collect::<Vec<_>>());
+        assert_eq!(strata, (0..12).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_unjittered_samples_are_centred() {
+        let mut sampler = StratifiedSampler::new(1, false, 0);
+        sampler.start_pixel_sample((0, 0), 0);
+        assert_eq!(sampler.get_2d(), (0.5, 0.5));
+        assert_eq!(sampler.get_1d(), 0.5);
+    }
+}
@@ -0,0 +1,176 @@
+use crate::light::{LightGroups, LightList};
+use crate::material::Material;
+use crate::objects::hittable::{HitRecord, Hittable, HittableList};
+use crate::ray::Ray;
+use crate::vec3::{ColorSpace, Point3, Vec3};
+use std::collections::HashMap;
+
+/// Radiance arriving from directions that miss every object.
+pub enum Background {
+    /// Gradient from the horizon colour up to the zenith colour.
+    Sky {
+        horizon: Vec3<f64>,
+        zenith: Vec3<f64>,
+    },
+    Color(Vec3<f64>),
+}
+
+impl Background {
+    /// White to light blue sky, in linear sRGB.
+    pub fn sky() -> Self {
+        Background::Sky {
+            horizon: Vec3::new(1.0, 1.0, 1.0),
+            zenith: Vec3::new(0.5, 0.7, 1.0),
+        }
+    }
+
+    /// True when no direction has any radiance, so that only the lights light the scene.
+    pub fn is_black(&self) -> bool {
+        match self {
+            Background::Sky { horizon, zenith } => horizon.near_zero() && zenith.near_zero(),
+            Background::Color(color) => color.near_zero(),
+        }
+    }
+
+    pub fn color(&self, dir: &Vec3<f64>) -> Vec3<f64> {
+        match self {
+            Background::Sky { horizon, zenith } => {
+                let unit_direction = dir.normalize();
+                let t = 0.5 * (unit_direction.y + 1.0);
+                *horizon * (1.0 - t) + (*zenith * t)
+            }
+            Background::Color(color) => *color,
+        }
+    }
+}
+
+/// Everything the renderer needs besides the camera: the objects, the lights found among them
+/// and the background.
+pub struct Scene {
+    pub world: HittableList,
+    pub lights: LightList,
+    pub light_groups: LightGroups,
+    pub background: Background,
+    /// Space the colours are in, which luminance is measured in.
+    pub working: ColorSpace,
+    /// Index of each material by `Material::key`, in the order the world lists them.
+    material_ids: HashMap<usize, usize>,
+}
+
+impl Scene {
+    pub fn new(world: HittableList, background: Background) -> Self {
+        let lights = LightList::new(&world);
+        let light_groups = LightGroups::new(&lights);
+        let mut material_ids = HashMap::new();
+        for material in world.materials() {
+            let next = material_ids.len();
+            material_ids.entry(material.key()).or_insert(next);
+        }
+        Self {
+            world,
+            lights,
+            light_groups,
+            background,
+            working: ColorSpace::Srgb,
+            material_ids,
+        }
+    }
+
+    /// Moves the `light`th light into the group `name`, see `LightGroups::assign`.
+    pub fn with_light_group(mut self, light: usize, name: &str) -> Self {
+        self.light_groups.assign(light, name);
+        self
+    }
+
+    pub fn with_working_space(mut self, working: ColorSpace) -> Self {
+        self.working = working;
+        self
+    }
+
+    /// Index of a material of the world, counting each once in the order the world lists them,
+    /// so that it's the same every time the scene is built.
+    pub fn material_id(&self, material: &dyn Material) -> Option<usize> {
+        self.material_ids.get(&material.key()).copied()
+    }
+
+    pub fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        self.world.hit(ray, t_min, t_max)
+    }
+
+    /// Closest hit and the index of the top level object it belongs to, see
+    /// `HittableList::hit_object`.
+    pub fn hit_object(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<(usize, HitRecord<'_>)> {
+        self.world.hit_object(ray, t_min, t_max)
+    }
+
+    /// Samples a point on one of the lights, see `LightList::sample`.
+    pub fn sample_light(
+        &self,
+        origin: &Point3<f64>,
+        u_light: f64,
+        u: (f64, f64),
+    ) -> Option<(usize, HitRecord<'_>, f64)> {
+        self.lights.sample(&self.world, origin, u_light, u)
+    }
+
+    pub fn light_pdf(&self, origin: &Point3<f64>, dir: &Vec3<f64>) -> f64 {
+        self.lights.pdf_value(&self.world, origin, dir)
+    }
+
+    /// True when nothing blocks the segment between two points, ignoring hits within 0.001 of
+    /// either end to avoid shadow acne.
+    pub fn unoccluded(&self, from: &Point3<f64>, to: &Point3<f64>, time: f64) -> bool {
+        let dir = *to - *from;
+        let dist = dir.length();
+        if dist <= 0.002 {
+            return true;
+        }
+        let ray = Ray::new(*from, dir / dist, time);
+        self.world.hit(&ray, 0.001, dist - 0.001).is_none()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::objects::Sphere;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_unoccluded() {
+        let mut world = HittableList::new();
+        world.push(Sphere::new(
+            Point3::zero(),
+            1.0,
+            Lambertian::new(Vec3::zero()),
+        ));
+        let scene = Scene::new(world, Background::sky());
+        let a = Point3::new(0.0, 0.0, 2.0);
+        assert!(!scene.unoccluded(&a, &Point3::new(0.0, 0.0, -2.0), 0.0));
+        assert!(scene.unoccluded(&a, &Point3::new(0.0, 2.0, 2.0), 0.0));
+        assert!(scene.unoccluded(&a, &Point3::new(0.0, 0.0, 1.0), 0.0));
+    }
+
+    #[test]
+    fn test_shared_materials_share_ids() {
+        let shared = Arc::new(Lambertian::new(Vec3::zero()));
+        let mut world = HittableList::new();
+        world
+            .push(Sphere::new(Point3::zero(), 1.0, shared.clone()))
+            .push(Sphere::new(
+                Point3::new(3.0, 0.0, 0.0),
+                1.0,
+                Lambertian::new(Vec3::zero()),
+            ))
+            .push(Sphere::new(Point3::new(6.0, 0.0, 0.0), 1.0, shared));
+        let scene = Scene::new(world, Background::sky());
+        let id = |x: f64| {
+            let ray = Ray::new(Point3::new(x, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+            scene.material_id(scene.hit(&ray, 0.001, f64::INFINITY).unwrap().material)
+        };
+        assert_eq!(id(0.0), Some(0));
+        assert_eq!(id(3.0), Some(1));
+        assert_eq!(id(6.0), Some(0));
+    }
+}
@@ -0,0 +1,195 @@
+pub mod rgb_table;
+
+pub use rgb_table::RgbToSpectrumTable;
+
+use crate::vec3::Vec3;
+use std::ops::{Add, AddAssign, Div, Mul, MulAssign};
+
+/// Visible wavelengths traced, in nanometres.
+pub const LAMBDA_MIN: f64 = 360.0;
+pub const LAMBDA_MAX: f64 = 830.0;
+
+/// Wavelengths carried by each path: a hero wavelength and others evenly spaced from it.
+pub const WAVELENGTHS: usize = 4;
+
+/// A spectral quantity at the wavelengths of a `SampledWavelengths`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SampledSpectrum(pub [f64; WAVELENGTHS]);
+
+impl SampledSpectrum {
+    pub fn constant(value: f64) -> Self {
+        Self([value; WAVELENGTHS])
+    }
+
+    pub fn max_component(&self) -> f64 {
+        self.0.iter().copied().fold(f64::MIN, f64::max)
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.0.iter().all(|&value| value == 0.0)
+    }
+}
+
+impl Add for SampledSpectrum {
+    type Output = Self;
+
+    fn add(mut self, rhs: Self) -> Self {
+        self += rhs;
+        self
+    }
+}
+
+impl AddAssign for SampledSpectrum {
+    fn add_assign(&mut self, rhs: Self) {
+        for (value, rhs) in self.0.iter_mut().zip(rhs.0) {
+            *value += rhs;
+        }
+    }
+}
+
+impl Mul for SampledSpectrum {
+    type Output = Self;
+
+    fn mul(mut self, rhs: Self) -> Self {
+        self *= rhs;
+        self
+    }
+}
+
+impl MulAssign for SampledSpectrum {
+    fn mul_assign(&mut self, rhs: Self) {
+        for (value, rhs) in self.0.iter_mut().zip(rhs.0) {
+            *value *= rhs;
+        }
+    }
+}
+
+impl Mul<f64> for SampledSpectrum {
+    type Output = Self;
+
+    fn mul(self, rhs: f64) -> Self {
+        Self(self.0.map(|value| value * rhs))
+    }
+}
+
+impl Div<f64> for SampledSpectrum {
+    type Output = Self;
+
+    fn div(self, rhs: f64) -> Self {
+        Self(self.0.map(|value| value / rhs))
+    }
+}
+
+/// The wavelengths a path carries and the density each was sampled with. Once a path passes
+/// through something dispersive only the hero wavelength, the first, can follow it, and the
+/// others are terminated by zeroing their densities.
+#[derive(Debug, Copy, Clone)]
+pub struct SampledWavelengths {
+    pub lambda: [f64; WAVELENGTHS],
+    pub pdf: [f64; WAVELENGTHS],
+}
+
+impl SampledWavelengths {
+    /// Hero wavelength sampling: one wavelength from `u` in proportion to how visible it is, and
+    /// the rest by rotating `u` evenly through [0, 1).
+    pub fn sample_visible(u: f64) -> Self {
+        let mut lambda = [0.0; WAVELENGTHS];
+        let mut pdf = [0.0; WAVELENGTHS];
+        for i in 0..WAVELENGTHS {
+            let u = (u + i as f64 / WAVELENGTHS as f64).fract();
+            lambda[i] = sample_visible_wavelength(u);
+            pdf[i] = visible_wavelength_pdf(lambda[i]);
+        }
+        Self { lambda, pdf }
+    }
+
+    pub fn hero(&self) -> f64 {
+        self.lambda[0]
+    }
+
+    pub fn secondary_terminated(&self) -> bool {
+        self.pdf[1..].iter().all(|&pdf| pdf == 0.0)
+    }
+
+    /// Keeps only the hero wavelength, which then stands for all of them.
+    pub fn terminate_secondary(&mut self) {
+        if self.secondary_terminated() {
+            return;
+        }
+        self.pdf[1..].fill(0.0);
+        self.pdf[0] /= WAVELENGTHS as f64;
+    }
+}
+
+/// Wavelength distributed roughly like the sensitivity of the eye (Radziszewski et al.), so
+/// that fewer samples go to wavelengths barely seen.
+fn sample_visible_wavelength(u: f64) -> f64 {
+    538.0 - 138.888889 * (0.85691062 - 1.82750197 * u).atanh()
+}
+
+fn visible_wavelength_pdf(lambda: f64) -> f64 {
+    if !(LAMBDA_MIN..=LAMBDA_MAX).contains(&lambda) {
+        return 0.0;
+    }
+    0.0039398042 / (0.0072 * (lambda - 538.0)).cosh().powi(2)
+}
+
+/// CIE 1931 colour matching functions at `lambda` nanometres, from the multi-lobe Gaussian fit
+/// of Wyman, Sloan and Shirley.
+pub fn cie_xyz(lambda: f64) -> Vec3<f64> {
+    let g = |mu: f64, sigma_below: f64, sigma_above: f64| {
+        let t = (lambda - mu)
+            / if lambda < mu {
+                sigma_below
+            } else {
+                sigma_above
+            };
+        (-0.5 * t * t).exp()
+    };
+    Vec3::new(
+        1.056 * g(599.8, 37.9, 31.0) + 0.362 * g(442.0, 16.0, 26.7) - 0.065 * g(501.1, 20.4, 26.2),
+        0.821 * g(568.8, 46.9, 40.5) + 0.286 * g(530.9, 16.3, 31.1),
+        1.217 * g(437.0, 11.8, 36.0) + 0.681 * g(459.0, 26.0, 13.8),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_visible_wavelength_pdf_integrates_to_one() {
+        let n = 4700;
+        let step = (LAMBDA_MAX - LAMBDA_MIN) / n as f64;
+        let integral: f64 = (0..n)
+            .map(|i| visible_wavelength_pdf(LAMBDA_MIN + (i as f64 + 0.5) * step) * step)
+            .sum();
+        assert!((integral - 1.0).abs() < 1e-3, "integral {}", integral);
+        for u in [0.0, 0.3, 0.999] {
+            let lambda = sample_visible_wavelength(u);
+            assert!((LAMBDA_MIN..=LAMBDA_MAX).contains(&lambda));
+        }
+    }
+
+    #[test]
+    fn test_hero_wavelengths_are_spread() {
+        let mut wavelengths = SampledWavelengths::sample_visible(0.1);
+        let mut sorted = wavelengths.lambda;
+        sorted.sort_by(f64::total_cmp);
+        assert!(sorted.windows(2).all(|pair| pair[1] - pair[0] > 10.0));
+        wavelengths.terminate_secondary();
+        assert!(wavelengths.secondary_terminated());
+        assert_eq!(
+            wavelengths.pdf[0],
+            visible_wavelength_pdf(wavelengths.hero()) / WAVELENGTHS as f64
+        );
+    }
+
+    #[test]
+    fn test_cie_y_peaks_in_green() {
+        let peak = (LAMBDA_MIN as u32..LAMBDA_MAX as u32)
+            .max_by(|&a, &b| cie_xyz(a as f64).y.total_cmp(&cie_xyz(b as f64).y))
+            .unwrap();
+        assert!((550..=560).contains(&peak), "peak {}", peak);
+    }
+}
@@ -0,0 +1,325 @@
+use crate::spectrum::{
+    cie_xyz, SampledSpectrum, SampledWavelengths, LAMBDA_MAX, LAMBDA_MIN, WAVELENGTHS,
+};
+use crate::vec3::color_space::chromatic_adaptation;
+use crate::vec3::{ColorSpace, Mat3, Vec3};
+use rayon::prelude::*;
+
+/// Smooth spectrum `s(c0 t^2 + c1 t + c2)` over the visible wavelengths `t` from 0 to 1,
+/// where the sigmoid `s` keeps it within [0, 1] (Jakob and Hanika).
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SigmoidPolynomial {
+    pub c: [f64; 3],
+}
+
+impl SigmoidPolynomial {
+    pub fn evaluate(&self, lambda: f64) -> f64 {
+        let t = (lambda - LAMBDA_MIN) / (LAMBDA_MAX - LAMBDA_MIN);
+        sigmoid(self.c[0] * t * t + self.c[1] * t + self.c[2])
+    }
+}
+
+fn sigmoid(x: f64) -> f64 {
+    if x.is_infinite() {
+        return if x > 0.0 { 1.0 } else { 0.0 };
+    }
+    0.5 + x / (2.0 * (1.0 + x * x).sqrt())
+}
+
+fn sigmoid_derivative(x: f64) -> f64 {
+    0.5 / (1.0 + x * x).powf(1.5)
+}
+
+/// Converts between RGB colours in a colour space and spectra. Reflectances are upsampled to
+/// the smooth spectra giving back their RGB under a white light, with the coefficients fitted
+/// in advance over a grid of colours and interpolated between. Lights are treated the same
+/// after scaling, taking an equal energy spectrum as white so that their colour is kept when
+/// spectra are converted back.
+pub struct RgbToSpectrumTable {
+    resolution: usize,
+    /// Brightness of the grid's slices, spaced more closely near black and white.
+    scale: Vec<f64>,
+    /// Coefficients by largest channel, brightness, then the other two channels relative to
+    /// the largest.
+    coefficients: Vec<[f64; 3]>,
+    xyz_to_rgb: Mat3,
+    /// Integral of the CIE Y matching function, the luminance of the equal energy white.
+    y_integral: f64,
+}
+
+/// Wavelength spacing the coefficients are fitted over, in nanometres.
+const FIT_STEP: f64 = 10.0;
+
+impl RgbToSpectrumTable {
+    /// Fits a table for `space` with `resolution` colours along each side of the grid.
+    pub fn new(space: ColorSpace, resolution: usize) -> Self {
+        let mut white = Vec3::zero();
+        for lambda in LAMBDA_MIN as u32..LAMBDA_MAX as u32 {
+            white += cie_xyz(lambda as f64 + 0.5);
+        }
+        let y_integral = white.y;
+        let white_xy = (
+            white.x / (white.x + white.y + white.z),
+            white.y / (white.x + white.y + white.z),
+        );
+        let xyz_to_rgb = space.from_xyz() * chromatic_adaptation(white_xy, space.white());
+
+        // what each fitted wavelength adds to the RGB of a spectrum
+        let steps = ((LAMBDA_MAX - LAMBDA_MIN) / FIT_STEP) as usize;
+        let fit_points: Vec<(f64, Vec3<f64>)> = (0..steps)
+            .map(|i| {
+                let t = (i as f64 + 0.5) / steps as f64;
+                let lambda = LAMBDA_MIN + t * (LAMBDA_MAX - LAMBDA_MIN);
+                (t, xyz_to_rgb * cie_xyz(lambda) * (FIT_STEP / y_integral))
+            })
+            .collect();
+
+        let scale: Vec<f64> = (0..resolution)
+            .map(|k| smoothstep(smoothstep(k as f64 / (resolution - 1) as f64)))
+            .collect();
+        let rows: Vec<Vec<(usize, [f64; 3])>> = (0..3 * resolution)
+            .into_par_iter()
+            .map(|row| {
+                let (largest, j) = (row / resolution, row % resolution);
+                let mut fitted = Vec::new();
+                for i in 0..resolution {
+                    let x = i as f64 / (resolution - 1) as f64;
+                    let y = j as f64 / (resolution - 1) as f64;
+                    let rgb = |z: f64| {
+                        let mut rgb = [0.0; 3];
+                        rgb[largest] = z;
+                        rgb[(largest + 1) % 3] = x * z;
+                        rgb[(largest + 2) % 3] = y * z;
+                        Vec3::new(rgb[0], rgb[1], rgb[2])
+                    };
+                    // fitting is easiest at middling brightness, so start there and work
+                    // outwards, starting each fit from its neighbour's coefficients
+                    let start = resolution / 5;
+                    let mut c = [0.0; 3];
+                    for (k, &z) in scale.iter().enumerate().skip(start) {
+                        c = fit(&fit_points, rgb(z), c);
+                        fitted.push((index(resolution, largest, k, j, i), c));
+                    }
+                    let mut c = fitted[fitted.len() - (resolution - start)].1;
+                    for (k, &z) in scale.iter().enumerate().take(start).rev() {
+                        c = fit(&fit_points, rgb(z), c);
+                        fitted.push((index(resolution, largest, k, j, i), c));
+                    }
+                }
+                fitted
+            })
+            .collect();
+        let mut coefficients = vec![[0.0; 3]; 3 * resolution * resolution * resolution];
+        for (i, c) in rows.into_iter().flatten() {
+            coefficients[i] = c;
+        }
+
+        Self {
+            resolution,
+            scale,
+            coefficients,
+            xyz_to_rgb,
+            y_integral,
+        }
+    }
+
+    /// The smooth reflectance spectrum of `rgb`, each channel clamped to [0, 1].
+    pub fn reflectance(&self, rgb: Vec3<f64>) -> SigmoidPolynomial {
+        let rgb = [rgb.x, rgb.y, rgb.z].map(|value| value.clamp(0.0, 1.0));
+        if rgb[0] == rgb[1] && rgb[1] == rgb[2] {
+            // grey is a constant, which the sigmoid gives exactly
+            let v = rgb[0];
+            return SigmoidPolynomial {
+                c: [0.0, 0.0, (v - 0.5) / (v * (1.0 - v)).sqrt()],
+            };
+        }
+        let largest = if rgb[0] > rgb[1] {
+            if rgb[0] > rgb[2] {
+                0
+            } else {
+                2
+            }
+        } else if rgb[1] > rgb[2] {
+            1
+        } else {
+            2
+        };
+        let z = rgb[largest];
+        let last = (self.resolution - 1) as f64;
+        let x = rgb[(largest + 1) % 3] / z * last;
+        let y = rgb[(largest + 2) % 3] / z * last;
+        let xi = (x as usize).min(self.resolution - 2);
+        let yi = (y as usize).min(self.resolution - 2);
+        let zi = self
+            .scale
+            .partition_point(|&scale| scale <= z)
+            .clamp(1, self.resolution - 1)
+            - 1;
+        let (dx, dy) = (x - xi as f64, y - yi as f64);
+        let dz = (z - self.scale[zi]) / (self.scale[zi + 1] - self.scale[zi]);
+
+        let mut c = [0.0; 3];
+        for (k, wz) in [(zi, 1.0 - dz), (zi + 1, dz)] {
+            for (j, wy) in [(yi, 1.0 - dy), (yi + 1, dy)] {
+                for (i, wx) in [(xi, 1.0 - dx), (xi + 1, dx)] {
+                    let corner = self.coefficients[index(self.resolution, largest, k, j, i)];
+                    for (c, corner) in c.iter_mut().zip(corner) {
+                        *c += wx * wy * wz * corner;
+                    }
+                }
+            }
+        }
+        SigmoidPolynomial { c }
+    }
+
+    /// Spectrum of `rgb` at `wavelengths`. Colours brighter than one, like lights, are scaled
+    /// down to be upsampled and the spectrum scaled back up.
+    pub fn spectrum(&self, rgb: Vec3<f64>, wavelengths: &SampledWavelengths) -> SampledSpectrum {
+        let largest = rgb.max_component();
+        if largest <= 0.0 {
+            return SampledSpectrum::constant(0.0);
+        }
+        let scale = if largest <= 1.0 { 1.0 } else { 2.0 * largest };
+        let polynomial = self.reflectance(rgb / scale);
+        SampledSpectrum(
+            wavelengths
+                .lambda
+                .map(|lambda| scale * polynomial.evaluate(lambda)),
+        )
+    }
+
+    /// RGB of a radiance estimate at `wavelengths`, averaging the wavelengths still carried.
+    pub fn to_rgb(&self, spectrum: SampledSpectrum, wavelengths: &SampledWavelengths) -> Vec3<f64> {
+        let mut xyz = Vec3::zero();
+        for i in 0..WAVELENGTHS {
+            if wavelengths.pdf[i] > 0.0 {
+                xyz += cie_xyz(wavelengths.lambda[i]) * (spectrum.0[i] / wavelengths.pdf[i]);
+            }
+        }
+        self.xyz_to_rgb * xyz / (WAVELENGTHS as f64 * self.y_integral)
+    }
+}
+
+fn smoothstep(x: f64) -> f64 {
+    x * x * (3.0 - 2.0 * x)
+}
+
+fn index(resolution: usize, largest: usize, k: usize, j: usize, i: usize) -> usize {
+    ((largest * resolution + k) * resolution + j) * resolution + i
+}
+
+/// RGB of the spectrum with coefficients `c`, and its derivatives by each coefficient.
+fn fitted_rgb(fit_points: &[(f64, Vec3<f64>)], c: [f64; 3]) -> (Vec3<f64>, [Vec3<f64>; 3]) {
+    let mut rgb = Vec3::zero();
+    let mut derivatives = [Vec3::zero(); 3];
+    for &(t, weight) in fit_points {
+        let x = c[0] * t * t + c[1] * t + c[2];
+        rgb += weight * sigmoid(x);
+        let slope = sigmoid_derivative(x);
+        derivatives[0] += weight * (slope * t * t);
+        derivatives[1] += weight * (slope * t);
+        derivatives[2] += weight * slope;
+    }
+    (rgb, derivatives)
+}
+
+/// Levenberg-Marquardt iterations from `c` towards coefficients whose spectrum has the RGB
+/// `target`. Colours on the edge of the grid, with a channel of zero, can only be approached
+/// by ever sharper spectra, so large coefficients are penalised slightly to keep them finite
+/// and their neighbours' interpolation sensible.
+fn fit(fit_points: &[(f64, Vec3<f64>)], target: Vec3<f64>, mut c: [f64; 3]) -> [f64; 3] {
+    const PENALTY: f64 = 1e-9;
+    let objective = |rgb: Vec3<f64>, c: [f64; 3]| {
+        let residual = rgb - target;
+        residual.dot(&residual) + PENALTY * (c[0] * c[0] + c[1] * c[1] + c[2] * c[2])
+    };
+    let (mut rgb, mut derivatives) = fitted_rgb(fit_points, c);
+    let mut error = objective(rgb, c);
+    let mut damping = 1e-3;
+    for _ in 0..50 {
+        let residual = rgb - target;
+        if residual.length() < 1e-5 || damping > 1e6 {
+            break;
+        }
+        // normal equations, with the penalty's gradient and curvature
+        let mut normal = [[0.0; 3]; 3];
+        for (i, row) in normal.iter_mut().enumerate() {
+            for (k, value) in row.iter_mut().enumerate() {
+                *value = derivatives[i].dot(&derivatives[k]);
+            }
+            row[i] += PENALTY + damping;
+        }
+        let gradient = Vec3::new(
+            derivatives[0].dot(&residual) + PENALTY * c[0],
+            derivatives[1].dot(&residual) + PENALTY * c[1],
+            derivatives[2].dot(&residual) + PENALTY * c[2],
+        );
+        let step = Mat3::new(normal).inverse() * gradient;
+        let next = [c[0] - step.x, c[1] - step.y, c[2] - step.z];
+        let (next_rgb, next_derivatives) = fitted_rgb(fit_points, next);
+        let next_error = objective(next_rgb, next);
+        if next_error < error {
+            (c, rgb, derivatives, error) = (next, next_rgb, next_derivatives, next_error);
+            damping /= 3.0;
+        } else {
+            damping *= 4.0;
+        }
+    }
+    c
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// RGB of a reflectance under the equal energy white, integrated finely.
+    fn reflected_rgb(table: &RgbToSpectrumTable, polynomial: SigmoidPolynomial) -> Vec3<f64> {
+        let mut xyz = Vec3::zero();
+        for lambda in LAMBDA_MIN as u32..LAMBDA_MAX as u32 {
+            let lambda = lambda as f64 + 0.5;
+            xyz += cie_xyz(lambda) * polynomial.evaluate(lambda);
+        }
+        table.xyz_to_rgb * xyz / table.y_integral
+    }
+
+    #[test]
+    fn test_reflectances_round_trip() {
+        let table = RgbToSpectrumTable::new(ColorSpace::Srgb, 16);
+        for rgb in [
+            Vec3::new(0.65, 0.05, 0.05),
+            Vec3::new(0.12, 0.45, 0.15),
+            Vec3::new(0.2, 0.3, 0.8),
+            Vec3::new(0.73, 0.73, 0.73),
+        ] {
+            let polynomial = table.reflectance(rgb);
+            let round_trip = reflected_rgb(&table, polynomial);
+            assert!(
+                (round_trip - rgb).length() < 0.02,
+                "{:?} {:?}",
+                rgb,
+                round_trip
+            );
+            for lambda in [400.0, 550.0, 700.0] {
+                assert!((0.0..=1.0).contains(&polynomial.evaluate(lambda)));
+            }
+        }
+    }
+
+    #[test]
+    fn test_white_light_stays_white() {
+        let table = RgbToSpectrumTable::new(ColorSpace::AcesCg, 8);
+        // many wavelength samples of a white light average back to its RGB
+        let n = 1000;
+        let mut rgb = Vec3::zero();
+        for i in 0..n {
+            let wavelengths = SampledWavelengths::sample_visible((i as f64 + 0.5) / n as f64);
+            let light = table.spectrum(Vec3::new(4.0, 4.0, 4.0), &wavelengths);
+            rgb += table.to_rgb(light, &wavelengths) / n as f64;
+        }
+        assert!(
+            (rgb - Vec3::new(4.0, 4.0, 4.0)).length() < 0.05,
+            "{:?}",
+            rgb
+        );
+    }
+}
@@ -0,0 +1,153 @@
+use crate::vec3::{ColorSpace, Point3, Vec3};
+use image::ImageResult;
+
+/// A value that varies over a surface, looked up by texture coordinates and hit point.
+/// Constants implement it directly so a plain `f64` or colour can be passed anywhere a texture is
+/// expected.
+pub trait Texture<T>: Sync {
+    fn value(&self, u: f64, v: f64, p: &Point3<f64>) -> T;
+}
+
+impl Texture<f64> for f64 {
+    fn value(&self, _u: f64, _v: f64, _p: &Point3<f64>) -> f64 {
+        *self
+    }
+}
+
+impl Texture<Vec3<f64>> for Vec3<f64> {
+    fn value(&self, _u: f64, _v: f64, _p: &Point3<f64>) -> Vec3<f64> {
+        *self
+    }
+}
+
+/// Solid 3D checker pattern alternating between two values every `scale` world units.
+pub struct Checker<T> {
+    scale: f64,
+    even: T,
+    odd: T,
+}
+
+impl<T> Checker<T> {
+    pub fn new(scale: f64, even: T, odd: T) -> Self {
+        Self { scale, even, odd }
+    }
+}
+
+impl<T: Copy + Sync> Texture<T> for Checker<T> {
+    fn value(&self, _u: f64, _v: f64, p: &Point3<f64>) -> T {
+        let sum =
+            (p.x / self.scale).floor() + (p.y / self.scale).floor() + (p.z / self.scale).floor();
+        if (sum as i64).rem_euclid(2) == 0 {
+            self.even
+        } else {
+            self.odd
+        }
+    }
+}
+
+/// Image lookup by texture coordinates with nearest neighbour filtering and wrapping.
+pub struct ImageTexture {
+    pixels: Vec<Vec3<f64>>,
+    width: u32,
+    height: u32,
+}
+
+impl ImageTexture {
+    /// Loads an sRGB colour image, decoding the sRGB transfer function into linear values and
+    /// converting them into the `working` colour space.
+    pub fn open(filename: &str, working: ColorSpace) -> ImageResult<Self> {
+        let image = image::open(filename)?.into_rgb8();
+        let (width, height) = image.dimensions();
+        let conversion = ColorSpace::Srgb.conversion(working);
+        let pixels = image
+            .pixels()
+            .map(|p| {
+                conversion
+                    * Vec3::new(
+                        srgb_to_linear(p[0] as f64 / 255.0),
+                        srgb_to_linear(p[1] as f64 / 255.0),
+                        srgb_to_linear(p[2] as f64 / 255.0),
+                    )
+            })
+            .collect();
+        Ok(Self {
+            pixels,
+            width,
+            height,
+        })
+    }
+
+    /// Loads an image holding non-colour data such as roughness or metallic maps, or an image
+    /// that is already linear, without any decoding.
+    pub fn open_linear(filename: &str) -> ImageResult<Self> {
+        let image = image::open(filename)?.into_rgb32f();
+        let (width, height) = image.dimensions();
+        let pixels = image
+            .pixels()
+            .map(|p| Vec3::new(p[0] as f64, p[1] as f64, p[2] as f64))
+            .collect();
+        Ok(Self {
+            pixels,
+            width,
+            height,
+        })
+    }
+
+    fn lookup(&self, u: f64, v: f64) -> Vec3<f64> {
+        // flip v so that v = 0 is the bottom row of the image
+        let u = u.rem_euclid(1.0);
+        let v = 1.0 - v.rem_euclid(1.0);
+        let i = ((u * self.width as f64) as u32).min(self.width - 1);
+        let j = ((v * self.height as f64) as u32).min(self.height - 1);
+        self.pixels[(j * self.width + i) as usize]
+    }
+}
+
+impl Texture<Vec3<f64>> for ImageTexture {
+    fn value(&self, u: f64, v: f64, _p: &Point3<f64>) -> Vec3<f64> {
+        self.lookup(u, v)
+    }
+}
+
+/// Scalar lookups use the first channel, which is what greyscale maps store.
+impl Texture<f64> for ImageTexture {
+    fn value(&self, u: f64, v: f64, _p: &Point3<f64>) -> f64 {
+        self.lookup(u, v).x
+    }
+}
+
+fn srgb_to_linear(c: f64) -> f64 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_constant() {
+        let p = Point3::zero();
+        assert_eq!(0.5.value(0.1, 0.2, &p), 0.5);
+        let c = Vec3::new(0.1, 0.2, 0.3);
+        assert_eq!(c.value(0.1, 0.2, &p), c);
+    }
+
+    #[test]
+    fn test_checker_alternates() {
+        let checker = Checker::new(1.0, 0.0, 1.0);
+        assert_eq!(checker.value(0.0, 0.0, &Point3::new(0.5, 0.5, 0.5)), 0.0);
+        assert_eq!(checker.value(0.0, 0.0, &Point3::new(1.5, 0.5, 0.5)), 1.0);
+        assert_eq!(checker.value(0.0, 0.0, &Point3::new(-0.5, 0.5, 0.5)), 1.0);
+    }
+
+    #[test]
+    fn test_srgb_to_linear() {
+        assert_eq!(srgb_to_linear(0.0), 0.0);
+        assert!((srgb_to_linear(1.0) - 1.0).abs() < 1e-12);
+        assert!((srgb_to_linear(0.5) - 0.214).abs() < 1e-3);
+    }
+}
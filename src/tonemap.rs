@@ -0,0 +1,338 @@
+use crate::vec3::color_space::chromatic_adaptation;
+use crate::vec3::{ColorSpace, Mat3, Vec3};
+
+/// Maps linear radiance onto the range a display shows, compressing highlights rather than
+/// clipping them, except for `Clamp`. Input and output are linear RGB, which `Aces` and `Agx`
+/// expect to have sRGB primaries, see `has_srgb_primaries`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ToneMap {
+    /// Clips each channel at one.
+    Clamp,
+    /// Reinhard et al.'s operator on luminance, extended so that `white` maps to one.
+    Reinhard { white: f64 },
+    /// John Hable's filmic curve from Uncharted 2, with its white point of 11.2.
+    Hable,
+    /// Stephen Hill's fit of the ACES reference rendering and sRGB output transforms.
+    Aces,
+    /// Troy Sobotka's AgX, which desaturates highlights towards white instead of skewing their
+    /// hue, with Benjamin Wrensch's polynomial fit of its default contrast.
+    Agx,
+}
+
+impl ToneMap {
+    /// True for the curves whose matrices are fitted to sRGB primaries, so that colours have to
+    /// be tone mapped in sRGB and converted to other primaries after.
+    pub fn has_srgb_primaries(&self) -> bool {
+        matches!(self, ToneMap::Aces | ToneMap::Agx)
+    }
+
+    /// Tone maps `color`, which has the primaries of `space` that `Reinhard` weighs luminance by.
+    pub fn apply(&self, color: Vec3<f64>, space: ColorSpace) -> Vec3<f64> {
+        let color = color_max(color, 0.0);
+        match *self {
+            ToneMap::Clamp => color_min(color, 1.0),
+            ToneMap::Reinhard { white } => {
+                let luminance = space.luminance(color);
+                if luminance <= 0.0 {
+                    return Vec3::zero();
+                }
+                let mapped = luminance * (1.0 + luminance / (white * white)) / (1.0 + luminance);
+                color_min(color * (mapped / luminance), 1.0)
+            }
+            ToneMap::Hable => {
+                // exposure bias from the original
+                let white = hable_partial(Vec3::new(11.2, 11.2, 11.2));
+                color_min(hable_partial(color * 2.0) / white, 1.0)
+            }
+            ToneMap::Aces => {
+                let input = Mat3::new([
+                    [0.59719, 0.35458, 0.04823],
+                    [0.07600, 0.90834, 0.01566],
+                    [0.02840, 0.13383, 0.83777],
+                ]);
+                let output = Mat3::new([
+                    [1.60475, -0.53108, -0.07367],
+                    [-0.10208, 1.10813, -0.00605],
+                    [-0.00327, -0.07276, 1.07602],
+                ]);
+                let v = input * color;
+                let fit = map(v, |v| {
+                    (v * (v + 0.0245786) - 0.000090537)
+                        / (v * (0.983729 * v + 0.4329510) + 0.238081)
+                });
+                color_max(color_min(output * fit, 1.0), 0.0)
+            }
+            ToneMap::Agx => {
+                let inset = Mat3::new([
+                    [0.842479062253094, 0.0784335999999992, 0.0792237451477643],
+                    [0.0423282422610123, 0.878468636469772, 0.0791661274605434],
+                    [0.0423756549057051, 0.0784336, 0.879142973793104],
+                ]);
+                let outset = Mat3::new([
+                    [1.19687900512017, -0.0980208811401368, -0.0990297440797205],
+                    [-0.0528968517574562, 1.15190312990417, -0.0989611768448433],
+                    [-0.0529716355144438, -0.0980434501171241, 1.15107367264116],
+                ]);
+                // log encoding over the stops the curve covers
+                let (min_ev, max_ev) = (-12.47393, 4.026069);
+                let encoded = map(inset * color, |v| {
+                    (v.max(1e-10).log2().clamp(min_ev, max_ev) - min_ev) / (max_ev - min_ev)
+                });
+                let curve = map(encoded, |x| {
+                    let x2 = x * x;
+                    let x4 = x2 * x2;
+                    15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x
+                        + 0.4298 * x2
+                        + 0.1191 * x
+                        - 0.00232
+                });
+                // the curve gives display values for a 2.2 gamma display
+                map(outset * curve, |v| v.clamp(0.0, 1.0).powf(2.2))
+            }
+        }
+    }
+}
+
+fn map(v: Vec3<f64>, f: impl Fn(f64) -> f64) -> Vec3<f64> {
+    Vec3::new(f(v.x), f(v.y), f(v.z))
+}
+
+fn color_min(v: Vec3<f64>, max: f64) -> Vec3<f64> {
+    map(v, |v| v.min(max))
+}
+
+fn color_max(v: Vec3<f64>, min: f64) -> Vec3<f64> {
+    map(v, |v| v.max(min))
+}
+
+fn hable_partial(x: Vec3<f64>) -> Vec3<f64> {
+    let (a, b, c, d, e, f) = (0.15, 0.50, 0.10, 0.20, 0.02, 0.30);
+    map(x, |x| {
+        (x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f) - e / f
+    })
+}
+
+/// The sRGB opto-electronic transfer function, from linear light to the encoded values image
+/// files store.
+pub fn srgb_oetf(linear: f64) -> f64 {
+    if linear <= 0.0031308 {
+        12.92 * linear
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Chromaticity of a black body at `kelvin`, from 1667K to 25000K (Kim et al.'s fit of the
+/// Planckian locus).
+fn planckian_xy(kelvin: f64) -> (f64, f64) {
+    let t = kelvin.clamp(1667.0, 25000.0);
+    let (t2, t3) = (t * t, t * t * t);
+    let x = if t <= 4000.0 {
+        -0.2661239e9 / t3 - 0.2343589e6 / t2 + 0.8776956e3 / t + 0.179910
+    } else {
+        -3.0258469e9 / t3 + 2.1070379e6 / t2 + 0.2226347e3 / t + 0.240390
+    };
+    let (x2, x3) = (x * x, x * x * x);
+    let y = if t <= 2222.0 {
+        -1.1063814 * x3 - 1.34811020 * x2 + 2.18555832 * x - 0.20219683
+    } else if t <= 4000.0 {
+        -0.9549476 * x3 - 1.37418593 * x2 + 2.09137015 * x - 0.16748867
+    } else {
+        3.0817580 * x3 - 5.87338670 * x2 + 3.75112997 * x - 0.37001483
+    };
+    (x, y)
+}
+
+/// Linear map in the `working` colour space making light of a black body at `kelvin` come out
+/// white, by adaptation from its white point to the space's.
+fn white_balance(kelvin: f64, working: ColorSpace) -> Mat3 {
+    let adaptation = chromatic_adaptation(planckian_xy(kelvin), working.white());
+    working.from_xyz() * adaptation * working.to_xyz()
+}
+
+/// What image files are encoded in.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum OutputSpace {
+    Srgb,
+    /// Display P3, which shares sRGB's transfer function.
+    DisplayP3,
+    /// Linear values in the working colour space, for compositing.
+    Linear,
+}
+
+/// Turns the linear radiance of a render into the values written to an image file.
+#[derive(Debug, Copy, Clone)]
+pub struct OutputTransform {
+    /// Exposure in stops, each doubling the brightness.
+    pub exposure: f64,
+    /// Colour temperature in kelvin of the light that should come out white, if any.
+    pub white_balance: Option<f64>,
+    pub tone_map: ToneMap,
+    /// The colour space radiance was rendered in.
+    pub working: ColorSpace,
+    pub output: OutputSpace,
+}
+
+impl OutputTransform {
+    /// The encoded value of `radiance` in the output space, each channel from 0 to 1.
+    pub fn apply(&self, radiance: Vec3<f64>) -> Vec3<f64> {
+        let mut color = radiance * self.exposure.exp2();
+        if let Some(kelvin) = self.white_balance {
+            color = white_balance(kelvin, self.working) * color;
+        }
+        let primaries = match self.output {
+            OutputSpace::Srgb => ColorSpace::Srgb,
+            OutputSpace::DisplayP3 => ColorSpace::DisplayP3,
+            OutputSpace::Linear => self.working,
+        };
+        // tone mapped in the output's primaries, so that highlights roll off within its gamut,
+        // unless the curve only works in sRGB
+        let curve_primaries = if self.tone_map.has_srgb_primaries() {
+            ColorSpace::Srgb
+        } else {
+            primaries
+        };
+        if curve_primaries != self.working {
+            color = self.working.conversion(curve_primaries) * color;
+        }
+        color = self.tone_map.apply(color, curve_primaries);
+        if curve_primaries != primaries {
+            color = curve_primaries.conversion(primaries) * color;
+        }
+        match self.output {
+            OutputSpace::Linear => map(color, |v| v.clamp(0.0, 1.0)),
+            _ => map(color, |v| srgb_oetf(v).clamp(0.0, 1.0)),
+        }
+    }
+}
+
+impl Default for OutputTransform {
+    fn default() -> Self {
+        Self {
+            exposure: 0.0,
+            white_balance: None,
+            tone_map: ToneMap::Clamp,
+            working: ColorSpace::Srgb,
+            output: OutputSpace::Srgb,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vec3::color_space::xyz;
+
+    #[test]
+    fn test_srgb_oetf_is_continuous() {
+        let knee = 0.0031308;
+        // the standard's rounded constants leave a tiny step at the knee
+        assert!((srgb_oetf(knee) - srgb_oetf(knee + 1e-12)).abs() < 1e-6);
+        assert_eq!(srgb_oetf(0.0), 0.0);
+        assert!((srgb_oetf(1.0) - 1.0).abs() < 1e-12);
+        assert!((srgb_oetf(0.18) - 0.4613561).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_tone_maps_compress_highlights() {
+        let operators = [
+            ToneMap::Reinhard { white: 16.0 },
+            ToneMap::Hable,
+            ToneMap::Aces,
+            ToneMap::Agx,
+        ];
+        for tone_map in operators {
+            let mut previous = 0.0;
+            for i in 1..=100 {
+                let value = tone_map
+                    .apply(
+                        Vec3::new(1.0, 1.0, 1.0) * (i as f64 * 0.2),
+                        ColorSpace::Srgb,
+                    )
+                    .y;
+                assert!(value >= previous && value <= 1.0, "{:?}", tone_map);
+                previous = value;
+            }
+            // bright light is told apart rather than clipped
+            let bright = tone_map.apply(Vec3::new(2.0, 2.0, 2.0), ColorSpace::Srgb).y;
+            let brighter = tone_map.apply(Vec3::new(4.0, 4.0, 4.0), ColorSpace::Srgb).y;
+            assert!(brighter > bright, "{:?}", tone_map);
+        }
+        let white =
+            ToneMap::Reinhard { white: 4.0 }.apply(Vec3::new(4.0, 4.0, 4.0), ColorSpace::Srgb);
+        assert!((white.y - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_reinhard_weighs_luminance_by_space() {
+        let green = Vec3::new(0.0, 1.0, 0.0);
+        for space in [ColorSpace::Srgb, ColorSpace::AcesCg, ColorSpace::Rec2020] {
+            let luminance = (space.to_xyz() * green).y;
+            let mapped = ToneMap::Reinhard { white: 1e9 }.apply(green, space);
+            // scaled so that its luminance maps to L / (1 + L)
+            assert!(
+                (mapped.y - 1.0 / (1.0 + luminance)).abs() < 1e-9,
+                "{:?}",
+                space
+            );
+        }
+    }
+
+    #[test]
+    fn test_white_balance_neutralises_light() {
+        // a surface lit by a warm light comes out grey when balanced for that light
+        let kelvin = 3200.0;
+        for working in [ColorSpace::Srgb, ColorSpace::AcesCg] {
+            let transform = white_balance(kelvin, working);
+            let light = working.from_xyz() * xyz(planckian_xy(kelvin));
+            assert!(light.x > light.z);
+            let balanced = transform * light;
+            assert!((balanced.x - balanced.y).abs() < 1e-3 * balanced.y);
+            assert!((balanced.z - balanced.y).abs() < 1e-3 * balanced.y);
+        }
+    }
+
+    #[test]
+    fn test_default_writes_mid_grey() {
+        let encoded = OutputTransform::default().apply(Vec3::new(0.18, 0.18, 0.18));
+        assert!((encoded.x - 0.4613561).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_output_spaces() {
+        // pure sRGB red is inside P3, so less saturated there, and written as is when linear
+        let red = Vec3::new(0.5, 0.0, 0.0);
+        let p3 = OutputTransform {
+            output: OutputSpace::DisplayP3,
+            ..Default::default()
+        }
+        .apply(red);
+        assert!(p3.x < srgb_oetf(0.5) && p3.y > 0.0);
+        let linear = OutputTransform {
+            working: ColorSpace::AcesCg,
+            output: OutputSpace::Linear,
+            ..Default::default()
+        }
+        .apply(red);
+        assert_eq!(linear, red);
+    }
+
+    #[test]
+    fn test_srgb_curves_convert_after_tone_mapping() {
+        let color = Vec3::new(2.0, 0.3, 0.1);
+        for tone_map in [ToneMap::Aces, ToneMap::Agx] {
+            let p3 = OutputTransform {
+                tone_map,
+                output: OutputSpace::DisplayP3,
+                ..Default::default()
+            }
+            .apply(color);
+            let expected = map(
+                ColorSpace::Srgb.conversion(ColorSpace::DisplayP3)
+                    * tone_map.apply(color, ColorSpace::Srgb),
+                |v| srgb_oetf(v).clamp(0.0, 1.0),
+            );
+            assert!((p3 - expected).length() < 1e-12, "{:?}", tone_map);
+        }
+    }
+}
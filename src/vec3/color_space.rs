@@ -0,0 +1,149 @@
+use crate::vec3::{Mat3, Vec3};
+use std::sync::OnceLock;
+
+/// D65 white point chromaticity, used by sRGB, Rec.2020 and Display P3.
+pub const D65: (f64, f64) = (0.3127, 0.3290);
+/// ACES white point chromaticity, close to D60.
+pub const D60: (f64, f64) = (0.32168, 0.33767);
+
+/// An RGB colour space with linear encoding, defined by the chromaticities of its primaries and
+/// white point.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ColorSpace {
+    /// sRGB and Rec.709, which share primaries.
+    Srgb,
+    /// ACES AP1 primaries, what compositors usually work in.
+    AcesCg,
+    Rec2020,
+    /// P3 primaries with a D65 white point, as on Apple displays.
+    DisplayP3,
+}
+
+impl ColorSpace {
+    /// Red, green and blue primaries, then the white point, as CIE xy chromaticities.
+    pub fn chromaticities(&self) -> [(f64, f64); 4] {
+        match self {
+            ColorSpace::Srgb => [(0.64, 0.33), (0.30, 0.60), (0.15, 0.06), D65],
+            ColorSpace::AcesCg => [(0.713, 0.293), (0.165, 0.830), (0.128, 0.044), D60],
+            ColorSpace::Rec2020 => [(0.708, 0.292), (0.170, 0.797), (0.131, 0.046), D65],
+            ColorSpace::DisplayP3 => [(0.680, 0.320), (0.265, 0.690), (0.150, 0.060), D65],
+        }
+    }
+
+    pub fn white(&self) -> (f64, f64) {
+        self.chromaticities()[3]
+    }
+
+    /// Linear map from this space to CIE XYZ, scaling the primaries so that RGB of one is the
+    /// white point with a luminance of one.
+    pub fn to_xyz(&self) -> Mat3 {
+        let [r, g, b, white] = self.chromaticities();
+        let primaries = Mat3::new([
+            [xyz(r).x, xyz(g).x, xyz(b).x],
+            [xyz(r).y, xyz(g).y, xyz(b).y],
+            [xyz(r).z, xyz(g).z, xyz(b).z],
+        ]);
+        let scale = primaries.inverse() * xyz(white);
+        primaries * Mat3::diagonal(scale)
+    }
+
+    pub fn from_xyz(&self) -> Mat3 {
+        self.to_xyz().inverse()
+    }
+
+    /// Linear map from this space into `other`, adapting between their white points so that
+    /// white stays white.
+    pub fn conversion(&self, other: ColorSpace) -> Mat3 {
+        if *self == other {
+            return Mat3::identity();
+        }
+        other.from_xyz() * chromatic_adaptation(self.white(), other.white()) * self.to_xyz()
+    }
+
+    /// Relative luminance of a colour in this space.
+    pub fn luminance(&self, color: Vec3<f64>) -> f64 {
+        // the Y row of `to_xyz`, worked out once per space as it's needed per sample
+        static WEIGHTS: [OnceLock<[f64; 3]>; 4] = [const { OnceLock::new() }; 4];
+        let y = WEIGHTS[*self as usize].get_or_init(|| self.to_xyz().rows[1]);
+        y[0] * color.x + y[1] * color.y + y[2] * color.z
+    }
+}
+
+/// XYZ of a chromaticity with a luminance of one.
+pub fn xyz((x, y): (f64, f64)) -> Vec3<f64> {
+    Vec3::new(x / y, 1.0, (1.0 - x - y) / y)
+}
+
+/// Bradford chromatic adaptation, mapping XYZ colours seen under the white point `source` to
+/// how they'd look under `destination`.
+pub fn chromatic_adaptation(source: (f64, f64), destination: (f64, f64)) -> Mat3 {
+    if source == destination {
+        return Mat3::identity();
+    }
+    let bradford = Mat3::new([
+        [0.8951, 0.2664, -0.1614],
+        [-0.7502, 1.7135, 0.0367],
+        [0.0389, -0.0685, 1.0296],
+    ]);
+    let scale = (bradford * xyz(destination)) / (bradford * xyz(source));
+    bradford.inverse() * Mat3::diagonal(scale) * bradford
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: Mat3, b: Mat3, tolerance: f64) {
+        for (row_a, row_b) in a.rows.iter().zip(b.rows.iter()) {
+            for (a, b) in row_a.iter().zip(row_b.iter()) {
+                assert!((a - b).abs() < tolerance, "{:?} != {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn test_srgb_to_xyz() {
+        let expected = Mat3::new([
+            [0.4124564, 0.3575761, 0.1804375],
+            [0.2126729, 0.7151522, 0.0721750],
+            [0.0193339, 0.1191920, 0.9503041],
+        ]);
+        // the standard matrix rounds the white point a little differently
+        assert_close(ColorSpace::Srgb.to_xyz(), expected, 1e-3);
+    }
+
+    #[test]
+    fn test_acescg_to_srgb() {
+        // as given by OpenColorIO's ACES config
+        let expected = Mat3::new([
+            [1.70505, -0.62179, -0.08326],
+            [-0.13026, 1.14080, -0.01055],
+            [-0.02400, -0.12897, 1.15297],
+        ]);
+        assert_close(
+            ColorSpace::AcesCg.conversion(ColorSpace::Srgb),
+            expected,
+            1e-3,
+        );
+    }
+
+    #[test]
+    fn test_conversions_keep_white() {
+        let spaces = [
+            ColorSpace::Srgb,
+            ColorSpace::AcesCg,
+            ColorSpace::Rec2020,
+            ColorSpace::DisplayP3,
+        ];
+        for from in spaces {
+            for to in spaces {
+                let white = from.conversion(to) * Vec3::new(1.0, 1.0, 1.0);
+                assert!((white - Vec3::new(1.0, 1.0, 1.0)).length() < 1e-9);
+                let round_trip =
+                    to.conversion(from) * (from.conversion(to) * Vec3::new(0.2, 0.5, 0.9));
+                assert!((round_trip - Vec3::new(0.2, 0.5, 0.9)).length() < 1e-9);
+            }
+            assert!((from.luminance(Vec3::new(1.0, 1.0, 1.0)) - 1.0).abs() < 1e-9);
+        }
+    }
+}
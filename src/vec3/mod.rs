@@ -1,4 +1,11 @@
+pub mod color_space;
+pub mod mat3;
+pub mod onb;
 pub mod utils;
+#[allow(clippy::module_inception)]
 pub mod vec3;
 
+pub use color_space::ColorSpace;
+pub use mat3::Mat3;
+pub use onb::Onb;
 pub use vec3::{Color, Point3, Vec3};
@@ -0,0 +1,54 @@
+use crate::vec3::Vec3;
+
+/// Orthonormal basis with `w` aligned to a surface normal, used to move directions in and out of
+/// a local shading frame where the normal is +z.
+#[derive(Debug, Copy, Clone)]
+pub struct Onb {
+    pub u: Vec3<f64>,
+    pub v: Vec3<f64>,
+    pub w: Vec3<f64>,
+}
+
+impl Onb {
+    pub fn new(n: &Vec3<f64>) -> Self {
+        // Duff et al. 2017, "Building an Orthonormal Basis, Revisited"
+        let w = n.normalize();
+        let sign = 1.0_f64.copysign(w.z);
+        let a = -1.0 / (sign + w.z);
+        let b = w.x * w.y * a;
+        let u = Vec3::new(1.0 + sign * w.x * w.x * a, sign * b, -sign * w.x);
+        let v = Vec3::new(b, sign + w.y * w.y * a, -w.y);
+        Self { u, v, w }
+    }
+
+    pub fn to_local(&self, a: &Vec3<f64>) -> Vec3<f64> {
+        Vec3::new(a.dot(&self.u), a.dot(&self.v), a.dot(&self.w))
+    }
+
+    pub fn to_world(&self, a: &Vec3<f64>) -> Vec3<f64> {
+        self.u * a.x + self.v * a.y + self.w * a.z
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_basis_is_orthonormal() {
+        let onb = Onb::new(&Vec3::new(0.3, -0.8, 0.2));
+        assert!((onb.u.length() - 1.0).abs() < 1e-12);
+        assert!((onb.v.length() - 1.0).abs() < 1e-12);
+        assert!(onb.u.dot(&onb.v).abs() < 1e-12);
+        assert!(onb.u.dot(&onb.w).abs() < 1e-12);
+        assert!(onb.v.dot(&onb.w).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let onb = Onb::new(&Vec3::new(0.0, 0.0, -1.0));
+        let a = Vec3::new(0.1, 0.2, 0.3);
+        let b = onb.to_world(&onb.to_local(&a));
+        assert!((a - b).length() < 1e-12);
+    }
+}
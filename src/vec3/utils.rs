@@ -1,35 +1,33 @@
+use crate::sampler::Sampler;
 use crate::vec3::{Point3, Vec3};
-use rand::Rng;
 use std::f64::consts::PI;
 
-pub fn random_in_unit_sphere() -> Point3<f64> {
-    let mut rng = rand::thread_rng();
-    let u: f64 = rng.gen();
+pub fn random_in_unit_sphere(sampler: &mut dyn Sampler) -> Point3<f64> {
+    let u = sampler.get_1d();
     let p = Point3::new(
-        rng.gen_range(-1.0..1.0),
-        rng.gen_range(-1.0..1.0),
-        rng.gen_range(-1.0..1.0),
+        sampler.get_1d() * 2.0 - 1.0,
+        sampler.get_1d() * 2.0 - 1.0,
+        sampler.get_1d() * 2.0 - 1.0,
     );
     p.normalize() * u.cbrt()
 }
 
-pub fn random_unit_vector() -> Vec3<f64> {
-    random_in_unit_sphere().normalize()
+pub fn random_unit_vector(sampler: &mut dyn Sampler) -> Vec3<f64> {
+    random_in_unit_sphere(sampler).normalize()
 }
 
-pub fn random_in_hemisphere(normal: &Vec3<f64>) -> Vec3<f64> {
-    let in_unit_sphere: Vec3<f64> = random_in_unit_sphere();
-    if in_unit_sphere.dot(&normal) > 0.0 {
+pub fn random_in_hemisphere(normal: &Vec3<f64>, sampler: &mut dyn Sampler) -> Vec3<f64> {
+    let in_unit_sphere: Vec3<f64> = random_in_unit_sphere(sampler);
+    if in_unit_sphere.dot(normal) > 0.0 {
         in_unit_sphere
     } else {
         -in_unit_sphere
     }
 }
 
-pub fn random_in_unit_disk() -> Point3<f64> {
-    let mut rng = rand::thread_rng();
-    let r = rng.gen_range(0.0..1.0);
-    let theta = rng.gen_range(0.0..2.0 * PI);
+pub fn random_in_unit_disk(sampler: &mut dyn Sampler) -> Point3<f64> {
+    let r = sampler.get_1d();
+    let theta = sampler.get_1d() * 2.0 * PI;
     Point3 {
         x: r * theta.cos(),
         y: r * theta.sin(),
@@ -37,13 +35,37 @@ pub fn random_in_unit_disk() -> Point3<f64> {
     }
 }
 
+/// Uniformly distributed direction on the unit sphere from two uniform numbers in [0, 1).
+pub fn sample_uniform_sphere(u: (f64, f64)) -> Vec3<f64> {
+    let z = 1.0 - 2.0 * u.0;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * u.1;
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+/// Uniformly distributed point on the unit disk in the xy plane from two uniform numbers in
+/// [0, 1).
+pub fn sample_uniform_disk(u: (f64, f64)) -> Point3<f64> {
+    let r = u.0.sqrt();
+    let theta = 2.0 * PI * u.1;
+    Point3::new(r * theta.cos(), r * theta.sin(), 0.0)
+}
+
+/// Cosine weighted direction about +z from two uniform numbers in [0, 1).
+pub fn sample_cosine_hemisphere(u: (f64, f64)) -> Vec3<f64> {
+    let r = u.0.sqrt();
+    let phi = 2.0 * PI * u.1;
+    Vec3::new(r * phi.cos(), r * phi.sin(), (1.0 - u.0).max(0.0).sqrt())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::sampler::IndependentSampler;
 
     #[test]
     fn test_random_in_unit_sphere_in_expected_range() {
-        let p = random_in_unit_sphere();
+        let p = random_in_unit_sphere(&mut IndependentSampler::new(0));
         let range = -1.0..1.0;
         assert!(range.contains(&p.x));
         assert!(range.contains(&p.y));
@@ -52,27 +74,27 @@ mod tests {
 
     #[test]
     fn test_random_unit_vector_is_unit_vector() {
-        let v = random_unit_vector();
+        let v = random_unit_vector(&mut IndependentSampler::new(0));
         assert_eq!(v.length(), 1.0);
     }
 
     #[test]
     fn test_random_in_hemisphere_with_positive_dot_product() {
         let normal = Vec3::new(0.0, 1.0, 0.0);
-        let v = random_in_hemisphere(&normal);
+        let v = random_in_hemisphere(&normal, &mut IndependentSampler::new(0));
         assert!(v.y > 0.0);
     }
 
     #[test]
     fn test_random_in_hemisphere_with_negative_dot_product() {
         let normal = Vec3::new(0.0, -1.0, 0.0);
-        let v = random_in_hemisphere(&normal);
+        let v = random_in_hemisphere(&normal, &mut IndependentSampler::new(0));
         assert!(v.y < 0.0);
     }
 
     #[test]
     fn test_random_in_unit_disk() {
-        let point = random_in_unit_disk();
+        let point = random_in_unit_disk(&mut IndependentSampler::new(0));
 
         // Ensure the point is within the unit disk
         assert!(point.length() < 1.0);
@@ -80,4 +102,18 @@ mod tests {
         // Ensure the point is on the xy plane
         assert_eq!(point.z, 0.0);
     }
+
+    #[test]
+    fn test_sample_uniform_sphere() {
+        let v = sample_uniform_sphere((0.9, 0.2));
+        assert!((v.length() - 1.0).abs() < 1e-12);
+        assert!(v.z < 0.0);
+    }
+
+    #[test]
+    fn test_sample_cosine_hemisphere() {
+        let v = sample_cosine_hemisphere((0.3, 0.7));
+        assert!((v.length() - 1.0).abs() < 1e-12);
+        assert!(v.z > 0.0);
+    }
 }
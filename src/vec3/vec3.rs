@@ -67,6 +67,10 @@ impl<N: Float> Vec3<N> {
         *self
     }
 
+    pub fn max_component(&self) -> N {
+        self.x.max(self.y).max(self.z)
+    }
+
     pub fn powf(&mut self, n: N) -> Self {
         self.x = self.x.powf(n);
         self.y = self.y.powf(n);
@@ -327,6 +331,12 @@ mod tests {
         assert_eq!(vec1.cross(&vec2), res);
     }
 
+    #[test]
+    fn test_max_component() {
+        let vec: Vec3<f64> = Vec3::new(0.2, 0.7, 0.1);
+        assert_eq!(vec.max_component(), 0.7);
+    }
+
     #[test]
     fn test_neg() {
         let vec = Vec3::new(1, 3, 4);
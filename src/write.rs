@@ -1,6 +1,16 @@
-use crate::vec3::Color;
-use image::{ImageBuffer, Rgb, RgbImage};
+use crate::film::AovImage;
+use crate::vec3::{Color, ColorSpace, Vec3};
+use exr::meta::attribute::Chromaticities;
+use exr::prelude::{
+    AnyChannel, AnyChannels, Encoding, FlatSamples, Image, Layer, LayerAttributes, Vec2,
+    WritableImage,
+};
+use image::codecs::hdr::HdrEncoder;
+use image::{ImageBuffer, ImageResult, Rgb, Rgb32FImage, RgbImage};
 use indicatif::{ProgressBar, ProgressStyle};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
 
 pub fn write_image(colors: Vec<Vec<Color>>, filename: &str) {
     let width = colors.len() as u32;
@@ -26,8 +36,226 @@ pub fn write_image(colors: Vec<Vec<Color>>, filename: &str) {
 
     bar.finish();
 
-    match buffer.save(filename) {
-        Err(e) => eprintln!("Error writing to file {}", e),
-        Ok(()) => (),
+    if let Err(e) = buffer.save(filename) {
+        eprintln!("Error writing to file {}", e)
+    }
+}
+
+/// File formats that keep linear radiance as floats, by extension.
+const HDR_EXTENSIONS: [&str; 3] = ["exr", "hdr", "pfm"];
+
+/// True when `filename` is written as high dynamic range floats rather than 8 bit colour.
+pub fn is_hdr_image(filename: &str) -> bool {
+    Path::new(filename)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| HDR_EXTENSIONS.contains(&extension.to_lowercase().as_str()))
+}
+
+/// Writes linear radiance in the colour space `space`, by column from the top left, as 32 bit
+/// floats without any tone mapping: OpenEXR, Radiance HDR or PFM depending on the extension of
+/// `filename`. OpenEXR files record the primaries of `space`. Radiance HDR and PFM have no way
+/// to, so they are converted to sRGB's, outside of which colours go negative (clipped to zero
+/// by Radiance HDR).
+pub fn write_hdr_image(pixels: &[Vec<Vec3<f64>>], space: ColorSpace, filename: &str) {
+    let extension = Path::new(filename)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_lowercase());
+    if extension.as_deref() == Some("exr") {
+        return write_exr_layers(pixels, &[], space, filename);
+    }
+    let width = pixels.len() as u32;
+    let height = pixels[0].len() as u32;
+    let to_srgb = space.conversion(ColorSpace::Srgb);
+    let buffer: Rgb32FImage = ImageBuffer::from_fn(width, height, |x, y| {
+        let pixel = to_srgb * pixels[x as usize][y as usize];
+        Rgb([pixel.x as f32, pixel.y as f32, pixel.z as f32])
+    });
+    let result = match extension.as_deref() {
+        Some("hdr") => write_radiance(&buffer, filename),
+        _ => write_pfm(&buffer, filename),
+    };
+    if let Err(e) = result {
+        eprintln!("Error writing to file {}", e)
+    }
+}
+
+/// Writes linear radiance like `write_hdr_image` as the RGB channels of an OpenEXR file, with
+/// each of `aovs` as a layer of channels named `<layer>.<channel>` alongside, and the primaries
+/// and white point of `space` as the file's chromaticities.
+pub fn write_exr_layers(
+    pixels: &[Vec<Vec3<f64>>],
+    aovs: &[AovImage],
+    space: ColorSpace,
+    filename: &str,
+) {
+    let width = pixels.len();
+    let height = pixels[0].len();
+    // exr stores rows from the top
+    let channel = |name: String, pixels: &[Vec<Vec3<f64>>], component: usize| {
+        let samples = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let pixel = pixels[x][y];
+                [pixel.x, pixel.y, pixel.z][component] as f32
+            })
+            .collect();
+        AnyChannel::new(name.as_str(), FlatSamples::F32(samples))
+    };
+    let mut channels: Vec<_> = ["R", "G", "B"]
+        .iter()
+        .enumerate()
+        .map(|(component, name)| channel(name.to_string(), pixels, component))
+        .collect();
+    for aov in aovs {
+        for (component, name) in aov.layer.aov.channels().iter().enumerate() {
+            let name = format!("{}.{}", aov.layer.name, name);
+            channels.push(channel(name, &aov.pixels, component));
+        }
+    }
+    let layer = Layer::new(
+        (width, height),
+        LayerAttributes::default(),
+        Encoding::FAST_LOSSLESS,
+        AnyChannels::sort(channels.into()),
+    );
+    let mut image = Image::from_layer(layer);
+    let [red, green, blue, white] = space
+        .chromaticities()
+        .map(|(x, y)| Vec2(x as f32, y as f32));
+    image.attributes.chromaticities = Some(Chromaticities {
+        red,
+        green,
+        blue,
+        white,
+    });
+    if let Err(e) = image.write().to_file(filename) {
+        eprintln!("Error writing to file {}", e)
+    }
+}
+
+fn write_radiance(buffer: &Rgb32FImage, filename: &str) -> ImageResult<()> {
+    let file = BufWriter::new(File::create(filename)?);
+    let pixels: Vec<Rgb<f32>> = buffer.pixels().copied().collect();
+    HdrEncoder::new(file).encode(&pixels, buffer.width() as usize, buffer.height() as usize)
+}
+
+/// Portable float map: a text header, then little endian floats by row from the bottom.
+fn write_pfm(buffer: &Rgb32FImage, filename: &str) -> ImageResult<()> {
+    let mut file = BufWriter::new(File::create(filename)?);
+    // a negative scale marks little endian
+    write!(file, "PF\n{} {}\n-1.0\n", buffer.width(), buffer.height())?;
+    for y in (0..buffer.height()).rev() {
+        for x in 0..buffer.width() {
+            for channel in buffer.get_pixel(x, y).0 {
+                file.write_all(&channel.to_le_bytes())?;
+            }
+        }
+    }
+    file.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::film::{Aov, AovLayer};
+
+    fn pixels() -> Vec<Vec<Vec3<f64>>> {
+        // a bright top left pixel, beyond what 8 bits hold
+        vec![
+            vec![Vec3::new(12.5, 0.25, 0.0), Vec3::zero()],
+            vec![Vec3::zero(), Vec3::new(0.5, 1.0, 2.0)],
+        ]
+    }
+
+    #[test]
+    fn test_hdr_formats_by_extension() {
+        assert!(is_hdr_image("render.exr"));
+        assert!(is_hdr_image("out/render.HDR"));
+        assert!(is_hdr_image("render.pfm"));
+        assert!(!is_hdr_image("render.png"));
+        assert!(!is_hdr_image("exr"));
+    }
+
+    #[test]
+    fn test_exr_keeps_values() {
+        let path = std::env::temp_dir().join("raytracer_test_write.exr");
+        let filename = path.to_str().unwrap();
+        write_hdr_image(&pixels(), ColorSpace::Srgb, filename);
+        let image = image::open(filename).unwrap().into_rgb32f();
+        std::fs::remove_file(filename).unwrap();
+        assert_eq!(image.get_pixel(0, 0).0, [12.5, 0.25, 0.0]);
+        assert_eq!(image.get_pixel(1, 1).0, [0.5, 1.0, 2.0]);
+    }
+
+    #[test]
+    fn test_exr_layers_are_named() {
+        let path = std::env::temp_dir().join("raytracer_test_write_layers.exr");
+        let filename = path.to_str().unwrap();
+        let depth = AovImage {
+            layer: AovLayer {
+                aov: Aov::Depth,
+                name: "depth".to_string(),
+            },
+            pixels: vec![vec![Vec3::new(1.0, 0.0, 0.0), Vec3::new(2.0, 0.0, 0.0)]; 2],
+        };
+        write_exr_layers(&pixels(), &[depth], ColorSpace::AcesCg, filename);
+        let image = exr::prelude::read_all_flat_layers_from_file(filename).unwrap();
+        std::fs::remove_file(filename).unwrap();
+        // values stay in the working space, which readers are told of
+        let chromaticities = image.attributes.chromaticities.unwrap();
+        assert_eq!(chromaticities.red, Vec2(0.713, 0.293));
+        assert_eq!(chromaticities.white, Vec2(0.32168, 0.33767));
+        let channels = &image.layer_data[0].channel_data.list;
+        let names: Vec<String> = channels.iter().map(|c| c.name.to_string()).collect();
+        assert_eq!(names, ["B", "G", "R", "depth.Z"]);
+        // the top left pixel, then the one right of it
+        let FlatSamples::F32(red) = &channels[2].sample_data else {
+            panic!("red isn't f32");
+        };
+        assert_eq!(&red[..2], &[12.5, 0.0]);
+        let FlatSamples::F32(depth) = &channels[3].sample_data else {
+            panic!("depth isn't f32");
+        };
+        assert_eq!(depth, &[1.0, 1.0, 2.0, 2.0]);
+    }
+
+    #[test]
+    fn test_pfm_rows_from_bottom() {
+        let path = std::env::temp_dir().join("raytracer_test_write.pfm");
+        let filename = path.to_str().unwrap();
+        write_hdr_image(&pixels(), ColorSpace::Srgb, filename);
+        let bytes = std::fs::read(filename).unwrap();
+        std::fs::remove_file(filename).unwrap();
+        let header = b"PF\n2 2\n-1.0\n";
+        assert_eq!(&bytes[..header.len()], header);
+        let floats: Vec<f32> = bytes[header.len()..]
+            .chunks(4)
+            .map(|chunk| f32::from_le_bytes(chunk.try_into().unwrap()))
+            .collect();
+        // the bottom row first, so the top left pixel starts the second row
+        assert_eq!(floats.len(), 12);
+        assert_eq!(&floats[3..6], &[0.5, 1.0, 2.0]);
+        assert_eq!(&floats[6..9], &[12.5, 0.25, 0.0]);
+    }
+
+    #[test]
+    fn test_pfm_converts_to_srgb() {
+        let path = std::env::temp_dir().join("raytracer_test_write_acescg.pfm");
+        let filename = path.to_str().unwrap();
+        let green = Vec3::new(0.0, 1.0, 0.0);
+        write_hdr_image(&[vec![green]], ColorSpace::AcesCg, filename);
+        let bytes = std::fs::read(filename).unwrap();
+        std::fs::remove_file(filename).unwrap();
+        let floats: Vec<f32> = bytes[bytes.len() - 12..]
+            .chunks(4)
+            .map(|chunk| f32::from_le_bytes(chunk.try_into().unwrap()))
+            .collect();
+        let expected = ColorSpace::AcesCg.conversion(ColorSpace::Srgb) * green;
+        // ACEScg green is beyond sRGB's
+        assert!(floats[0] < 0.0);
+        assert!((floats[1] - expected.y as f32).abs() < 1e-6);
     }
 }